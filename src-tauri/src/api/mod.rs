@@ -1,11 +1,14 @@
 use async_trait::async_trait;
 
 use crate::{
-    models::{model::Model, parameters::EngineParameters},
+    attachment::media::ImageData,
+    models::{constraints::Constraints, model::Model, parameters::EngineParameters},
     prelude::*,
 };
 
-mod abstractions;
+pub mod abstractions;
+pub mod ollama;
+pub mod openai;
 pub mod ullm;
 
 #[async_trait]
@@ -25,10 +28,16 @@ pub trait Api: Send + Sync {
     async fn status(&mut self) -> Result<Option<Model>>;
     async fn list(&mut self) -> Result<Vec<Model>>;
 
+    // `images` are referenced from the snippet by `media::image_marker`.
     async fn complete(
         &mut self,
         snippet: &str,
+        images: &[ImageData],
         engine_parameters: EngineParameters,
+        constraints: Constraints,
         streaming_callback: Box<dyn Fn(String) -> Result<()> + Send + Sync>,
     ) -> Result<String>;
+
+    // One embedding per text, in the same order.
+    async fn embed(&mut self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
 }
@@ -0,0 +1,246 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use models::{
+    EmbedRequest, EmbedResult, GenerateChunk, GenerateOptions, GenerateRequest, TagsResult,
+};
+use reqwest::Client;
+use tokio::sync::Mutex;
+
+use crate::{
+    attachment::media::ImageData,
+    models::{
+        constraints::Constraints,
+        model::{Engine, Model},
+        parameters::EngineParameters,
+    },
+    prelude::*,
+};
+
+use super::Api;
+
+mod models;
+
+// Ollama's native API, which unlike its OpenAI compatible one takes images along with a raw
+// prompt. Models are loaded on demand, so `load` only selects one and warms it up.
+pub struct OllamaApi {
+    client: Client,
+    url: String,
+    // Used for `/api/embed` instead of the selected model, which often can't embed.
+    embedding_model: Option<String>,
+    model: Option<Model>,
+}
+
+impl OllamaApi {
+    pub fn new(url: String, embedding_model: Option<String>) -> Result<Arc<Mutex<Self>>> {
+        Ok(Arc::new(Mutex::new(Self {
+            client: Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            embedding_model,
+            model: None,
+        })))
+    }
+
+    fn model_name(&self) -> Result<String> {
+        self.model
+            .as_ref()
+            .map(|model| model.name.clone())
+            .ok_or(AliceError::Other("No model selected".into()))
+    }
+
+    // A request without a prompt only loads or, with a zero keep alive, unloads the model.
+    async fn touch(&self, model: String, keep_alive: Option<i64>) -> Result<()> {
+        self.client
+            .post(format!("{}/api/generate", self.url))
+            .json(&GenerateRequest {
+                model,
+                prompt: None,
+                raw: false,
+                stream: false,
+                images: vec![],
+                options: None,
+                format: None,
+                keep_alive,
+            })
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Api for OllamaApi {
+    async fn connect(&mut self) -> Result<()> {
+        self.client
+            .get(format!("{}/api/version", self.url))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn is_alive(&mut self) -> Result<bool> {
+        Ok(self
+            .client
+            .get(format!("{}/api/version", self.url))
+            .send()
+            .await
+            .map(|response| response.status().is_success())
+            .unwrap_or(false))
+    }
+
+    async fn load(
+        &mut self,
+        model: &Model,
+        preload_callback: Box<dyn Fn(String) -> Result<()> + Send + Sync>,
+    ) -> Result<String> {
+        preload_callback("loading".into())?;
+        self.touch(model.name.clone(), None).await?;
+        self.model = Some(model.clone());
+        Ok("loaded".into())
+    }
+
+    async fn unload(&mut self) -> Result<()> {
+        if let Some(model) = self.model.take() {
+            self.touch(model.name, Some(0)).await?;
+        }
+        Ok(())
+    }
+
+    async fn status(&mut self) -> Result<Option<Model>> {
+        Ok(self.model.clone())
+    }
+
+    async fn list(&mut self) -> Result<Vec<Model>> {
+        Ok(self
+            .client
+            .get(format!("{}/api/tags", self.url))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<TagsResult>()
+            .await?
+            .models
+            .into_iter()
+            .map(|model| Model::new(model.name, Engine::Ollama))
+            .collect())
+    }
+
+    async fn complete(
+        &mut self,
+        snippet: &str,
+        images: &[ImageData],
+        engine_parameters: EngineParameters,
+        constraints: Constraints,
+        streaming_callback: Box<dyn Fn(String) -> Result<()> + Send + Sync>,
+    ) -> Result<String> {
+        let request = GenerateRequest {
+            model: self.model_name()?,
+            prompt: Some(snippet.to_string()),
+            raw: true,
+            stream: true,
+            images: images.iter().map(|image| image.data.clone()).collect(),
+            options: Some(GenerateOptions {
+                num_predict: engine_parameters.max_tokens,
+                num_ctx: engine_parameters.context_window,
+                temperature: engine_parameters.temperature,
+                top_k: engine_parameters.top_k,
+                top_p: engine_parameters.top_p,
+                min_p: engine_parameters.min_p,
+                typical_p: engine_parameters.typical_p,
+                repeat_penalty: engine_parameters.repetition_penalty,
+                repeat_last_n: engine_parameters.repetition_penalty_range,
+                frequency_penalty: engine_parameters.frequency_penalty,
+                presence_penalty: engine_parameters.presence_penalty,
+                mirostat: match engine_parameters.mirostat {
+                    true => engine_parameters.mirostat_mode,
+                    false => 0,
+                },
+                mirostat_tau: engine_parameters.mirostat_tau,
+                mirostat_eta: engine_parameters.mirostat_eta,
+                stop: engine_parameters.stop_sequences,
+            }),
+            format: constraints.json_schema,
+            keep_alive: None,
+        };
+
+        let mut response = self
+            .client
+            .post(format!("{}/api/generate", self.url))
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let mut buffer = Vec::new();
+        let mut full = String::new();
+        while let Some(chunk) = response.chunk().await? {
+            buffer.extend_from_slice(&chunk);
+            for line in drain_lines(&mut buffer) {
+                let chunk: GenerateChunk = serde_json::from_str(&line)?;
+                if !chunk.response.is_empty() {
+                    full.push_str(&chunk.response);
+                    streaming_callback(chunk.response)?;
+                }
+                if chunk.done {
+                    return Ok(full);
+                }
+            }
+        }
+        Ok(full)
+    }
+
+    async fn embed(&mut self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let model = match &self.embedding_model {
+            Some(model) => model.clone(),
+            None => self.model_name()?,
+        };
+        Ok(self
+            .client
+            .post(format!("{}/api/embed", self.url))
+            .json(&EmbedRequest {
+                model,
+                input: texts.to_vec(),
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<EmbedResult>()
+            .await?
+            .embeddings)
+    }
+}
+
+// Takes every complete line of the newline delimited JSON stream out of the buffer, leaving a
+// trailing partial line in place for the next chunk.
+fn drain_lines(buffer: &mut Vec<u8>) -> Vec<String> {
+    let Some(end) = buffer.iter().rposition(|byte| *byte == b'\n') else {
+        return vec![];
+    };
+    let lines = String::from_utf8_lossy(&buffer[..end])
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+    buffer.drain(..=end);
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drain_lines() {
+        let mut buffer = b"{\"response\":\"Hi\"}\n\n{\"done\":true}\n{\"resp".to_vec();
+        let lines = drain_lines(&mut buffer);
+        assert_eq!(lines, vec!["{\"response\":\"Hi\"}", "{\"done\":true}"]);
+        assert_eq!(buffer, b"{\"resp");
+    }
+}
@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Deserialize, Default)]
+pub struct TagsResult {
+    pub models: Vec<ModelObject>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct ModelObject {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GenerateRequest {
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    // The prompt is already templated, Ollama would wrap it in the model's template otherwise.
+    pub raw: bool,
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<GenerateOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GenerateOptions {
+    pub num_predict: i64,
+    pub num_ctx: i64,
+    pub temperature: f64,
+    pub top_k: i64,
+    pub top_p: f64,
+    pub min_p: f64,
+    pub typical_p: f64,
+    pub repeat_penalty: f64,
+    pub repeat_last_n: i64,
+    pub frequency_penalty: f64,
+    pub presence_penalty: f64,
+    pub mirostat: i64,
+    pub mirostat_tau: f64,
+    pub mirostat_eta: f64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct GenerateChunk {
+    #[serde(default)]
+    pub response: String,
+    #[serde(default)]
+    pub done: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmbedRequest {
+    pub model: String,
+    pub input: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct EmbedResult {
+    pub embeddings: Vec<Vec<f32>>,
+}
@@ -0,0 +1,290 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use models::{
+    ChatMessage, CompletionChunk, CompletionRequest, ContentPart, EmbeddingRequest,
+    EmbeddingResult, ImageUrl, ModelListResult,
+};
+use reqwest::{Client, RequestBuilder};
+use tokio::sync::Mutex;
+
+use crate::{
+    attachment::media::{self, ImageData, Segment},
+    models::{
+        constraints::Constraints,
+        model::{Engine, Model},
+        parameters::EngineParameters,
+    },
+    prelude::*,
+};
+
+use super::Api;
+
+mod models;
+
+// Any server exposing the OpenAI `/v1/models` and `/v1/completions` endpoints, e.g. llama.cpp's
+// server, vLLM, TabbyAPI or Ollama. These load models on demand, so `load` only selects one.
+pub struct OpenAiApi {
+    client: Client,
+    url: String,
+    api_key: Option<String>,
+    // Used for `/v1/embeddings` instead of the selected model, which often can't embed.
+    embedding_model: Option<String>,
+    model: Option<Model>,
+}
+
+impl OpenAiApi {
+    pub fn new(
+        url: String,
+        api_key: Option<String>,
+        embedding_model: Option<String>,
+    ) -> Result<Arc<Mutex<Self>>> {
+        Ok(Arc::new(Mutex::new(Self {
+            client: Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            api_key,
+            embedding_model,
+            model: None,
+        })))
+    }
+
+    fn get(&self, path: &str) -> RequestBuilder {
+        self.authorize(self.client.get(format!("{}{}", self.url, path)))
+    }
+
+    fn post(&self, path: &str) -> RequestBuilder {
+        self.authorize(self.client.post(format!("{}{}", self.url, path)))
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        }
+    }
+
+    fn model_name(&self) -> Result<String> {
+        self.model
+            .as_ref()
+            .map(|model| model.name.clone())
+            .ok_or(AliceError::Other("No model selected".into()))
+    }
+}
+
+#[async_trait]
+impl Api for OpenAiApi {
+    async fn connect(&mut self) -> Result<()> {
+        self.get("/models").send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn is_alive(&mut self) -> Result<bool> {
+        Ok(self
+            .get("/models")
+            .send()
+            .await
+            .map(|response| response.status().is_success())
+            .unwrap_or(false))
+    }
+
+    async fn load(
+        &mut self,
+        model: &Model,
+        preload_callback: Box<dyn Fn(String) -> Result<()> + Send + Sync>,
+    ) -> Result<String> {
+        preload_callback("loading".into())?;
+        self.model = Some(model.clone());
+        Ok("loaded".into())
+    }
+
+    async fn unload(&mut self) -> Result<()> {
+        self.model = None;
+        Ok(())
+    }
+
+    async fn status(&mut self) -> Result<Option<Model>> {
+        Ok(self.model.clone())
+    }
+
+    async fn list(&mut self) -> Result<Vec<Model>> {
+        Ok(self
+            .get("/models")
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<ModelListResult>()
+            .await?
+            .data
+            .into_iter()
+            .map(|model| Model::new(model.id, Engine::OpenAi))
+            .collect())
+    }
+
+    async fn complete(
+        &mut self,
+        snippet: &str,
+        images: &[ImageData],
+        engine_parameters: EngineParameters,
+        constraints: Constraints,
+        streaming_callback: Box<dyn Fn(String) -> Result<()> + Send + Sync>,
+    ) -> Result<String> {
+        // The whole templated prompt goes in a single message, so the server's chat template only
+        // wraps it rather than replacing the one the prompt was built with.
+        let (path, prompt, messages) = match images.is_empty() {
+            true => ("/completions", Some(snippet.to_string()), None),
+            false => (
+                "/chat/completions",
+                None,
+                Some(vec![ChatMessage {
+                    role: "user".into(),
+                    content: content_parts(snippet, images)?,
+                }]),
+            ),
+        };
+        let request = CompletionRequest {
+            model: self.model_name()?,
+            prompt,
+            messages,
+            stream: true,
+            max_tokens: engine_parameters.max_tokens,
+            temperature: engine_parameters.temperature,
+            top_p: engine_parameters.top_p,
+            top_k: engine_parameters.top_k,
+            min_p: engine_parameters.min_p,
+            typical_p: engine_parameters.typical_p,
+            repetition_penalty: engine_parameters.repetition_penalty,
+            frequency_penalty: engine_parameters.frequency_penalty,
+            presence_penalty: engine_parameters.presence_penalty,
+            stop: engine_parameters.stop_sequences,
+            logit_bias: engine_parameters
+                .banned_tokens
+                .iter()
+                .map(|token| (token.to_string(), -100.0))
+                .collect::<HashMap<_, _>>(),
+            response_format: constraints.response_format(),
+            grammar: constraints.grammar,
+        };
+
+        let mut response = self
+            .post(path)
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let mut buffer = Vec::new();
+        let mut full = String::new();
+        while let Some(chunk) = response.chunk().await? {
+            buffer.extend_from_slice(&chunk);
+            for data in drain_events(&mut buffer) {
+                if data == "[DONE]" {
+                    return Ok(full);
+                }
+                let chunk: CompletionChunk = serde_json::from_str(&data)?;
+                for choice in chunk.choices {
+                    let text = choice.into_text();
+                    if !text.is_empty() {
+                        full.push_str(&text);
+                        streaming_callback(text)?;
+                    }
+                }
+            }
+        }
+        Ok(full)
+    }
+
+    async fn embed(&mut self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let model = match &self.embedding_model {
+            Some(model) => model.clone(),
+            None => self.model_name()?,
+        };
+        let mut data = self
+            .post("/embeddings")
+            .json(&EmbeddingRequest {
+                model,
+                input: texts.to_vec(),
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<EmbeddingResult>()
+            .await?
+            .data;
+        data.sort_by_key(|embedding| embedding.index);
+        Ok(data
+            .into_iter()
+            .map(|embedding| embedding.embedding)
+            .collect())
+    }
+}
+
+// The prompt split around its image markers, each image sent as a base64 data URL.
+fn content_parts(snippet: &str, images: &[ImageData]) -> Result<Vec<ContentPart>> {
+    media::segments(snippet)
+        .into_iter()
+        .map(|segment| match segment {
+            Segment::Text(text) => Ok(ContentPart::Text {
+                text: text.to_string(),
+            }),
+            Segment::Image(index) => images
+                .get(index)
+                .map(|image| ContentPart::ImageUrl {
+                    image_url: ImageUrl {
+                        url: image.data_url(),
+                    },
+                })
+                .ok_or_else(|| AliceError::Other(format!("No image {} to send", index))),
+        })
+        .collect()
+}
+
+// Takes the `data:` payloads of every complete server-sent event line out of the buffer, leaving
+// a trailing partial line in place for the next chunk. Splitting on the newline byte never cuts
+// a multi-byte character in half.
+fn drain_events(buffer: &mut Vec<u8>) -> Vec<String> {
+    let Some(end) = buffer.iter().rposition(|byte| *byte == b'\n') else {
+        return vec![];
+    };
+    let events = String::from_utf8_lossy(&buffer[..end])
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("data:"))
+        .map(|data| data.trim().to_string())
+        .collect();
+    buffer.drain(..=end);
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drain_events() {
+        let mut buffer = b"data: {\"a\": 1}\n\n: keep-alive\ndata: [DONE]\ndata: {\"b\"".to_vec();
+        let events = drain_events(&mut buffer);
+        assert_eq!(events, vec!["{\"a\": 1}", "[DONE]"]);
+        assert_eq!(buffer, b"data: {\"b\"");
+    }
+
+    #[test]
+    fn test_content_parts() {
+        let image = ImageData {
+            mime: "image/png".into(),
+            data: "AAAA".into(),
+        };
+        let parts = content_parts("Look [img-0] here", &[image]).unwrap();
+        assert_eq!(
+            serde_json::to_value(parts).unwrap(),
+            serde_json::json!([
+                {"type": "text", "text": "Look "},
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}},
+                {"type": "text", "text": " here"},
+            ])
+        );
+        assert!(content_parts("[img-1]", &[]).is_err());
+    }
+}
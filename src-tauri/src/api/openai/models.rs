@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Deserialize, Default)]
+pub struct ModelListResult {
+    pub data: Vec<ModelObject>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct ModelObject {
+    pub id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CompletionRequest {
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    // Set instead of the prompt when sending images, which only `/v1/chat/completions` takes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub messages: Option<Vec<ChatMessage>>,
+    pub stream: bool,
+    pub max_tokens: i64,
+    pub temperature: f64,
+    pub top_p: f64,
+    pub top_k: i64,
+    pub min_p: f64,
+    pub typical_p: f64,
+    pub repetition_penalty: f64,
+    pub frequency_penalty: f64,
+    pub presence_penalty: f64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub logit_bias: HashMap<String, f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grammar: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: Vec<ContentPart>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImageUrl {
+    pub url: String,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct CompletionChunk {
+    pub choices: Vec<CompletionChoice>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct CompletionChoice {
+    #[serde(default)]
+    pub text: String,
+    // What chat completions stream instead of the text.
+    pub delta: Option<ChoiceDelta>,
+}
+
+impl CompletionChoice {
+    pub fn into_text(self) -> String {
+        match self.delta {
+            Some(delta) => delta.content.unwrap_or_default(),
+            None => self.text,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct ChoiceDelta {
+    pub content: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmbeddingRequest {
+    pub model: String,
+    pub input: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct EmbeddingResult {
+    pub data: Vec<EmbeddingObject>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct EmbeddingObject {
+    pub index: usize,
+    pub embedding: Vec<f32>,
+}
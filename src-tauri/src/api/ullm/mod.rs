@@ -2,14 +2,15 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use models::{
-    CompletionParams, CompletionResult, CompletionStatus, LoadParams, ModelListResult, Response,
-    StatusResult,
+    CompletionParams, CompletionResult, CompletionStatus, EmbedParams, EmbedResult, LoadParams,
+    ModelListResult, Response, StatusResult,
 };
 use tokio::sync::Mutex;
 use uuid::Uuid;
 
 use crate::{
-    models::{model::Model, parameters::EngineParameters},
+    attachment::media::ImageData,
+    models::{constraints::Constraints, model::Model, parameters::EngineParameters},
     prelude::*,
 };
 
@@ -146,7 +147,9 @@ impl Api for UllmApi {
     async fn complete(
         &mut self,
         snippet: &str,
+        images: &[ImageData],
         engine_parameters: EngineParameters,
+        constraints: Constraints,
         streaming_callback: Box<dyn Fn(String) -> Result<()> + Send + Sync>,
     ) -> Result<String> {
         fn should_stop(response: &Response<CompletionResult>) -> Result<bool> {
@@ -158,7 +161,9 @@ impl Api for UllmApi {
             method: "complete".to_string(),
             params: Some(CompletionParams {
                 snippet: snippet.to_string(),
+                images: images.to_vec(),
                 engine_parameters,
+                constraints,
             }),
         };
 
@@ -175,4 +180,23 @@ impl Api for UllmApi {
             .result
             .tokens)
     }
+
+    async fn embed(&mut self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let embed = MethodCall {
+            id: Uuid::new_v4(),
+            method: "embed".to_string(),
+            params: Some(EmbedParams {
+                texts: texts.to_vec(),
+            }),
+        };
+
+        self.client.send_str(serde_json::to_string(&embed)?).await?;
+
+        Ok(self
+            .client
+            .return_single::<Response<EmbedResult>>()
+            .await?
+            .result
+            .embeddings)
+    }
 }
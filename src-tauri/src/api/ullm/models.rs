@@ -3,7 +3,12 @@ use std::fmt::{self, Display, Formatter};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::models::{model::{Engine, Model}, parameters::EngineParameters};
+use crate::attachment::media::ImageData;
+use crate::models::{
+    constraints::Constraints,
+    model::{Engine, Model},
+    parameters::EngineParameters,
+};
 
 #[derive(Debug, Deserialize, Default)]
 pub struct Response<T> {
@@ -84,7 +89,11 @@ pub struct LoadParams {
 #[derive(Debug, Serialize)]
 pub struct CompletionParams {
     pub snippet: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<ImageData>,
     pub engine_parameters: EngineParameters,
+    #[serde(skip_serializing_if = "Constraints::is_empty")]
+    pub constraints: Constraints,
 }
 
 #[derive(Default, Debug, Deserialize)]
@@ -98,3 +107,13 @@ pub struct StatusResult {
 pub struct ModelListResult {
     pub models: Vec<Model>,
 }
+
+#[derive(Debug, Serialize)]
+pub struct EmbedParams {
+    pub texts: Vec<String>,
+}
+
+#[derive(Default, Debug, Deserialize)]
+pub struct EmbedResult {
+    pub embeddings: Vec<Vec<f32>>,
+}
@@ -0,0 +1,134 @@
+use crate::prelude::*;
+
+use crate::DB;
+
+use std::{collections::HashSet, path::Path};
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use sha2::{Digest, Sha256};
+
+pub mod media;
+
+use media::{Attachment, AttachmentKind, ImageData};
+
+// The longest side of an image's thumbnail, in pixels.
+static THUMBNAIL_SIZE: u32 = 256;
+
+impl Attachment {
+    // Copies the file into the data dir, unless a file with the same content already is.
+    pub async fn import(path: &str) -> Result<Self> {
+        let bytes = tokio::fs::read(path).await?;
+        let name = Path::new(path)
+            .file_name()
+            .map_or(path.to_string(), |name| name.to_string_lossy().into_owned());
+        Self::store(name, &bytes).await
+    }
+
+    // Stores a file made in the app rather than picked by the user, like a generated image.
+    pub async fn store(name: String, bytes: &[u8]) -> Result<Self> {
+        let attachment = Self::describe(name, bytes)?;
+        tokio::fs::create_dir_all(Self::dir()).await?;
+        let stored = attachment.path();
+        if !tokio::fs::try_exists(&stored).await? {
+            tokio::fs::write(&stored, bytes).await?;
+        }
+        Ok(attachment)
+    }
+
+    // The attachment stored under `hash`, for hashes sent by the UI. Everything else is read from
+    // the file, and the original name is gone by now, so the hash stands in for it.
+    pub async fn stored(hash: &str) -> Result<Self> {
+        let unknown = || AliceError::UnknownAttachment(hash.to_string());
+        if !media::is_hash(hash) {
+            return Err(unknown());
+        }
+        let bytes = match tokio::fs::read(format!("{}/{}", Self::dir(), hash)).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(unknown()),
+            Err(e) => return Err(e.into()),
+        };
+        let attachment = Self::describe(hash.to_string(), &bytes)?;
+        match attachment.hash == hash {
+            true => Ok(attachment),
+            false => Err(unknown()),
+        }
+    }
+
+    fn describe(name: String, bytes: &[u8]) -> Result<Self> {
+        let (mime, kind) = media::sniff(&name, bytes)
+            .ok_or_else(|| AliceError::UnsupportedAttachment(name.clone()))?;
+        let hash = format!("{:x}", Sha256::digest(bytes));
+        let dimensions = match kind {
+            AttachmentKind::Image => media::dimensions(bytes),
+            AttachmentKind::Text => None,
+        };
+        Ok(Self {
+            hash,
+            name,
+            mime,
+            kind,
+            size: bytes.len(),
+            dimensions,
+            thumbnail: dimensions.map(|dimensions| media::thumbnail(dimensions, THUMBNAIL_SIZE)),
+            generation: None,
+        })
+    }
+
+    fn dir() -> String {
+        format!("{}/attachments", crate::data_dir())
+    }
+
+    pub fn path(&self) -> String {
+        format!("{}/{}", Self::dir(), self.hash)
+    }
+
+    // Where the file is, as long as the hash can't lead out of the attachments dir. Messages saved
+    // before hashes were checked may have anything in them.
+    fn checked_path(&self) -> Result<String> {
+        match media::is_hash(&self.hash) {
+            true => Ok(self.path()),
+            false => Err(AliceError::UnknownAttachment(self.hash.clone())),
+        }
+    }
+
+    pub async fn image(&self) -> Result<ImageData> {
+        Ok(ImageData {
+            mime: self.mime.clone(),
+            data: STANDARD.encode(tokio::fs::read(self.checked_path()?).await?),
+        })
+    }
+
+    pub async fn text(&self) -> Result<String> {
+        Ok(tokio::fs::read_to_string(self.checked_path()?).await?)
+    }
+
+    // Deletes the stored files no message refers to anymore, returning how many there were. Files
+    // imported for a message that hasn't been sent yet go too.
+    pub async fn remove_orphans() -> Result<usize> {
+        let attachments: Vec<Vec<Option<Vec<Attachment>>>> = db!()
+            .query("SELECT VALUE messages.attachments FROM conversation")
+            .await?
+            .take(0)?;
+        let referenced = attachments
+            .into_iter()
+            .flatten()
+            .flatten()
+            .flatten()
+            .map(|attachment| attachment.hash)
+            .collect::<HashSet<_>>();
+        let mut removed = 0;
+        let mut entries = match tokio::fs::read_dir(Self::dir()).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !referenced.contains(&name) {
+                tokio::fs::remove_file(entry.path()).await?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
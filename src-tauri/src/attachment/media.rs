@@ -0,0 +1,314 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{imaging::prompt::ImageParameters, png};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachmentKind {
+    Image,
+    Text,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dimensions {
+    pub width: u32,
+    pub height: u32,
+}
+
+// A file attached to a message. It's stored in the data dir under the hash of its content, so the
+// same file attached twice is stored once.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attachment {
+    pub hash: String,
+    pub name: String,
+    pub mime: String,
+    pub kind: AttachmentKind,
+    pub size: usize,
+    // Images only, and only for the formats whose headers are understood.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<Dimensions>,
+    // What to show the image at in the chat without stretching it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<Dimensions>,
+    // What a generated image was made with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generation: Option<ImageParameters>,
+}
+
+// An image as it's sent to a backend along with a prompt.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageData {
+    pub mime: String,
+    // Base64, without a data URL prefix.
+    pub data: String,
+}
+
+impl ImageData {
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.mime, self.data)
+    }
+}
+
+// Part of a prompt, which is text with image markers in it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    // Index into the images sent with the prompt.
+    Image(usize),
+}
+
+// Where an image goes in a prompt, llama.cpp's `[img-N]` convention.
+pub fn image_marker(index: usize) -> String {
+    format!("[img-{}]", index)
+}
+
+// Marker look-alikes in what users and files write, so only the markers of attached images are
+// taken for images. `[img-2]` becomes `[img 2]`.
+pub fn escape_markers(text: &str) -> String {
+    text.replace("[img-", "[img ")
+}
+
+// How a text attachment is inlined into its message.
+pub fn text_block(name: &str, content: &str) -> String {
+    format!(
+        "Attached file `{}`:\n```\n{}\n```",
+        name,
+        escape_markers(content.trim_end())
+    )
+}
+
+// Splits a prompt around its image markers, for backends that take images as separate parts.
+pub fn segments(snippet: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut text_start = 0;
+    let mut search = 0;
+    while let Some(found) = snippet[search..].find("[img-") {
+        let start = search + found;
+        let digits = &snippet[start + 5..];
+        let marker = digits
+            .find(']')
+            .and_then(|end| Some((digits[..end].parse::<usize>().ok()?, end)));
+        let Some((index, end)) = marker else {
+            search = start + 5;
+            continue;
+        };
+        if start > text_start {
+            segments.push(Segment::Text(&snippet[text_start..start]));
+        }
+        segments.push(Segment::Image(index));
+        text_start = start + 5 + end + 1;
+        search = text_start;
+    }
+    if text_start < snippet.len() {
+        segments.push(Segment::Text(&snippet[text_start..]));
+    }
+    segments
+}
+
+// Images are recognized by their signature, anything else has to be UTF-8 text to be attached.
+pub fn sniff(name: &str, bytes: &[u8]) -> Option<(String, AttachmentKind)> {
+    let image = if bytes.starts_with(&png::SIGNATURE) {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    };
+    if let Some(mime) = image {
+        return Some((mime.to_string(), AttachmentKind::Image));
+    }
+    if bytes.contains(&0) || std::str::from_utf8(bytes).is_err() {
+        return None;
+    }
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase);
+    let mime = match extension.as_deref() {
+        Some("md" | "markdown") => "text/markdown",
+        Some("json") => "application/json",
+        Some("csv") => "text/csv",
+        Some("html" | "htm") => "text/html",
+        _ => "text/plain",
+    };
+    Some((mime.to_string(), AttachmentKind::Text))
+}
+
+// Reads the size out of the image's header, without decoding it.
+pub fn dimensions(bytes: &[u8]) -> Option<Dimensions> {
+    let le16 = |at: usize| Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32);
+    let be32 = |at: usize| Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+    let le24 = |at: usize| {
+        let bytes = bytes.get(at..at + 3)?;
+        Some(bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16)
+    };
+    let (width, height) = if bytes.starts_with(&png::SIGNATURE) {
+        // The header chunk always comes first.
+        (be32(16)?, be32(20)?)
+    } else if bytes.starts_with(b"GIF8") {
+        (le16(6)?, le16(8)?)
+    } else if bytes.starts_with(&[0xff, 0xd8]) {
+        jpeg_dimensions(bytes)?
+    } else if bytes.len() >= 30 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        match &bytes[12..16] {
+            b"VP8X" => (le24(24)? + 1, le24(27)? + 1),
+            b"VP8L" => {
+                let bits = u32::from_le_bytes(bytes.get(21..25)?.try_into().ok()?);
+                ((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1)
+            }
+            b"VP8 " => (le16(26)? & 0x3fff, le16(28)? & 0x3fff),
+            _ => return None,
+        }
+    } else {
+        return None;
+    };
+    Some(Dimensions { width, height })
+}
+
+// Walks the segments up to the first start of frame, which holds the size.
+fn jpeg_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let mut at = 2;
+    while at + 9 < bytes.len() {
+        if bytes[at] != 0xff {
+            return None;
+        }
+        let marker = bytes[at + 1];
+        let length = u16::from_be_bytes([bytes[at + 2], bytes[at + 3]]) as usize;
+        let is_frame = (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker);
+        if is_frame {
+            let height = u16::from_be_bytes([bytes[at + 5], bytes[at + 6]]) as u32;
+            let width = u16::from_be_bytes([bytes[at + 7], bytes[at + 8]]) as u32;
+            return Some((width, height));
+        }
+        at += 2 + length;
+    }
+    None
+}
+
+// Fits the image in a `max` pixel square, never scaling it up.
+pub fn thumbnail(dimensions: Dimensions, max: u32) -> Dimensions {
+    let longest = dimensions.width.max(dimensions.height);
+    if longest <= max || longest == 0 {
+        return dimensions;
+    }
+    let scale = |side: u32| ((side as u64 * max as u64) / longest as u64).max(1) as u32;
+    Dimensions {
+        width: scale(dimensions.width),
+        height: scale(dimensions.height),
+    }
+}
+
+// Whether `text` could be the name of a stored file, a SHA-256 in lowercase hex. Anything else
+// could point outside the attachments dir.
+pub fn is_hash(text: &str) -> bool {
+    text.len() == 64 && text.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segments() {
+        assert_eq!(
+            segments("<|user|>Look [img-0] and [img-1]."),
+            vec![
+                Segment::Text("<|user|>Look "),
+                Segment::Image(0),
+                Segment::Text(" and "),
+                Segment::Image(1),
+                Segment::Text("."),
+            ]
+        );
+        assert_eq!(
+            segments("Not [img-x] an image"),
+            vec![Segment::Text("Not [img-x] an image")]
+        );
+        assert_eq!(segments("[img-2]"), vec![Segment::Image(2)]);
+        let typed = format!("{} {}", image_marker(0), escape_markers("See [img-3]"));
+        assert_eq!(
+            segments(&typed),
+            vec![Segment::Image(0), Segment::Text(" See [img 3]")]
+        );
+        assert!(!text_block("notes.md", "[img-0]").contains("[img-"));
+    }
+
+    #[test]
+    fn test_is_hash() {
+        assert!(is_hash(&"0a".repeat(32)));
+        assert!(!is_hash(&"0A".repeat(32)));
+        assert!(!is_hash("../../.ssh/id_rsa"));
+        assert!(!is_hash(&format!("../{}", "a".repeat(61))));
+    }
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(
+            sniff("blank.png", &png::BLANK),
+            Some(("image/png".into(), AttachmentKind::Image))
+        );
+        assert_eq!(
+            sniff("notes.md", "# Notes".as_bytes()),
+            Some(("text/markdown".into(), AttachmentKind::Text))
+        );
+        assert_eq!(sniff("data.bin", &[0, 159, 146, 150]), None);
+    }
+
+    #[test]
+    fn test_dimensions() {
+        assert_eq!(
+            dimensions(&png::BLANK),
+            Some(Dimensions {
+                width: 1,
+                height: 1
+            })
+        );
+        let mut gif = b"GIF89a".to_vec();
+        gif.extend_from_slice(&[0x40, 0x01, 0xf0, 0x00]);
+        assert_eq!(
+            dimensions(&gif),
+            Some(Dimensions {
+                width: 320,
+                height: 240
+            })
+        );
+        let jpeg = [
+            0xff, 0xd8, 0xff, 0xe0, 0x00, 0x04, 0x00, 0x00, 0xff, 0xc0, 0x00, 0x11, 0x08, 0x01,
+            0xe0, 0x02, 0x80, 0x03, 0x00, 0x00,
+        ];
+        assert_eq!(
+            dimensions(&jpeg),
+            Some(Dimensions {
+                width: 640,
+                height: 480
+            })
+        );
+        assert_eq!(dimensions(b"plain text"), None);
+    }
+
+    #[test]
+    fn test_thumbnail() {
+        let large = Dimensions {
+            width: 1024,
+            height: 512,
+        };
+        assert_eq!(
+            thumbnail(large, 256),
+            Dimensions {
+                width: 256,
+                height: 128
+            }
+        );
+        let small = Dimensions {
+            width: 10,
+            height: 20,
+        };
+        assert_eq!(thumbnail(small, 256), small);
+    }
+}
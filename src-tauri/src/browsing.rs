@@ -0,0 +1,367 @@
+use crate::prelude::*;
+
+use crate::DB;
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect, Client,
+};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+pub mod domains;
+pub mod readable;
+
+use domains::DomainRules;
+
+static USER_AGENT: &str = "Mozilla/5.0 (compatible; Alice)";
+
+// Redirects followed before giving up on a page.
+static MAX_REDIRECTS: usize = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BrowsingConfig {
+    // Whether the model gets the web tools, fetching from the UI works either way.
+    #[serde(default)]
+    pub enabled: bool,
+    // A SearXNG instance with the JSON format enabled, or anything answering
+    // `/search?format=json` the same way.
+    #[serde(default)]
+    pub search_url: Option<String>,
+    #[serde(default)]
+    pub domains: DomainRules,
+    // Pages are cut off after this many bytes.
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: usize,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_max_bytes() -> usize {
+    2 * 1024 * 1024
+}
+
+fn default_timeout_secs() -> u64 {
+    15
+}
+
+impl Default for BrowsingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            search_url: None,
+            domains: DomainRules::default(),
+            max_bytes: default_max_bytes(),
+            timeout_secs: default_timeout_secs(),
+        }
+    }
+}
+
+impl BrowsingConfig {
+    pub async fn get() -> Result<Self> {
+        let config: Option<Self> = db!().select(("browsing", "default")).await?;
+        Ok(config.unwrap_or_default())
+    }
+
+    pub async fn set(self) -> Result<Self> {
+        db!()
+            .upsert(("browsing", "default"))
+            .content(self)
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "upsert".into(),
+                "browsing".into(),
+            ))
+    }
+}
+
+// A fetched page, as readable text.
+#[derive(Debug, Clone, Serialize)]
+pub struct Page {
+    // Where the page ended up after redirects.
+    pub url: String,
+    pub title: Option<String>,
+    pub content: String,
+    // The page was longer than the size limit.
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+    // The snippet the engine shows under the title.
+    #[serde(default)]
+    pub content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResponse {
+    results: Vec<SearchResult>,
+}
+
+// Resolves names the system's way, then refuses any that lead to local addresses. Hosts given as
+// IP addresses never get here, `DomainRules::permits` checks those.
+struct GuardedResolver {
+    domains: DomainRules,
+}
+
+impl Resolve for GuardedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let domains = self.domains.clone();
+        Box::pin(async move {
+            let addresses = resolve(&domains, name.as_str()).await?;
+            Ok(Box::new(addresses.into_iter()) as Addrs)
+        })
+    }
+}
+
+async fn resolve(domains: &DomainRules, host: &str) -> Result<Vec<SocketAddr>> {
+    let addresses = tokio::net::lookup_host((host, 0))
+        .await?
+        .collect::<Vec<_>>();
+    let ips = addresses
+        .iter()
+        .map(|address| address.ip())
+        .collect::<Vec<_>>();
+    match domains.permits_addresses(host, &ips) {
+        true => Ok(addresses),
+        false => Err(AliceError::BlockedUrl(host.to_string())),
+    }
+}
+
+pub struct Browser {
+    client: Client,
+    // The search engine is the user's own choice, often a SearXNG on the LAN, so it's reached
+    // without the domain rules. Only its results go through them.
+    search_client: Client,
+    config: BrowsingConfig,
+}
+
+impl Browser {
+    pub fn new(config: BrowsingConfig) -> Result<Self> {
+        // Redirects are checked against the domain rules too, or an allowed page could bounce the
+        // request anywhere.
+        let domains = config.domains.clone();
+        let policy = redirect::Policy::custom(move |attempt| {
+            let permitted = attempt
+                .url()
+                .host_str()
+                .is_some_and(|host| domains.permits(host));
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if !permitted {
+                let blocked = attempt.url().to_string();
+                attempt.error(AliceError::BlockedUrl(blocked))
+            } else {
+                attempt.follow()
+            }
+        });
+        let client = Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .redirect(policy)
+            .dns_resolver(Arc::new(GuardedResolver {
+                domains: config.domains.clone(),
+            }))
+            .build()?;
+        let search_client = Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()?;
+        Ok(Self {
+            client,
+            search_client,
+            config,
+        })
+    }
+
+    pub async fn with_config() -> Result<Self> {
+        Self::new(BrowsingConfig::get().await?)
+    }
+
+    fn check(&self, url: &str) -> Result<Url> {
+        let parsed = Url::parse(url).map_err(|_| AliceError::BlockedUrl(url.to_string()))?;
+        let permitted = matches!(parsed.scheme(), "http" | "https")
+            && parsed
+                .host_str()
+                .is_some_and(|host| self.config.domains.permits(host));
+        match permitted {
+            true => Ok(parsed),
+            false => Err(AliceError::BlockedUrl(url.to_string())),
+        }
+    }
+
+    // Downloads up to the size limit and extracts the article when it's HTML.
+    pub async fn fetch(&self, url: &str) -> Result<Page> {
+        let url = self.check(url)?;
+        let mut response = self.client.get(url).send().await?.error_for_status()?;
+        let url = response.url().to_string();
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("text/html")
+            .to_lowercase();
+        let html = content_type.contains("html");
+        let text = content_type.starts_with("text/") || content_type.contains("json");
+        if !html && !text {
+            return Err(AliceError::UnsupportedPage(content_type));
+        }
+
+        let mut body = Vec::new();
+        let mut truncated = false;
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() > self.config.max_bytes {
+                body.truncate(self.config.max_bytes);
+                truncated = true;
+                break;
+            }
+        }
+        let body = String::from_utf8_lossy(&body);
+        let (title, content) = match html {
+            true => (readable::title(&body), readable::readable(&body)),
+            false => (None, body.trim().to_string()),
+        };
+        Ok(Page {
+            url,
+            title,
+            content,
+            truncated,
+        })
+    }
+
+    // Results on hosts the rules don't permit are left out.
+    pub async fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
+        let search_url = self
+            .config
+            .search_url
+            .as_deref()
+            .ok_or(AliceError::NoSearchEngine)?;
+        let response = self
+            .search_client
+            .get(format!("{}/search", search_url.trim_end_matches('/')))
+            .query(&[("q", query), ("format", "json")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<SearchResponse>()
+            .await?;
+        Ok(response
+            .results
+            .into_iter()
+            .filter(|result| self.check(&result.url).is_ok())
+            .take(limit)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::{self, StubResponse};
+
+    static ARTICLE: &str = "<html><head><title>Tides</title></head><body><nav>Menu</nav>\
+        <article><h1>Tides</h1><p>The moon pulls the sea.</p></article></body></html>";
+
+    fn config(url: &str) -> BrowsingConfig {
+        BrowsingConfig {
+            search_url: Some(url.to_string()),
+            domains: DomainRules {
+                allow: vec!["127.0.0.1".into(), "example.org".into()],
+                deny: vec![],
+            },
+            ..BrowsingConfig::default()
+        }
+    }
+
+    async fn fixtures() -> String {
+        let (url, _) = testing::serve(|request| match request.path.as_str() {
+            "/tides" => StubResponse::new(200, "text/html; charset=utf-8", ARTICLE),
+            "/big" => StubResponse::new(200, "text/plain", "a".repeat(4096)),
+            "/image" => StubResponse::new(200, "image/png", vec![0; 8]),
+            path if path.starts_with("/search?") => StubResponse::json(json!({
+                "query": "tides",
+                "results": [
+                    {"title": "Tides", "url": "http://127.0.0.1/tides", "content": "The moon."},
+                    {"title": "Blocked", "url": "http://blocked.test/tides", "content": ""},
+                    {"title": "Example", "url": "https://example.org/", "content": "Example."},
+                ],
+            })),
+            _ => StubResponse::new(404, "text/plain", "Not found"),
+        })
+        .await;
+        url
+    }
+
+    #[tokio::test]
+    async fn test_fetch() {
+        let url = fixtures().await;
+        let browser = Browser::new(config(&url)).unwrap();
+        let page = browser.fetch(&format!("{}/tides", url)).await.unwrap();
+        assert_eq!(page.title.as_deref(), Some("Tides"));
+        assert_eq!(page.content, "# Tides\n\nThe moon pulls the sea.");
+        assert!(!page.truncated);
+
+        let limited = Browser::new(BrowsingConfig {
+            max_bytes: 1024,
+            ..config(&url)
+        })
+        .unwrap();
+        let page = limited.fetch(&format!("{}/big", url)).await.unwrap();
+        assert_eq!(page.content.len(), 1024);
+        assert!(page.truncated);
+
+        assert!(browser.fetch(&format!("{}/image", url)).await.is_err());
+        assert!(browser.fetch(&format!("{}/missing", url)).await.is_err());
+        assert!(browser.fetch("http://blocked.test/").await.is_err());
+        assert!(browser.fetch("file:///etc/passwd").await.is_err());
+        assert!(browser.fetch("http://[::ffff:127.0.0.1]/").await.is_err());
+        let open = Browser::new(BrowsingConfig::default()).unwrap();
+        assert!(open.fetch(&format!("{}/tides", url)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve() {
+        // `localhost` is blocked by name already, the addresses are checked on their own here.
+        let open = DomainRules::default();
+        assert!(resolve(&open, "localhost").await.is_err());
+        let allowed = DomainRules {
+            allow: vec!["localhost".into()],
+            deny: vec![],
+        };
+        assert!(!resolve(&allowed, "localhost").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        let url = fixtures().await;
+        let browser = Browser::new(config(&url)).unwrap();
+        let results = browser.search("tides", 5).await.unwrap();
+        let urls = results
+            .iter()
+            .map(|result| result.url.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(urls, vec!["http://127.0.0.1/tides", "https://example.org/"]);
+        assert_eq!(browser.search("tides", 1).await.unwrap().len(), 1);
+        // A local search engine is reached even though the rules keep its local results out.
+        let open = Browser::new(BrowsingConfig {
+            search_url: Some(url.replace("127.0.0.1", "localhost")),
+            ..BrowsingConfig::default()
+        })
+        .unwrap();
+        let results = open.search("tides", 5).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].url, "https://example.org/");
+        assert!(Browser::new(BrowsingConfig::default())
+            .unwrap()
+            .search("tides", 5)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+
+// Which hosts the browsing tools may reach. A domain covers its subdomains too, and the deny list
+// wins over the allow list. With an empty allow list every public host is allowed, but local
+// and private addresses never are unless allowed explicitly, so the model can't probe the LAN.
+// Names are only half the check, `permits_addresses` has to pass for what they resolve to too.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DomainRules {
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl DomainRules {
+    pub fn permits(&self, host: &str) -> bool {
+        let host = normalize(host);
+        if self.deny.iter().any(|domain| covers(domain, &host)) {
+            return false;
+        }
+        self.allows(&host) || (self.allow.is_empty() && !is_local(&host))
+    }
+
+    // Whether a permitted host may connect to the addresses it resolved to. A public name can
+    // point anywhere, so local addresses need the name allowed explicitly like local hosts do.
+    pub fn permits_addresses(&self, host: &str, addresses: &[IpAddr]) -> bool {
+        self.allows(&normalize(host)) || !addresses.iter().any(|ip| is_local_ip(*ip))
+    }
+
+    fn allows(&self, host: &str) -> bool {
+        self.allow.iter().any(|domain| covers(domain, host))
+    }
+}
+
+fn normalize(host: &str) -> String {
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .trim_end_matches('.')
+        .to_lowercase()
+}
+
+// Whether `domain` is `host` or one of its parents. A leading `*.` or `.` means the same.
+fn covers(domain: &str, host: &str) -> bool {
+    let domain = domain
+        .trim()
+        .trim_start_matches("*.")
+        .trim_start_matches('.')
+        .to_lowercase();
+    !domain.is_empty()
+        && (host == domain
+            || host
+                .strip_suffix(&domain)
+                .is_some_and(|subdomain| subdomain.ends_with('.')))
+}
+
+fn is_local(host: &str) -> bool {
+    if host == "localhost" || host.ends_with(".localhost") || host.ends_with(".local") {
+        return true;
+    }
+    host.parse::<IpAddr>().is_ok_and(is_local_ip)
+}
+
+pub fn is_local_ip(ip: IpAddr) -> bool {
+    // `::ffff:127.0.0.1` reaches the same host as `127.0.0.1`.
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        IpAddr::V4(_) => ip,
+    };
+    match ip {
+        IpAddr::V4(ip) => {
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+        }
+        IpAddr::V6(ip) => {
+            ip.is_loopback()
+                || ip.is_unspecified()
+                // Unique local and link-local addresses.
+                || (ip.segments()[0] & 0xfe00) == 0xfc00
+                || (ip.segments()[0] & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permits() {
+        let open = DomainRules::default();
+        assert!(open.permits("en.wikipedia.org"));
+        assert!(!open.permits("localhost"));
+        assert!(!open.permits("192.168.1.10"));
+        assert!(!open.permits("[::1]"));
+        assert!(!open.permits("[::ffff:127.0.0.1]"));
+        assert!(!open.permits("[::ffff:a9fe:a9fe]"));
+
+        let rules = DomainRules {
+            allow: vec!["wikipedia.org".into(), "127.0.0.1".into()],
+            deny: vec!["*.evil.wikipedia.org".into()],
+        };
+        assert!(rules.permits("en.wikipedia.org"));
+        assert!(rules.permits("127.0.0.1"));
+        assert!(!rules.permits("very.evil.wikipedia.org"));
+        assert!(!rules.permits("notwikipedia.org"));
+        assert!(!rules.permits("example.com"));
+    }
+
+    #[test]
+    fn test_permits_addresses() {
+        let local = ["127.0.0.1".parse().unwrap()];
+        let mapped = ["::ffff:10.0.0.1".parse().unwrap()];
+        let public = ["93.184.215.14".parse().unwrap()];
+        let open = DomainRules::default();
+        assert!(!open.permits_addresses("localtest.me", &local));
+        assert!(!open.permits_addresses("localtest.me", &mapped));
+        assert!(open.permits_addresses("example.org", &public));
+
+        let rules = DomainRules {
+            allow: vec!["localtest.me".into()],
+            deny: vec![],
+        };
+        assert!(rules.permits_addresses("localtest.me", &local));
+    }
+}
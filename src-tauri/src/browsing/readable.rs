@@ -0,0 +1,144 @@
+use crate::document::text::html_to_markdown;
+
+// Page furniture around the article, dropped along with everything in it.
+static BOILERPLATE_ELEMENTS: [&str; 7] =
+    ["nav", "header", "footer", "aside", "form", "button", "svg"];
+
+// Where the article is most likely to be, best guess first.
+static CONTENT_ELEMENTS: [&str; 3] = ["article", "main", "body"];
+
+// The page's title, if it has one.
+pub fn title(html: &str) -> Option<String> {
+    let title = element_contents(html, "title").into_iter().next()?;
+    let title = html_to_markdown(title);
+    (!title.is_empty()).then_some(title)
+}
+
+// The article text of a page as Markdown, without its navigation, sidebars and footer. The
+// longest `<article>` wins, then `<main>`, then the whole body.
+pub fn readable(html: &str) -> String {
+    let content = CONTENT_ELEMENTS
+        .iter()
+        .find_map(|name| {
+            element_contents(html, name)
+                .into_iter()
+                .max_by_key(|content| content.len())
+        })
+        .unwrap_or(html);
+    let mut content = content.to_string();
+    for name in BOILERPLATE_ELEMENTS {
+        content = without_elements(&content, name);
+    }
+    html_to_markdown(&content)
+}
+
+// Where each outermost element named `name` opens and closes, as the byte ranges of its opening
+// tag's start and its closing tag's end. Unclosed elements run to the end.
+fn element_spans(html: &str, name: &str) -> Vec<(usize, usize, usize, usize)> {
+    // ASCII lowercasing keeps byte offsets, so they work on the original too.
+    let lower = html.to_ascii_lowercase();
+    let open = format!("<{}", name);
+    let close = format!("</{}", name);
+    let is_tag = |at: usize, tag: &str| {
+        lower[at..].starts_with(tag)
+            && lower[at + tag.len()..]
+                .chars()
+                .next()
+                .is_some_and(|char| char == '>' || char == '/' || char.is_whitespace())
+    };
+    let tag_end = |at: usize| {
+        lower[at..]
+            .find('>')
+            .map_or(lower.len(), |end| at + end + 1)
+    };
+    let mut spans = Vec::new();
+    let mut depth = 0;
+    let mut start = (0, 0);
+    let mut at = 0;
+    while let Some(found) = lower[at..].find('<') {
+        let position = at + found;
+        if is_tag(position, &open) {
+            let end = tag_end(position);
+            if depth == 0 {
+                start = (position, end);
+            }
+            if !lower[..end].ends_with("/>") {
+                depth += 1;
+            }
+            at = end;
+        } else if depth > 0 && is_tag(position, &close) {
+            let end = tag_end(position);
+            depth -= 1;
+            if depth == 0 {
+                spans.push((start.0, start.1, position, end));
+            }
+            at = end;
+        } else {
+            at = position + 1;
+        }
+    }
+    if depth > 0 {
+        spans.push((start.0, start.1, lower.len(), lower.len()));
+    }
+    spans
+}
+
+fn element_contents<'a>(html: &'a str, name: &str) -> Vec<&'a str> {
+    element_spans(html, name)
+        .into_iter()
+        .map(|(_, inner_start, inner_end, _)| &html[inner_start..inner_end])
+        .collect()
+}
+
+fn without_elements(html: &str, name: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut kept = 0;
+    for (start, _, _, end) in element_spans(html, name) {
+        output.push_str(&html[kept..start]);
+        kept = end;
+    }
+    output.push_str(&html[kept..]);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static PAGE: &str = r#"<!DOCTYPE html>
+<html><head><title>Moths &amp; Lamps</title><style>body { color: red }</style></head>
+<body>
+  <header><nav><a href="/">Home</a> <a href="/blog">Blog</a></nav></header>
+  <main>
+    <article class="teaser"><p>Short teaser.</p></article>
+    <ARTICLE>
+      <h1>Why moths love lamps</h1>
+      <p>Moths navigate by the moon.</p>
+      <aside>Related: <a href="/bees">Bees</a></aside>
+      <section><p>Lamps confuse them.</p></section>
+    </ARTICLE>
+  </main>
+  <footer>© 2024</footer>
+</body></html>"#;
+
+    #[test]
+    fn test_readable() {
+        assert_eq!(title(PAGE), Some("Moths & Lamps".into()));
+        assert_eq!(
+            readable(PAGE),
+            "# Why moths love lamps\n\nMoths navigate by the moon.\n\nLamps confuse them."
+        );
+        assert_eq!(
+            readable("<p>No body at all.</p><nav>Menu</nav>"),
+            "No body at all."
+        );
+    }
+
+    #[test]
+    fn test_element_spans() {
+        let html = "<div><div>a</div></div><divider/><div>b";
+        let contents = element_contents(html, "div");
+        assert_eq!(contents, vec!["<div>a</div>", "b"]);
+        assert_eq!(without_elements("a<nav>x<nav/>y</nav>b", "nav"), "ab");
+    }
+}
@@ -0,0 +1,460 @@
+use crate::prelude::*;
+
+use crate::DB;
+
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use surrealdb::opt::PatchOp;
+use surrealdb::RecordId;
+use uuid::Uuid;
+
+use crate::lorebook::{self, Lorebook};
+use crate::png;
+use crate::speech::synthesis::Voice;
+use crate::wpp::{
+    chat::{ChatPromptContext, Example},
+    edit::{self, WppEdit},
+    header::HeaderItem,
+    item::WppItem,
+    parser,
+};
+
+pub mod card;
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CharacterSort {
+    Name,
+    Created,
+    Modified,
+}
+
+impl CharacterSort {
+    fn field(&self) -> &'static str {
+        match self {
+            CharacterSort::Name => "name",
+            CharacterSort::Created => "created_time",
+            CharacterSort::Modified => "modified_time",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LeanCharacter {
+    pub id: RecordId,
+    pub name: String,
+    pub avatar: Option<String>,
+    pub description: Option<String>,
+    pub created_time: DateTime<Utc>,
+    pub modified_time: DateTime<Utc>,
+}
+
+// Everything the character editor can change, with the definition as raw W++.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CharacterDetails {
+    pub name: String,
+    pub avatar: Option<String>,
+    pub description: Option<String>,
+    pub definition: Option<String>,
+    pub personality: Option<String>,
+    pub greeting: Option<String>,
+    #[serde(default)]
+    pub alternate_greetings: Vec<String>,
+    #[serde(default)]
+    pub example_dialogues: Vec<String>,
+    pub scenario: Option<String>,
+    // Imported lorebook, kept as the card's JSON.
+    pub character_book: Option<Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InsertableCharacter {
+    pub name: String,
+    pub avatar: Option<String>,
+    pub description: Option<String>,
+    pub definition: Option<WppItem>,
+    pub personality: Option<String>,
+    pub greeting: Option<String>,
+    #[serde(default)]
+    pub alternate_greetings: Vec<String>,
+    pub example_dialogues: Vec<String>,
+    pub scenario: Option<String>,
+    pub character_book: Option<Value>,
+    #[serde(default)]
+    pub lorebooks: Vec<RecordId>,
+    #[serde(default)]
+    pub voice: Option<Voice>,
+    pub created_time: DateTime<Utc>,
+    pub modified_time: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Character {
+    pub id: RecordId,
+    pub name: String,
+    pub avatar: Option<String>,
+    pub description: Option<String>,
+    pub definition: Option<WppItem>,
+    pub personality: Option<String>,
+    pub greeting: Option<String>,
+    #[serde(default)]
+    pub alternate_greetings: Vec<String>,
+    pub example_dialogues: Vec<String>,
+    pub scenario: Option<String>,
+    pub character_book: Option<Value>,
+    #[serde(default)]
+    pub lorebooks: Vec<RecordId>,
+    // What replies are read out with, the configured voice when none.
+    #[serde(default)]
+    pub voice: Option<Voice>,
+    pub created_time: DateTime<Utc>,
+    pub modified_time: DateTime<Utc>,
+}
+
+impl TryFrom<CharacterDetails> for InsertableCharacter {
+    type Error = AliceError;
+
+    fn try_from(details: CharacterDetails) -> Result<Self> {
+        if details.name.trim().is_empty() {
+            return Err(AliceError::InvalidCharacter("name can't be empty".into()));
+        }
+        let definition = match details.definition.as_deref().map(str::trim) {
+            Some(definition) if !definition.is_empty() => Some(
+                parser::parse(definition)
+                    .map_err(|e| AliceError::InvalidCharacter(e.to_string()))?,
+            ),
+            _ => None,
+        };
+        let time = Utc::now();
+        Ok(Self {
+            name: details.name.trim().to_string(),
+            avatar: details.avatar,
+            description: details.description,
+            definition,
+            personality: details.personality,
+            greeting: details.greeting,
+            alternate_greetings: details.alternate_greetings,
+            example_dialogues: details.example_dialogues,
+            scenario: details.scenario,
+            character_book: details.character_book,
+            lorebooks: Vec::new(),
+            voice: None,
+            created_time: time,
+            modified_time: time,
+        })
+    }
+}
+
+impl Character {
+    pub async fn new(details: CharacterDetails) -> Result<Self> {
+        db!()
+            .create("character")
+            .content(InsertableCharacter::try_from(details)?)
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "create".into(),
+                "character".into(),
+            ))
+    }
+
+    pub async fn find(id: String) -> Result<Self> {
+        db!()
+            .select(("character", &id))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "select".into(),
+                id.to_string(),
+            ))
+    }
+
+    pub async fn sorted_lean(
+        sort: CharacterSort,
+        descending: bool,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<LeanCharacter>> {
+        let query = format!(
+            "SELECT id, name, avatar, description, created_time, modified_time FROM character ORDER BY {} {} LIMIT $limit START $offset",
+            sort.field(),
+            if descending { "DESC" } else { "ASC" },
+        );
+        let result = db!()
+            .query(query)
+            .bind(("limit", limit))
+            .bind(("offset", offset))
+            .await?
+            .take(0)?;
+        Ok(result)
+    }
+
+    pub async fn with_details(self, details: CharacterDetails) -> Result<Self> {
+        let mut character = InsertableCharacter::try_from(details)?;
+        character.created_time = self.created_time;
+        character.lorebooks = self.lorebooks;
+        character.voice = self.voice;
+        db!()
+            .update(self.id)
+            .content(character)
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "character".into(),
+            ))
+    }
+
+    pub async fn with_lorebooks(self, lorebooks: Vec<String>) -> Result<Self> {
+        db!()
+            .update(self.id)
+            .patch(PatchOp::replace(
+                "/lorebooks",
+                lorebooks
+                    .into_iter()
+                    .map(|lorebook| RecordId::from_table_key("lorebook", lorebook))
+                    .collect::<Vec<_>>(),
+            ))
+            .patch(PatchOp::replace("/modified_time", Utc::now()))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "character".into(),
+            ))
+    }
+
+    pub async fn with_voice(self, voice: Option<Voice>) -> Result<Self> {
+        db!()
+            .update(self.id)
+            .patch(PatchOp::replace("/voice", voice))
+            .patch(PatchOp::replace("/modified_time", Utc::now()))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "character".into(),
+            ))
+    }
+
+    // Edits the definition in place, starting from an empty one named after the character if
+    // there is none yet.
+    pub async fn with_definition_edits(self, edits: &[WppEdit]) -> Result<Self> {
+        let definition = self
+            .definition
+            .clone()
+            .unwrap_or_else(|| WppItem::new("Character", &self.name));
+        let definition =
+            edit::apply_all(&definition, edits).map_err(AliceError::InvalidCharacter)?;
+        db!()
+            .update(self.id)
+            .patch(PatchOp::replace("/definition", definition))
+            .patch(PatchOp::replace("/modified_time", Utc::now()))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "character".into(),
+            ))
+    }
+
+    // Imports a character card, either a PNG which then also becomes the avatar or plain JSON.
+    // The card's character book, if it has entries, becomes a lorebook attached to the character.
+    pub async fn import(path: &str) -> Result<Self> {
+        let bytes = tokio::fs::read(path).await?;
+        let is_png = bytes.starts_with(&png::SIGNATURE);
+        let mut details = match is_png {
+            true => CharacterDetails::from(card::from_png(&bytes)?),
+            false => card::from_json(&String::from_utf8_lossy(&bytes))?.into(),
+        };
+        let book = details
+            .character_book
+            .as_ref()
+            .map(lorebook::import::from_character_book)
+            .transpose()?
+            .filter(|(_, _, entries)| !entries.is_empty());
+        if is_png {
+            let avatars = Self::avatars();
+            tokio::fs::create_dir_all(&avatars).await?;
+            let avatar = format!("{}/{}.png", avatars, Uuid::new_v4());
+            tokio::fs::write(&avatar, &bytes).await?;
+            details.avatar = Some(avatar);
+        }
+        let character = Self::new(details).await?;
+        let Some((name, settings, entries)) = book else {
+            return Ok(character);
+        };
+        let name = name.unwrap_or_else(|| character.name.clone());
+        let lorebook = Lorebook::imported(name, settings, entries).await?;
+        db!()
+            .update(character.id)
+            .patch(PatchOp::replace("/lorebooks", vec![lorebook.id]))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "character".into(),
+            ))
+    }
+
+    // Exports as a V2 card embedded in the avatar, or in a blank image without a PNG avatar.
+    pub async fn export_png(&self, path: &str) -> Result<()> {
+        let image = match &self.avatar {
+            Some(avatar) => match tokio::fs::read(avatar).await {
+                Ok(bytes) if bytes.starts_with(&png::SIGNATURE) => bytes,
+                _ => png::BLANK.to_vec(),
+            },
+            None => png::BLANK.to_vec(),
+        };
+        let card = card::CardV2::from(card::CardData::from(self.details()));
+        tokio::fs::write(path, card::to_png(&image, &card)?).await?;
+        Ok(())
+    }
+
+    pub async fn export_json(&self, path: &str) -> Result<()> {
+        let card = card::CardV2::from(card::CardData::from(self.details()));
+        tokio::fs::write(path, serde_json::to_string_pretty(&card)?).await?;
+        Ok(())
+    }
+
+    pub fn details(&self) -> CharacterDetails {
+        let character = self.clone();
+        CharacterDetails {
+            name: character.name,
+            avatar: character.avatar,
+            description: character.description,
+            definition: character.definition.map(|definition| definition.prompt()),
+            personality: character.personality,
+            greeting: character.greeting,
+            alternate_greetings: character.alternate_greetings,
+            example_dialogues: character.example_dialogues,
+            scenario: character.scenario,
+            character_book: character.character_book,
+        }
+    }
+
+    // How the character is described in a prompt's header: the definition when there is one,
+    // otherwise the card's description and personality, with `{{char}}` and `{{user}}` resolved.
+    pub fn header_item(&self, user: &str) -> HeaderItem {
+        let context = ChatPromptContext::new(vec![self.name.clone()], user.to_string());
+        HeaderItem::new(&self.name, &context.substitute(&self.sheet()))
+    }
+
+    // The greeting, or the alternate one at `alternate`, with `{{char}}` and `{{user}}` resolved.
+    pub fn greeting(&self, alternate: Option<usize>, user: &str) -> Result<Option<String>> {
+        let greeting = match alternate {
+            Some(index) => Some(
+                self.alternate_greetings
+                    .get(index)
+                    .ok_or(AliceError::IndexOutOfBounds(index))?,
+            ),
+            None => self.greeting.as_ref(),
+        };
+        let context = ChatPromptContext::new(vec![self.name.clone()], user.to_string());
+        Ok(greeting
+            .filter(|greeting| !greeting.trim().is_empty())
+            .map(|greeting| context.substitute(greeting)))
+    }
+
+    // The example dialogues, with `{{char}}` and `{{user}}` resolved.
+    pub fn examples(&self, user: &str) -> Vec<Example> {
+        let context = ChatPromptContext::new(vec![self.name.clone()], user.to_string());
+        self.example_dialogues
+            .iter()
+            .map(|example| Example::parse(&context.substitute(example)))
+            .collect()
+    }
+
+    // The W++ definition, or the description and personality when the character has none.
+    pub fn sheet(&self) -> String {
+        match &self.definition {
+            Some(definition) => definition.prompt(),
+            None => [&self.description, &self.personality]
+                .into_iter()
+                .flatten()
+                .filter(|text| !text.trim().is_empty())
+                .cloned()
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+
+    // Where imported avatars are kept.
+    fn avatars() -> String {
+        format!("{}/avatars", crate::data_dir())
+    }
+
+    // The avatar goes too when it was imported with a card, images picked from elsewhere stay.
+    pub async fn delete(self) -> Result<()> {
+        let deleted: Option<Self> = db!().delete(self.id).await?;
+        deleted.ok_or(AliceError::DatabaseOperation(
+            "delete".into(),
+            "character".into(),
+        ))?;
+        let avatars = Self::avatars();
+        let imported = self
+            .avatar
+            .filter(|avatar| Path::new(avatar).parent() == Some(Path::new(&avatars)));
+        if let Some(avatar) = imported {
+            match tokio::fs::remove_file(avatar).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_details_definition() {
+        let details = CharacterDetails {
+            name: "Nika Orchid".into(),
+            definition: Some(crate::wpp::CHARACTER_FULL_VALID.into()),
+            ..Default::default()
+        };
+        let character = InsertableCharacter::try_from(details).unwrap();
+        assert_eq!(character.definition.unwrap().name(), "Nika Orchid");
+    }
+
+    #[test]
+    fn test_details_invalid() {
+        let details = CharacterDetails {
+            name: "Nika Orchid".into(),
+            definition: Some("[Character(\"Nika\"".into()),
+            ..Default::default()
+        };
+        assert!(InsertableCharacter::try_from(details).is_err());
+        assert!(InsertableCharacter::try_from(CharacterDetails::default()).is_err());
+    }
+
+    #[test]
+    fn test_greeting() {
+        let time = Utc::now();
+        let character = Character {
+            id: RecordId::from_table_key("character", "nika"),
+            name: "Nika".into(),
+            avatar: None,
+            description: None,
+            definition: None,
+            personality: None,
+            greeting: Some("Welcome home, {{user}}.".into()),
+            alternate_greetings: vec!["{{char}} waves at {{user}}.".into()],
+            example_dialogues: vec![],
+            scenario: None,
+            character_book: None,
+            lorebooks: vec![],
+            voice: None,
+            created_time: time,
+            modified_time: time,
+        };
+        assert_eq!(
+            character.greeting(None, "Alex").unwrap().as_deref(),
+            Some("Welcome home, Alex.")
+        );
+        assert_eq!(
+            character.greeting(Some(0), "Alex").unwrap().as_deref(),
+            Some("Nika waves at Alex.")
+        );
+        assert!(character.greeting(Some(1), "Alex").is_err());
+    }
+}
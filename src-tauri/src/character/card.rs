@@ -0,0 +1,349 @@
+use crate::prelude::*;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    png,
+    wpp::{chat::EXAMPLE_SEPARATOR, parser},
+};
+
+use super::CharacterDetails;
+
+// Character cards as shared by TavernAI, SillyTavern and most character hubs. V1 cards are a
+// flat object, V2 and V3 wrap the same fields in `data` next to a `spec` marker.
+// https://github.com/malfoyslastname/character-card-spec-v2
+// https://github.com/kwaroran/character-card-spec-v3
+static SPEC_V2: &str = "chara_card_v2";
+static SPEC_V3: &str = "chara_card_v3";
+
+// PNG `tEXt` keywords, V3 writers keep a V2 `chara` chunk around for older readers.
+static KEYWORD_V2: &str = "chara";
+static KEYWORD_V3: &str = "ccv3";
+
+// Where exported cards keep the W++ definition, under `data.extensions`. Other apps only read the
+// prose description.
+static EXTENSION: &str = "alice";
+
+// Hubs export `null` for fields they leave empty, which should read as the default.
+fn nullable<'de, D, T>(deserializer: D) -> std::result::Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CardData {
+    #[serde(deserialize_with = "nullable")]
+    pub name: String,
+    #[serde(deserialize_with = "nullable")]
+    pub description: String,
+    #[serde(deserialize_with = "nullable")]
+    pub personality: String,
+    #[serde(deserialize_with = "nullable")]
+    pub scenario: String,
+    #[serde(deserialize_with = "nullable")]
+    pub first_mes: String,
+    #[serde(deserialize_with = "nullable")]
+    pub mes_example: String,
+    #[serde(deserialize_with = "nullable")]
+    pub creator_notes: String,
+    #[serde(deserialize_with = "nullable")]
+    pub system_prompt: String,
+    #[serde(deserialize_with = "nullable")]
+    pub post_history_instructions: String,
+    #[serde(deserialize_with = "nullable")]
+    pub alternate_greetings: Vec<String>,
+    pub character_book: Option<Value>,
+    #[serde(deserialize_with = "nullable")]
+    pub tags: Vec<String>,
+    #[serde(deserialize_with = "nullable")]
+    pub creator: String,
+    #[serde(deserialize_with = "nullable")]
+    pub character_version: String,
+    #[serde(deserialize_with = "nullable")]
+    pub extensions: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CardV2 {
+    pub spec: String,
+    pub spec_version: String,
+    pub data: CardData,
+}
+
+impl From<CardData> for CardV2 {
+    fn from(data: CardData) -> Self {
+        Self {
+            spec: SPEC_V2.into(),
+            spec_version: "2.0".into(),
+            data,
+        }
+    }
+}
+
+// Reads a V1, V2 or V3 card from its JSON.
+pub fn from_json(json: &str) -> Result<CardData> {
+    let card: Value = serde_json::from_str(json)?;
+    let data = match card.get("spec").and_then(Value::as_str) {
+        Some(spec) if spec == SPEC_V2 || spec == SPEC_V3 => card
+            .get("data")
+            .cloned()
+            .ok_or(AliceError::InvalidCharacter("card has no `data`".into()))?,
+        Some(spec) => {
+            return Err(AliceError::InvalidCharacter(format!(
+                "unsupported card spec `{}`",
+                spec
+            )))
+        }
+        None => card,
+    };
+    Ok(serde_json::from_value(data)?)
+}
+
+// Reads the card embedded in a PNG, preferring the V3 chunk when both are present.
+pub fn from_png(bytes: &[u8]) -> Result<CardData> {
+    let texts = png::texts(bytes)?;
+    let text = [KEYWORD_V3, KEYWORD_V2]
+        .iter()
+        .find_map(|keyword| texts.iter().find(|(key, _)| key == keyword))
+        .map(|(_, text)| text)
+        .ok_or(AliceError::InvalidCharacter(
+            "PNG has no character card".into(),
+        ))?;
+    let json = STANDARD
+        .decode(text.trim())
+        .map_err(|e| AliceError::InvalidCharacter(format!("invalid card encoding: {}", e)))?;
+    from_json(&String::from_utf8_lossy(&json))
+}
+
+// Embeds the card as a V2 `chara` chunk, replacing any card the image already carries.
+pub fn to_png(image: &[u8], card: &CardV2) -> Result<Vec<u8>> {
+    let text = STANDARD.encode(serde_json::to_string(card)?);
+    let bytes = png::with_text(image, KEYWORD_V2, &text)?;
+    // A stale V3 chunk would take precedence over ours when read back.
+    let chunks = png::read_chunks(&bytes)?
+        .into_iter()
+        .filter(|chunk| !matches!(chunk.as_text(), Some((keyword, _)) if keyword == KEYWORD_V3))
+        .collect::<Vec<_>>();
+    Ok(png::write_chunks(&chunks))
+}
+
+fn non_empty(text: String) -> Option<String> {
+    if text.trim().is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+// Only a description that is nothing but a W++ block becomes the definition, prose that merely
+// starts with one stays a description.
+fn is_wpp(text: &str) -> bool {
+    let text = text.trim();
+    text.starts_with('[') && text.ends_with(']') && parser::parse(text).is_ok()
+}
+
+// The definition a card exported from here carries in its extension data.
+fn extension_definition(card: &CardData) -> Option<String> {
+    card.extensions
+        .get(EXTENSION)?
+        .get("definition")?
+        .as_str()
+        .and_then(|definition| non_empty(definition.to_string()))
+}
+
+impl From<CardData> for CharacterDetails {
+    fn from(card: CardData) -> Self {
+        // Cards from elsewhere often have nothing but a W++ block as their description, with the
+        // prose, if any, in the creator notes.
+        let (description, definition) = match extension_definition(&card) {
+            Some(definition) if card.description.trim() == definition.trim() => {
+                (None, Some(definition))
+            }
+            Some(definition) => (non_empty(card.description), Some(definition)),
+            None if is_wpp(&card.description) => {
+                (non_empty(card.creator_notes), Some(card.description))
+            }
+            None => (non_empty(card.description), None),
+        };
+        Self {
+            name: card.name,
+            avatar: None,
+            description,
+            definition,
+            personality: non_empty(card.personality),
+            greeting: non_empty(card.first_mes),
+            alternate_greetings: card.alternate_greetings,
+            example_dialogues: card
+                .mes_example
+                .split(EXAMPLE_SEPARATOR)
+                .map(str::trim)
+                .filter(|example| !example.is_empty())
+                .map(str::to_string)
+                .collect(),
+            scenario: non_empty(card.scenario),
+            character_book: card.character_book,
+        }
+    }
+}
+
+// The prose description goes where every app looks for it, the definition into our extension.
+// A character without prose exports its definition as the description, so other apps still get
+// something to go on.
+impl From<CharacterDetails> for CardData {
+    fn from(details: CharacterDetails) -> Self {
+        let definition = details.definition.and_then(non_empty);
+        let description = details
+            .description
+            .and_then(non_empty)
+            .or(definition.clone())
+            .unwrap_or_default();
+        let mut extensions = Map::new();
+        if let Some(definition) = definition {
+            extensions.insert(EXTENSION.into(), json!({ "definition": definition }));
+        }
+        Self {
+            name: details.name,
+            description,
+            personality: details.personality.unwrap_or_default(),
+            scenario: details.scenario.unwrap_or_default(),
+            first_mes: details.greeting.unwrap_or_default(),
+            mes_example: details
+                .example_dialogues
+                .iter()
+                .map(|example| format!("{}\n{}", EXAMPLE_SEPARATOR, example))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            alternate_greetings: details.alternate_greetings,
+            character_book: details.character_book,
+            extensions,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_json_versions() {
+        let v1 = r#"{"name": "Nika", "description": "A shy cat girl.", "first_mes": "Hello.", "mes_example": null}"#;
+        let card = from_json(v1).unwrap();
+        assert_eq!(card.name, "Nika");
+        assert_eq!(card.first_mes, "Hello.");
+        assert_eq!(card.mes_example, "");
+
+        let v2 = r#"{"spec": "chara_card_v2", "spec_version": "2.0", "data": {"name": "Nika", "alternate_greetings": ["Hi.", "Hey."], "character_book": {"entries": []}}}"#;
+        let card = from_json(v2).unwrap();
+        assert_eq!(card.alternate_greetings, vec!["Hi.", "Hey."]);
+        assert!(card.character_book.is_some());
+
+        let v3 = r#"{"spec": "chara_card_v3", "spec_version": "3.0", "data": {"name": "Nika", "nickname": "Nik", "assets": []}}"#;
+        assert_eq!(from_json(v3).unwrap().name, "Nika");
+
+        assert!(from_json(r#"{"spec": "chara_card_v9", "data": {}}"#).is_err());
+    }
+
+    #[test]
+    fn test_details_mapping() {
+        let card = CardData {
+            name: "Nika Orchid".into(),
+            description: crate::wpp::CHARACTER_FULL_VALID.into(),
+            creator_notes: "A shy cat girl.".into(),
+            first_mes: "Hello, master.".into(),
+            mes_example: "<START>\n{{user}}: Hi.\n{{char}}: Hello.\n<START>\n{{char}}: Bye.".into(),
+            ..Default::default()
+        };
+        let details = CharacterDetails::from(card);
+        assert!(details.definition.is_some());
+        assert_eq!(details.description.as_deref(), Some("A shy cat girl."));
+        assert_eq!(
+            details.example_dialogues,
+            vec!["{{user}}: Hi.\n{{char}}: Hello.", "{{char}}: Bye."]
+        );
+
+        let prose = CardData {
+            name: "Nika".into(),
+            description: "[Nika is a cat girl] who lives in a tower.".into(),
+            ..Default::default()
+        };
+        let details = CharacterDetails::from(prose);
+        assert!(details.definition.is_none());
+        assert!(details.description.is_some());
+
+        // Without prose the definition stands in for the description, and reads back as one.
+        let wpp_only = CharacterDetails {
+            name: "Nika Orchid".into(),
+            definition: Some(crate::wpp::CHARACTER_FULL_VALID.into()),
+            ..Default::default()
+        };
+        let card = CardData::from(wpp_only.clone());
+        assert_eq!(card.description, crate::wpp::CHARACTER_FULL_VALID);
+        let details = CharacterDetails::from(card);
+        assert_eq!(details.description, None);
+        assert_eq!(details.definition, wpp_only.definition);
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        let details = CharacterDetails {
+            name: "Nika Orchid".into(),
+            description: Some("A shy cat girl.".into()),
+            definition: Some(crate::wpp::CHARACTER_FULL_VALID.into()),
+            personality: Some("Shy".into()),
+            greeting: Some("Hello, master.".into()),
+            alternate_greetings: vec!["Welcome back.".into()],
+            example_dialogues: vec!["{{char}}: Hi.".into(), "{{char}}: Bye.".into()],
+            scenario: Some("A tower.".into()),
+            character_book: Some(serde_json::json!({ "entries": [] })),
+            ..Default::default()
+        };
+        let card = CardV2::from(CardData::from(details.clone()));
+        let bytes = to_png(&png::BLANK, &card).unwrap();
+        let read = from_png(&bytes).unwrap();
+        assert_eq!(read, card.data);
+
+        assert_eq!(read.description, "A shy cat girl.");
+        assert_eq!(read.creator_notes, "");
+        assert!(read.extensions[EXTENSION]["definition"]
+            .as_str()
+            .unwrap()
+            .contains("Nika Orchid"));
+
+        let imported = CharacterDetails::from(read);
+        assert_eq!(imported.name, details.name);
+        assert_eq!(imported.description, details.description);
+        assert_eq!(imported.definition, details.definition);
+        assert_eq!(imported.personality, details.personality);
+        assert_eq!(imported.greeting, details.greeting);
+        assert_eq!(imported.alternate_greetings, details.alternate_greetings);
+        assert_eq!(imported.example_dialogues, details.example_dialogues);
+        assert_eq!(imported.scenario, details.scenario);
+        assert_eq!(imported.character_book, details.character_book);
+    }
+
+    #[test]
+    fn test_png_prefers_v3() {
+        let v3 = STANDARD.encode(r#"{"spec": "chara_card_v3", "data": {"name": "New"}}"#);
+        let v2 = STANDARD.encode(r#"{"spec": "chara_card_v2", "data": {"name": "Old"}}"#);
+        let bytes = png::with_text(&png::BLANK, KEYWORD_V2, &v2).unwrap();
+        let bytes = png::with_text(&bytes, KEYWORD_V3, &v3).unwrap();
+        assert_eq!(from_png(&bytes).unwrap().name, "New");
+
+        // Exporting drops the V3 chunk so the new card is the one read back.
+        let card = CardV2::from(CardData {
+            name: "Newer".into(),
+            ..Default::default()
+        });
+        let bytes = to_png(&bytes, &card).unwrap();
+        assert_eq!(from_png(&bytes).unwrap().name, "Newer");
+
+        assert!(from_png(&png::BLANK).is_err());
+    }
+}
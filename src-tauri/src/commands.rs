@@ -1,3 +1,13 @@
+pub mod attachment;
+pub mod browsing;
+pub mod character;
 pub mod connection;
 pub mod conversation;
+pub mod document;
+pub mod generation;
+pub mod imaging;
+pub mod lorebook;
 pub mod models;
+pub mod persona;
+pub mod preset;
+pub mod speech;
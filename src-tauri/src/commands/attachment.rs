@@ -0,0 +1,12 @@
+use crate::attachment::media::Attachment;
+
+// Stores a file to attach to a message, `new_message` takes the hash of what this returns.
+#[tauri::command]
+pub async fn import_attachment(path: String) -> Result<Attachment, String> {
+    Ok(Attachment::import(&path).await?)
+}
+
+#[tauri::command]
+pub async fn remove_orphaned_attachments() -> Result<usize, String> {
+    Ok(Attachment::remove_orphans().await?)
+}
@@ -0,0 +1,26 @@
+use crate::browsing::{Browser, BrowsingConfig, Page, SearchResult};
+
+static DEFAULT_LIMIT: usize = 10;
+
+#[tauri::command]
+pub async fn fetch_page(url: String) -> Result<Page, String> {
+    Ok(Browser::with_config().await?.fetch(&url).await?)
+}
+
+#[tauri::command]
+pub async fn web_search(query: String, limit: Option<usize>) -> Result<Vec<SearchResult>, String> {
+    Ok(Browser::with_config()
+        .await?
+        .search(&query, limit.unwrap_or(DEFAULT_LIMIT))
+        .await?)
+}
+
+#[tauri::command]
+pub async fn browsing_config() -> Result<BrowsingConfig, String> {
+    Ok(BrowsingConfig::get().await?)
+}
+
+#[tauri::command]
+pub async fn set_browsing_config(config: BrowsingConfig) -> Result<BrowsingConfig, String> {
+    Ok(config.set().await?)
+}
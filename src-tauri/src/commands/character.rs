@@ -0,0 +1,126 @@
+use crate::{
+    character::{Character, CharacterDetails, CharacterSort, LeanCharacter},
+    speech::synthesis::Voice,
+    wpp::{
+        edit::WppEdit,
+        error::WppDiagnostic,
+        format::{self, Format, RenderVariant},
+        item::AttributeChange,
+        parser,
+    },
+};
+
+#[tauri::command]
+pub async fn new_character(details: CharacterDetails) -> Result<Character, String> {
+    Ok(Character::new(details).await?)
+}
+
+#[tauri::command]
+pub async fn characters_sorted(
+    sort: CharacterSort,
+    descending: bool,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<LeanCharacter>, String> {
+    Ok(Character::sorted_lean(sort, descending, limit, offset).await?)
+}
+
+#[tauri::command]
+pub async fn find_character(id: String) -> Result<Character, String> {
+    Ok(Character::find(id).await?)
+}
+
+#[tauri::command]
+pub async fn update_character(id: String, details: CharacterDetails) -> Result<Character, String> {
+    let character = Character::find(id).await?;
+    Ok(character.with_details(details).await?)
+}
+
+#[tauri::command]
+pub async fn set_character_lorebooks(
+    id: String,
+    lorebooks: Vec<String>,
+) -> Result<Character, String> {
+    let character = Character::find(id).await?;
+    Ok(character.with_lorebooks(lorebooks).await?)
+}
+
+#[tauri::command]
+pub async fn set_character_voice(id: String, voice: Option<Voice>) -> Result<Character, String> {
+    let character = Character::find(id).await?;
+    Ok(character.with_voice(voice).await?)
+}
+
+#[tauri::command]
+pub async fn delete_character(id: String) -> Result<(), String> {
+    let character = Character::find(id).await?;
+    Ok(character.delete().await?)
+}
+
+#[tauri::command]
+pub async fn import_character_card(path: String) -> Result<Character, String> {
+    Ok(Character::import(&path).await?)
+}
+
+#[tauri::command]
+pub async fn export_character_png(id: String, path: String) -> Result<(), String> {
+    let character = Character::find(id).await?;
+    Ok(character.export_png(&path).await?)
+}
+
+#[tauri::command]
+pub async fn export_character_json(id: String, path: String) -> Result<(), String> {
+    let character = Character::find(id).await?;
+    Ok(character.export_json(&path).await?)
+}
+
+// Checks a W++ definition while it's being edited, without saving anything.
+#[tauri::command]
+pub async fn validate_definition(definition: String) -> Result<Option<WppDiagnostic>, String> {
+    Ok(parser::parse(&definition)
+        .err()
+        .map(|error| error.diagnostic(&definition)))
+}
+
+// Rewrites a definition in another format, e.g. W++ as PList for models that follow it better.
+#[tauri::command]
+pub async fn convert_definition(
+    definition: String,
+    from: Format,
+    to: Format,
+) -> Result<String, String> {
+    format::convert(&definition, from, to).map_err(|e| e.to_string())
+}
+
+// Every rendering of a W++ definition with its estimated token cost.
+#[tauri::command]
+pub async fn definition_variants(
+    definition: String,
+    attributes: Option<Vec<String>>,
+) -> Result<Vec<RenderVariant>, String> {
+    let item = parser::parse(&definition).map_err(|e| e.to_string())?;
+    Ok(format::variants(&item, attributes))
+}
+
+#[tauri::command]
+pub async fn edit_character_definition(
+    id: String,
+    edits: Vec<WppEdit>,
+) -> Result<Character, String> {
+    let character = Character::find(id).await?;
+    Ok(character.with_definition_edits(&edits).await?)
+}
+
+// What it takes to turn the first character's definition into the second's.
+#[tauri::command]
+pub async fn diff_character_definitions(
+    id: String,
+    other_id: String,
+) -> Result<Vec<AttributeChange>, String> {
+    let character = Character::find(id).await?;
+    let other = Character::find(other_id).await?;
+    match (character.definition, other.definition) {
+        (Some(definition), Some(other)) => Ok(definition.diff(&other)),
+        _ => Err("Both characters need a definition to compare".into()),
+    }
+}
@@ -1,4 +1,12 @@
-use crate::conversation::{Conversation, LeanConversation};
+use crate::{
+    attachment::media::Attachment,
+    conversation::{
+        authors_note::AuthorsNote, summary::Summary, turn::TurnOrder, Conversation,
+        LeanConversation,
+    },
+    memory::{chunk::MemorySettings, Memory},
+    speech::synthesis,
+};
 
 #[tauri::command]
 pub async fn new_conversation() -> Result<Conversation, String> {
@@ -29,14 +37,21 @@ pub async fn new_message(
     id: String,
     role: String,
     message: String,
+    attachments: Option<Vec<String>>,
 ) -> Result<Conversation, String> {
+    let mut stored = Vec::new();
+    for hash in attachments.unwrap_or_default() {
+        stored.push(Attachment::stored(&hash).await?);
+    }
     let conv = Conversation::find(id).await?;
-    Ok(conv.with_message(role, message).await?)
+    Ok(conv.with_attached_message(role, message, stored).await?)
 }
 
 #[tauri::command]
 pub async fn delete_message(id: String, index: usize) -> Result<Conversation, String> {
     let conv = Conversation::find(id).await?;
+    // A clip that can't be deleted is left for the cache limit to clean up.
+    let _ = synthesis::forget_message(&conv, index).await;
     Ok(conv.without_message(index).await?)
 }
 
@@ -47,5 +62,120 @@ pub async fn with_replaced_message(
     content: String,
 ) -> Result<Conversation, String> {
     let conv = Conversation::find(id).await?;
+    // The clips reading out the old content won't be asked for again.
+    let _ = synthesis::forget_message(&conv, index).await;
     Ok(conv.with_replaced_message(index, content).await?)
 }
+
+#[tauri::command]
+pub async fn set_conversation_preset(
+    id: String,
+    preset: Option<String>,
+) -> Result<Conversation, String> {
+    let conv = Conversation::find(id).await?;
+    Ok(conv.with_preset(preset).await?)
+}
+
+#[tauri::command]
+pub async fn set_conversation_persona(
+    id: String,
+    persona: Option<String>,
+) -> Result<Conversation, String> {
+    let conv = Conversation::find(id).await?;
+    Ok(conv.with_persona(persona).await?)
+}
+
+#[tauri::command]
+pub async fn set_conversation_authors_note(
+    id: String,
+    authors_note: Option<AuthorsNote>,
+) -> Result<Conversation, String> {
+    let conv = Conversation::find(id).await?;
+    Ok(conv.with_authors_note(authors_note).await?)
+}
+
+#[tauri::command]
+pub async fn conversation_summary(id: String) -> Result<Option<Summary>, String> {
+    let conv = Conversation::find(id).await?;
+    Ok(conv.summary)
+}
+
+// Replaces the summary's text, keeping the messages it covers. Without a summary yet, the text
+// becomes one that the next summary builds on.
+#[tauri::command]
+pub async fn set_conversation_summary(id: String, content: String) -> Result<Conversation, String> {
+    let conv = Conversation::find(id).await?;
+    let summary = match &conv.summary {
+        _ if content.trim().is_empty() => None,
+        Some(summary) => Some(summary.edited(content)),
+        None => Some(Summary::written(content)),
+    };
+    Ok(conv.with_summary(summary).await?)
+}
+
+#[tauri::command]
+pub async fn set_conversation_memory(
+    id: String,
+    memory: MemorySettings,
+) -> Result<Conversation, String> {
+    let conv = Conversation::find(id).await?;
+    Ok(conv.with_memory_settings(memory).await?)
+}
+
+// Deletes the conversation's memories, they're rebuilt from its messages on the next reply.
+#[tauri::command]
+pub async fn forget_conversation_memories(id: String) -> Result<Conversation, String> {
+    let conv = Conversation::find(id).await?;
+    Ok(Memory::forget(conv).await?)
+}
+
+#[tauri::command]
+pub async fn set_conversation_lorebooks(
+    id: String,
+    lorebooks: Vec<String>,
+) -> Result<Conversation, String> {
+    let conv = Conversation::find(id).await?;
+    Ok(conv.with_lorebooks(lorebooks).await?)
+}
+
+#[tauri::command]
+pub async fn set_conversation_participants(
+    id: String,
+    characters: Vec<String>,
+) -> Result<Conversation, String> {
+    let conv = Conversation::find(id).await?;
+    Ok(conv.with_participants(characters).await?)
+}
+
+// Swaps the opening greeting for the first character's alternate greeting at `alternate`, or back
+// to its main one, as long as nothing else has been said yet.
+#[tauri::command]
+pub async fn set_conversation_greeting(
+    id: String,
+    alternate: Option<usize>,
+) -> Result<Conversation, String> {
+    let conv = Conversation::find(id).await?;
+    Ok(conv.with_greeting(alternate).await?)
+}
+
+#[tauri::command]
+pub async fn set_participant_settings(
+    id: String,
+    character: String,
+    talkativeness: f32,
+    muted: bool,
+) -> Result<Conversation, String> {
+    let conv = Conversation::find(id).await?;
+    Ok(conv
+        .with_participant_settings(character, talkativeness, muted)
+        .await?)
+}
+
+#[tauri::command]
+pub async fn set_conversation_turn_order(
+    id: String,
+    turn_order: TurnOrder,
+) -> Result<Conversation, String> {
+    let conv = Conversation::find(id).await?;
+    Ok(conv.with_turn_order(turn_order).await?)
+}
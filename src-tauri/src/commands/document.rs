@@ -0,0 +1,29 @@
+use crate::document::{Document, LeanDocument};
+
+// Imports a text, Markdown, HTML or PDF file into the conversation's knowledge base, or into the
+// one every conversation shares without a conversation.
+#[tauri::command]
+pub async fn import_document(
+    path: String,
+    conversation: Option<String>,
+) -> Result<Document, String> {
+    Ok(Document::import(&path, conversation).await?)
+}
+
+#[tauri::command]
+pub async fn documents_name_sorted(
+    conversation: Option<String>,
+) -> Result<Vec<LeanDocument>, String> {
+    Ok(Document::name_sorted_lean(conversation).await?)
+}
+
+#[tauri::command]
+pub async fn find_document(id: String) -> Result<Document, String> {
+    Ok(Document::find(id).await?)
+}
+
+#[tauri::command]
+pub async fn delete_document(id: String) -> Result<(), String> {
+    let document = Document::find(id).await?;
+    Ok(document.delete().await?)
+}
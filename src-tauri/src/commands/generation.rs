@@ -0,0 +1,40 @@
+use crate::{
+    character::Character,
+    conversation::Conversation,
+    generation::{self, GenerationMode, PromptPreview},
+    tools::{ToolDescription, ToolRegistry},
+};
+
+#[tauri::command]
+pub async fn generate(id: String, speaker: Option<String>) -> Result<Conversation, String> {
+    Ok(generation::generate(id, speaker).await?)
+}
+
+#[tauri::command]
+pub async fn continue_generation(id: String) -> Result<Conversation, String> {
+    Ok(generation::continue_last(id).await?)
+}
+
+#[tauri::command]
+pub async fn impersonate(id: String) -> Result<String, String> {
+    Ok(generation::impersonate(id).await?)
+}
+
+#[tauri::command]
+pub async fn preview_prompt(
+    id: String,
+    mode: GenerationMode,
+    speaker: Option<String>,
+) -> Result<PromptPreview, String> {
+    Ok(generation::preview(id, mode, speaker).await?)
+}
+
+#[tauri::command]
+pub async fn next_speaker(id: String) -> Result<Option<Character>, String> {
+    Ok(generation::next_speaker(id).await?)
+}
+
+#[tauri::command]
+pub async fn list_tools() -> Result<Vec<ToolDescription>, String> {
+    Ok(ToolRegistry::enabled().await?.describe())
+}
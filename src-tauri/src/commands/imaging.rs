@@ -0,0 +1,38 @@
+use crate::{
+    conversation::Conversation,
+    imaging::{self, prompt::ImageSubject, ImageGeneratorConfig},
+};
+
+// The prompt `generate_image` would use, for the user to edit before generating.
+#[tauri::command]
+pub async fn write_image_prompt(
+    id: String,
+    index: usize,
+    subject: ImageSubject,
+) -> Result<String, String> {
+    let conversation = Conversation::find(id).await?;
+    Ok(imaging::write_prompt(&conversation, index, subject).await?)
+}
+
+// Attaches the image to the message at `index`, returning the updated conversation.
+#[tauri::command]
+pub async fn generate_image(
+    id: String,
+    index: usize,
+    subject: ImageSubject,
+    prompt: Option<String>,
+) -> Result<Conversation, String> {
+    Ok(imaging::generate_image(id, index, subject, prompt).await?)
+}
+
+#[tauri::command]
+pub async fn image_generator_config() -> Result<ImageGeneratorConfig, String> {
+    Ok(ImageGeneratorConfig::get().await?)
+}
+
+#[tauri::command]
+pub async fn set_image_generator_config(
+    config: ImageGeneratorConfig,
+) -> Result<ImageGeneratorConfig, String> {
+    Ok(config.set().await?)
+}
@@ -0,0 +1,46 @@
+use crate::lorebook::{
+    scan::{LoreEntry, LoreSettings},
+    LeanLorebook, Lorebook,
+};
+
+#[tauri::command]
+pub async fn new_lorebook(name: String) -> Result<Lorebook, String> {
+    Ok(Lorebook::new(name).await?)
+}
+
+#[tauri::command]
+pub async fn lorebooks_name_sorted(
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<LeanLorebook>, String> {
+    Ok(Lorebook::name_sorted_lean(limit, offset).await?)
+}
+
+#[tauri::command]
+pub async fn find_lorebook(id: String) -> Result<Lorebook, String> {
+    Ok(Lorebook::find(id).await?)
+}
+
+#[tauri::command]
+pub async fn set_lorebook_name(id: String, name: String) -> Result<Lorebook, String> {
+    let lorebook = Lorebook::find(id).await?;
+    Ok(lorebook.with_name(name).await?)
+}
+
+#[tauri::command]
+pub async fn set_lorebook_settings(id: String, settings: LoreSettings) -> Result<Lorebook, String> {
+    let lorebook = Lorebook::find(id).await?;
+    Ok(lorebook.with_settings(settings).await?)
+}
+
+#[tauri::command]
+pub async fn set_lorebook_entries(id: String, entries: Vec<LoreEntry>) -> Result<Lorebook, String> {
+    let lorebook = Lorebook::find(id).await?;
+    Ok(lorebook.with_entries(entries).await?)
+}
+
+#[tauri::command]
+pub async fn delete_lorebook(id: String) -> Result<(), String> {
+    let lorebook = Lorebook::find(id).await?;
+    Ok(lorebook.delete().await?)
+}
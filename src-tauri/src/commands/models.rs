@@ -1,4 +1,10 @@
-use crate::{models::model::Model, API_MANAGER, APP};
+use crate::{
+    models::{
+        model::{Engine, Model},
+        parameters::{EngineParameters, ParameterError},
+    },
+    API_MANAGER, APP,
+};
 use tauri::Emitter;
 
 #[tauri::command]
@@ -47,3 +53,15 @@ pub async fn unload_model() -> Result<(), String> {
         Err(e) => Err(format!("Error command: {}", e)),
     }
 }
+
+#[tauri::command]
+pub async fn validate_parameters(
+    parameters: EngineParameters,
+    engine: Option<Engine>,
+) -> Result<Vec<ParameterError>, String> {
+    let mut errors = parameters.validate().err().unwrap_or_default();
+    if let Some(engine) = engine {
+        errors.extend(parameters.for_engine(engine).1);
+    }
+    Ok(errors)
+}
@@ -0,0 +1,38 @@
+use crate::persona::{LeanPersona, Persona, PersonaDetails};
+
+#[tauri::command]
+pub async fn new_persona(details: PersonaDetails) -> Result<Persona, String> {
+    Ok(Persona::new(details).await?)
+}
+
+#[tauri::command]
+pub async fn personas_name_sorted(limit: usize, offset: usize) -> Result<Vec<LeanPersona>, String> {
+    Ok(Persona::name_sorted_lean(limit, offset).await?)
+}
+
+#[tauri::command]
+pub async fn find_persona(id: String) -> Result<Persona, String> {
+    Ok(Persona::find(id).await?)
+}
+
+#[tauri::command]
+pub async fn update_persona(id: String, details: PersonaDetails) -> Result<Persona, String> {
+    let persona = Persona::find(id).await?;
+    Ok(persona.with_details(details).await?)
+}
+
+#[tauri::command]
+pub async fn delete_persona(id: String) -> Result<(), String> {
+    let persona = Persona::find(id).await?;
+    Ok(persona.delete().await?)
+}
+
+#[tauri::command]
+pub async fn default_persona() -> Result<Option<Persona>, String> {
+    Ok(Persona::default_persona().await?)
+}
+
+#[tauri::command]
+pub async fn set_default_persona(persona: Option<String>) -> Result<(), String> {
+    Ok(Persona::set_default(persona).await?)
+}
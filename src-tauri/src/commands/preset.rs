@@ -0,0 +1,55 @@
+use crate::{
+    models::parameters::EngineParameters,
+    preset::{LeanPreset, Preset},
+};
+
+#[tauri::command]
+pub async fn new_preset(name: String) -> Result<Preset, String> {
+    Ok(Preset::new(name, EngineParameters::default()).await?)
+}
+
+#[tauri::command]
+pub async fn import_preset(name: String, json: String) -> Result<Preset, String> {
+    Ok(Preset::import(name, &json).await?)
+}
+
+#[tauri::command]
+pub async fn presets_name_sorted(limit: usize, offset: usize) -> Result<Vec<LeanPreset>, String> {
+    Ok(Preset::name_sorted_lean(limit, offset).await?)
+}
+
+#[tauri::command]
+pub async fn find_preset(id: String) -> Result<Preset, String> {
+    Ok(Preset::find(id).await?)
+}
+
+#[tauri::command]
+pub async fn set_preset_name(id: String, name: String) -> Result<Preset, String> {
+    let preset = Preset::find(id).await?;
+    Ok(preset.with_name(name).await?)
+}
+
+#[tauri::command]
+pub async fn set_preset_parameters(
+    id: String,
+    parameters: EngineParameters,
+) -> Result<Preset, String> {
+    let preset = Preset::find(id).await?;
+    Ok(preset.with_parameters(parameters).await?)
+}
+
+#[tauri::command]
+pub async fn delete_preset(id: String) -> Result<(), String> {
+    let preset = Preset::find(id).await?;
+    Ok(preset.delete().await?)
+}
+
+#[tauri::command]
+pub async fn model_preset(model: String) -> Result<Option<Preset>, String> {
+    Ok(Preset::for_model(&model).await?)
+}
+
+#[tauri::command]
+pub async fn set_model_preset(model: String, preset: Option<String>) -> Result<(), String> {
+    Ok(Preset::set_for_model(&model, preset).await?)
+}
@@ -0,0 +1,44 @@
+use crate::speech::{
+    synthesis::{self, SynthesizerConfig},
+    transcription::{self, TranscriberConfig, Transcription},
+};
+
+// `audio` is the whole recording, in whatever format the frontend recorded it in.
+#[tauri::command]
+pub async fn transcribe(
+    audio: Vec<u8>,
+    mime: String,
+    language: Option<String>,
+) -> Result<Transcription, String> {
+    Ok(transcription::transcribe(audio, &mime, language).await?)
+}
+
+#[tauri::command]
+pub async fn transcriber_config() -> Result<TranscriberConfig, String> {
+    Ok(TranscriberConfig::get().await?)
+}
+
+#[tauri::command]
+pub async fn set_transcriber_config(
+    config: TranscriberConfig,
+) -> Result<TranscriberConfig, String> {
+    Ok(config.set().await?)
+}
+
+// Paths of the clips reading the message out, one per sentence, to be played in order.
+#[tauri::command]
+pub async fn speak_message(id: String, index: usize) -> Result<Vec<String>, String> {
+    Ok(synthesis::speak_message(id, index).await?)
+}
+
+#[tauri::command]
+pub async fn synthesizer_config() -> Result<SynthesizerConfig, String> {
+    Ok(SynthesizerConfig::get().await?)
+}
+
+#[tauri::command]
+pub async fn set_synthesizer_config(
+    config: SynthesizerConfig,
+) -> Result<SynthesizerConfig, String> {
+    Ok(config.set().await?)
+}
@@ -1,4 +1,4 @@
-use crate::api::ullm::UllmApi;
+use crate::api::{ollama::OllamaApi, openai::OpenAiApi, ullm::UllmApi};
 use crate::prelude::*;
 use crate::DB;
 
@@ -23,9 +23,45 @@ impl Default for UllmConfig {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAiConfig {
+    pub url: String,
+    pub api_key: Option<String>,
+    #[serde(default)]
+    pub embedding_model: Option<String>,
+}
+
+impl Default for OpenAiConfig {
+    fn default() -> Self {
+        Self {
+            url: "http://localhost:8080/v1".into(),
+            api_key: None,
+            embedding_model: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaConfig {
+    pub url: String,
+    #[serde(default)]
+    pub embedding_model: Option<String>,
+}
+
+impl Default for OllamaConfig {
+    fn default() -> Self {
+        Self {
+            url: "http://localhost:11434".into(),
+            embedding_model: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SubConfig {
     UllmDefault(UllmConfig),
+    OpenAi(OpenAiConfig),
+    Ollama(OllamaConfig),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,6 +85,12 @@ impl ApiConfig {
     pub fn into_api(self) -> Result<Arc<Mutex<dyn Api>>> {
         match self.subconfig {
             SubConfig::UllmDefault(config) => Ok(UllmApi::new(config.url)?),
+            SubConfig::OpenAi(config) => Ok(OpenAiApi::new(
+                config.url,
+                config.api_key,
+                config.embedding_model,
+            )?),
+            SubConfig::Ollama(config) => Ok(OllamaApi::new(config.url, config.embedding_model)?),
         }
     }
 }
@@ -2,12 +2,53 @@ use crate::prelude::*;
 
 use crate::DB;
 
+use std::collections::HashMap;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use surrealdb::opt::PatchOp;
 use surrealdb::RecordId;
 
-use crate::models::message::Message;
+use crate::attachment::media::Attachment;
+use crate::character::Character;
+use crate::generation;
+use crate::memory::chunk::MemorySettings;
+use crate::models::message::{Citation, Message};
+use crate::persona::Persona;
+
+pub mod authors_note;
+pub mod summary;
+pub mod turn;
+
+use authors_note::AuthorsNote;
+use summary::Summary;
+use turn::TurnOrder;
+
+fn talkativeness() -> f32 {
+    0.5
+}
+
+// A character taking part in the conversation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Participant {
+    pub character: RecordId,
+    // How often the character is picked by the random turn orders, relative to the others.
+    #[serde(default = "talkativeness")]
+    pub talkativeness: f32,
+    // Muted characters are never picked to speak next, but stay in the prompt.
+    #[serde(default)]
+    pub muted: bool,
+}
+
+impl Participant {
+    pub fn new(character: String) -> Self {
+        Self {
+            character: RecordId::from_table_key("character", character),
+            talkativeness: talkativeness(),
+            muted: false,
+        }
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LeanConversation {
@@ -17,12 +58,41 @@ pub struct LeanConversation {
     pub modified_time: DateTime<Utc>,
 }
 
+// A conversation with only the messages that matched a search.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConversationMatch {
+    pub id: RecordId,
+    pub name: String,
+    pub messages: Vec<Message>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InsertableConversation {
     pub name: String,
     pub start_time: DateTime<Utc>,
     pub modified_time: DateTime<Utc>,
     pub messages: Vec<Message>,
+    #[serde(default)]
+    pub preset: Option<RecordId>,
+    #[serde(default)]
+    pub lorebooks: Vec<RecordId>,
+    // The turn each lorebook entry last triggered on, by entry id.
+    #[serde(default)]
+    pub lore_activations: HashMap<String, usize>,
+    #[serde(default)]
+    pub participants: Vec<Participant>,
+    #[serde(default)]
+    pub turn_order: TurnOrder,
+    // Overrides the default persona.
+    #[serde(default)]
+    pub persona: Option<RecordId>,
+    #[serde(default)]
+    pub authors_note: Option<AuthorsNote>,
+    #[serde(default)]
+    pub memory: MemorySettings,
+    // The latest summary of the messages too old to fit in the prompt.
+    #[serde(default)]
+    pub summary: Option<Summary>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -32,6 +102,27 @@ pub struct Conversation {
     pub start_time: DateTime<Utc>,
     pub modified_time: DateTime<Utc>,
     pub messages: Vec<Message>,
+    #[serde(default)]
+    pub preset: Option<RecordId>,
+    #[serde(default)]
+    pub lorebooks: Vec<RecordId>,
+    // The turn each lorebook entry last triggered on, by entry id.
+    #[serde(default)]
+    pub lore_activations: HashMap<String, usize>,
+    #[serde(default)]
+    pub participants: Vec<Participant>,
+    #[serde(default)]
+    pub turn_order: TurnOrder,
+    // Overrides the default persona.
+    #[serde(default)]
+    pub persona: Option<RecordId>,
+    #[serde(default)]
+    pub authors_note: Option<AuthorsNote>,
+    #[serde(default)]
+    pub memory: MemorySettings,
+    // The latest summary of the messages too old to fit in the prompt.
+    #[serde(default)]
+    pub summary: Option<Summary>,
 }
 
 impl Default for InsertableConversation {
@@ -42,6 +133,15 @@ impl Default for InsertableConversation {
             start_time: time,
             modified_time: time,
             messages: Vec::new(),
+            preset: None,
+            lorebooks: Vec::new(),
+            lore_activations: HashMap::new(),
+            participants: Vec::new(),
+            turn_order: TurnOrder::default(),
+            persona: None,
+            authors_note: None,
+            memory: MemorySettings::default(),
+            summary: None,
         }
     }
 }
@@ -80,6 +180,27 @@ impl Conversation {
         Ok(result)
     }
 
+    // Conversations other than `exclude` with messages containing `query`.
+    pub async fn search_messages(
+        query: String,
+        limit: usize,
+        exclude: Option<String>,
+    ) -> Result<Vec<ConversationMatch>> {
+        let result = db!()
+            .query(
+                "SELECT id, name, modified_time, messages[WHERE string::contains(string::lowercase(content), $query)] AS messages FROM conversation WHERE id != $exclude AND messages[WHERE string::contains(string::lowercase(content), $query)] != [] ORDER BY modified_time DESC LIMIT $limit",
+            )
+            .bind(("query", query.to_lowercase()))
+            .bind((
+                "exclude",
+                exclude.map(|exclude| RecordId::from_table_key("conversation", exclude)),
+            ))
+            .bind(("limit", limit))
+            .await?
+            .take(0)?;
+        Ok(result)
+    }
+
     pub async fn with_name(self, name: String) -> Result<Self> {
         let db = db!();
         db.update(self.id)
@@ -92,10 +213,114 @@ impl Conversation {
             ))
     }
 
+    pub async fn with_preset(self, preset: Option<String>) -> Result<Self> {
+        let db = db!();
+        db.update(self.id)
+            .patch(PatchOp::replace(
+                "/preset",
+                preset.map(|preset| RecordId::from_table_key("preset", preset)),
+            ))
+            .patch(PatchOp::replace("/modified_time", Utc::now()))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "conversation".into(),
+            ))
+    }
+
+    pub async fn with_persona(self, persona: Option<String>) -> Result<Self> {
+        let db = db!();
+        db.update(self.id)
+            .patch(PatchOp::replace(
+                "/persona",
+                persona.map(|persona| RecordId::from_table_key("persona", persona)),
+            ))
+            .patch(PatchOp::replace("/modified_time", Utc::now()))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "conversation".into(),
+            ))
+    }
+
+    pub async fn with_authors_note(self, authors_note: Option<AuthorsNote>) -> Result<Self> {
+        let db = db!();
+        db.update(self.id)
+            .patch(PatchOp::replace("/authors_note", authors_note))
+            .patch(PatchOp::replace("/modified_time", Utc::now()))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "conversation".into(),
+            ))
+    }
+
+    pub async fn with_summary(self, summary: Option<Summary>) -> Result<Self> {
+        let db = db!();
+        db.update(self.id)
+            .patch(PatchOp::replace("/summary", summary))
+            .patch(PatchOp::replace("/modified_time", Utc::now()))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "conversation".into(),
+            ))
+    }
+
+    pub async fn with_memory_settings(self, memory: MemorySettings) -> Result<Self> {
+        let db = db!();
+        db.update(self.id)
+            .patch(PatchOp::replace("/memory", memory))
+            .patch(PatchOp::replace("/modified_time", Utc::now()))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "conversation".into(),
+            ))
+    }
+
+    pub async fn with_lorebooks(self, lorebooks: Vec<String>) -> Result<Self> {
+        let db = db!();
+        db.update(self.id)
+            .patch(PatchOp::replace(
+                "/lorebooks",
+                lorebooks
+                    .into_iter()
+                    .map(|lorebook| RecordId::from_table_key("lorebook", lorebook))
+                    .collect::<Vec<_>>(),
+            ))
+            .patch(PatchOp::replace("/modified_time", Utc::now()))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "conversation".into(),
+            ))
+    }
+
+    pub async fn with_lore_activations(
+        self,
+        lore_activations: HashMap<String, usize>,
+    ) -> Result<Self> {
+        let db = db!();
+        db.update(self.id)
+            .patch(PatchOp::replace("/lore_activations", lore_activations))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "conversation".into(),
+            ))
+    }
+
+    // The summary's positions follow the messages, so it keeps covering the same ones.
     pub async fn without_message(self, index: usize) -> Result<Self> {
+        let summary = self
+            .summary
+            .as_ref()
+            .map(|summary| summary.without_message(index));
         let db = db!();
         db.update(self.id)
             .patch(PatchOp::remove(&format!("/messages/{}", index)))
+            .patch(PatchOp::replace("/summary", summary))
             .patch(PatchOp::replace("/modified_time", Utc::now()))
             .await?
             .ok_or(AliceError::DatabaseOperation(
@@ -105,19 +330,26 @@ impl Conversation {
     }
 
     pub async fn with_replaced_message(self, index: usize, content: String) -> Result<Self> {
+        let original = self
+            .messages
+            .get(index)
+            .ok_or(AliceError::IndexOutOfBounds(index))?;
+        let summary = self
+            .summary
+            .as_ref()
+            .map(|summary| summary.with_replaced_message(index));
         let db = db!();
         db.update(self.id)
+            .patch(PatchOp::replace("/summary", summary))
             .patch(PatchOp::replace(
                 &format!("/messages/{}", index),
                 Message {
                     timestamp: Utc::now(),
-                    role: self
-                        .messages
-                        .get(index)
-                        .ok_or(AliceError::IndexOutOfBounds(index))?
-                        .role
-                        .clone(),
+                    role: original.role.clone(),
                     content,
+                    author: original.author.clone(),
+                    citations: original.citations.clone(),
+                    attachments: original.attachments.clone(),
                 },
             ))
             .patch(PatchOp::replace("/modified_time", Utc::now()))
@@ -129,6 +361,15 @@ impl Conversation {
     }
 
     pub async fn with_message(self, role: String, content: String) -> Result<Self> {
+        self.with_attached_message(role, content, Vec::new()).await
+    }
+
+    pub async fn with_attached_message(
+        self,
+        role: String,
+        content: String,
+        attachments: Vec<Attachment>,
+    ) -> Result<Self> {
         let db = db!();
         db.update(self.id)
             .patch(PatchOp::add(
@@ -137,6 +378,81 @@ impl Conversation {
                     timestamp: Utc::now(),
                     role,
                     content,
+                    author: None,
+                    citations: Vec::new(),
+                    attachments,
+                },
+            ))
+            .patch(PatchOp::replace("/modified_time", Utc::now()))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "conversation".into(),
+            ))
+    }
+
+    // Sets which document passages a message cites, without touching its content.
+    pub async fn with_citations(self, index: usize, citations: Vec<Citation>) -> Result<Self> {
+        if index >= self.messages.len() {
+            return Err(AliceError::IndexOutOfBounds(index));
+        }
+        let db = db!();
+        db.update(self.id)
+            .patch(PatchOp::replace(
+                &format!("/messages/{}/citations", index),
+                citations,
+            ))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "conversation".into(),
+            ))
+    }
+
+    // Adds a file to a message that's already been sent, like an image generated for it.
+    // Appended to the message's attachments as they are in the database, which may have changed
+    // since `self` was read. Messages without attachments don't have the field, so there it's
+    // added with just this one.
+    pub async fn with_attachment(self, index: usize, attachment: Attachment) -> Result<Self> {
+        let message = self
+            .messages
+            .get(index)
+            .ok_or(AliceError::IndexOutOfBounds(index))?;
+        let patch = match message.attachments.is_empty() {
+            true => PatchOp::add(
+                &format!("/messages/{}/attachments", index),
+                vec![attachment],
+            ),
+            false => PatchOp::add(&format!("/messages/{}/attachments/-", index), attachment),
+        };
+        let db = db!();
+        db.update(self.id)
+            .patch(patch)
+            .patch(PatchOp::replace("/modified_time", Utc::now()))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "conversation".into(),
+            ))
+    }
+
+    // An assistant message written by one of the participants, given by record id.
+    pub async fn with_character_message(
+        self,
+        character: &RecordId,
+        content: String,
+    ) -> Result<Self> {
+        let db = db!();
+        db.update(self.id)
+            .patch(PatchOp::add(
+                "/messages/-",
+                Message {
+                    timestamp: Utc::now(),
+                    role: "assistant".into(),
+                    content,
+                    author: Some(character.to_string()),
+                    citations: Vec::new(),
+                    attachments: Vec::new(),
                 },
             ))
             .patch(PatchOp::replace("/modified_time", Utc::now()))
@@ -146,4 +462,118 @@ impl Conversation {
                 "conversation".into(),
             ))
     }
+
+    // Keeps the settings of characters that were already taking part. A conversation without
+    // messages opens with the first character's greeting.
+    pub async fn with_participants(self, characters: Vec<String>) -> Result<Self> {
+        let participants = characters
+            .into_iter()
+            .map(|character| {
+                self.participant(&character)
+                    .cloned()
+                    .unwrap_or_else(|| Participant::new(character))
+            })
+            .collect::<Vec<_>>();
+        let db = db!();
+        let conversation: Self = db
+            .update(self.id)
+            .patch(PatchOp::replace("/participants", participants))
+            .patch(PatchOp::replace("/modified_time", Utc::now()))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "conversation".into(),
+            ))?;
+        match conversation.messages.is_empty() {
+            true => conversation.with_greeting(None).await,
+            false => Ok(conversation),
+        }
+    }
+
+    // Opens with the first character's greeting, or its alternate greeting at `alternate`. Only
+    // while nothing but a greeting has been said, so picking another one replaces it.
+    pub async fn with_greeting(self, alternate: Option<usize>) -> Result<Self> {
+        let Some((_, character)) = self.characters().await?.into_iter().next() else {
+            return Ok(self);
+        };
+        let opening = match self.messages.as_slice() {
+            [] => true,
+            [message] => message.author == Some(character.id.to_string()),
+            _ => false,
+        };
+        if !opening {
+            return Ok(self);
+        }
+        let persona = Persona::resolve(&self).await?;
+        let Some(greeting) =
+            character.greeting(alternate, generation::user_name(persona.as_ref()))?
+        else {
+            return Ok(self);
+        };
+        match self.messages.is_empty() {
+            true => self.with_character_message(&character.id, greeting).await,
+            false => self.with_replaced_message(0, greeting).await,
+        }
+    }
+
+    pub async fn with_participant_settings(
+        self,
+        character: String,
+        talkativeness: f32,
+        muted: bool,
+    ) -> Result<Self> {
+        let id = RecordId::from_table_key("character", character.as_str());
+        let index = self
+            .participants
+            .iter()
+            .position(|participant| participant.character == id)
+            .ok_or(AliceError::NotAParticipant(character))?;
+        let db = db!();
+        db.update(self.id)
+            .patch(PatchOp::replace(
+                &format!("/participants/{}/talkativeness", index),
+                talkativeness,
+            ))
+            .patch(PatchOp::replace(
+                &format!("/participants/{}/muted", index),
+                muted,
+            ))
+            .patch(PatchOp::replace("/modified_time", Utc::now()))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "conversation".into(),
+            ))
+    }
+
+    pub async fn with_turn_order(self, turn_order: TurnOrder) -> Result<Self> {
+        let db = db!();
+        db.update(self.id)
+            .patch(PatchOp::replace("/turn_order", turn_order))
+            .patch(PatchOp::replace("/modified_time", Utc::now()))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "conversation".into(),
+            ))
+    }
+
+    // The participants with their characters, in order, skipping any that have since been deleted.
+    pub async fn characters(&self) -> Result<Vec<(Participant, Character)>> {
+        let mut characters = Vec::new();
+        for participant in &self.participants {
+            let character: Option<Character> = db!().select(participant.character.clone()).await?;
+            if let Some(character) = character {
+                characters.push((participant.clone(), character));
+            }
+        }
+        Ok(characters)
+    }
+
+    pub fn participant(&self, character: &str) -> Option<&Participant> {
+        let id = RecordId::from_table_key("character", character);
+        self.participants
+            .iter()
+            .find(|participant| participant.character == id)
+    }
 }
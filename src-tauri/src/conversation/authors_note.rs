@@ -0,0 +1,114 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::models::message::Message;
+
+fn depth() -> usize {
+    4
+}
+
+fn frequency() -> usize {
+    1
+}
+
+fn role() -> String {
+    "system".to_string()
+}
+
+// Steering text inserted a few messages from the end of the prompt, so it stays close to what the
+// model is writing next however long the chat gets. It's never stored with the messages.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthorsNote {
+    pub content: String,
+    // Messages from the end, 0 puts it after the last one.
+    #[serde(default = "depth")]
+    pub depth: usize,
+    // Inserted every this many turns, 0 turns it off.
+    #[serde(default = "frequency")]
+    pub frequency: usize,
+    #[serde(default = "role")]
+    pub role: String,
+}
+
+impl AuthorsNote {
+    pub fn is_due(&self, turn: usize) -> bool {
+        self.frequency > 0 && !self.content.trim().is_empty() && turn.is_multiple_of(self.frequency)
+    }
+
+    // Inserts the note into the messages about to be rendered, returning where it went. Counting
+    // from the end means dropping older messages never moves or loses it.
+    pub fn inject(&self, messages: &mut Vec<Message>, content: &str, turn: usize) -> Option<usize> {
+        if !self.is_due(turn) {
+            return None;
+        }
+        let index = messages.len() - self.depth.min(messages.len());
+        messages.insert(
+            index,
+            Message {
+                timestamp: Utc::now(),
+                role: self.role.clone(),
+                content: content.to_string(),
+                author: None,
+                citations: Vec::new(),
+                attachments: Vec::new(),
+            },
+        );
+        Some(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(depth: usize, frequency: usize) -> AuthorsNote {
+        AuthorsNote {
+            content: "Keep it short.".into(),
+            depth,
+            frequency,
+            role: role(),
+        }
+    }
+
+    fn messages(count: usize) -> Vec<Message> {
+        (0..count)
+            .map(|index| Message {
+                timestamp: Utc::now(),
+                role: "user".into(),
+                content: index.to_string(),
+                author: None,
+                citations: Vec::new(),
+                attachments: Vec::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_inject() {
+        let mut injected = messages(6);
+        assert_eq!(note(2, 1).inject(&mut injected, "Note", 3), Some(4));
+        assert_eq!(injected[4].content, "Note");
+        assert_eq!(injected[4].role, "system");
+        assert_eq!(injected[5].content, "4");
+
+        let mut injected = messages(6);
+        assert_eq!(note(0, 1).inject(&mut injected, "Note", 3), Some(6));
+        // Deeper than the history goes, so it's the first message.
+        let mut injected = messages(2);
+        assert_eq!(note(4, 1).inject(&mut injected, "Note", 3), Some(0));
+    }
+
+    #[test]
+    fn test_frequency() {
+        let mut injected = messages(3);
+        assert_eq!(note(1, 2).inject(&mut injected, "Note", 3), None);
+        assert_eq!(injected.len(), 3);
+        assert_eq!(note(1, 2).inject(&mut injected, "Note", 4), Some(2));
+        assert!(!note(1, 0).is_due(0));
+        assert!(!AuthorsNote {
+            content: " ".into(),
+            ..note(1, 1)
+        }
+        .is_due(1));
+    }
+}
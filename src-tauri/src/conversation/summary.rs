@@ -0,0 +1,176 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{models::message::Message, wpp::format::estimate_tokens};
+
+// Tokens a message costs besides its content, for the role header and end of turn around it.
+static MESSAGE_OVERHEAD: usize = 5;
+
+// What happened in the messages `start..end`, which no longer fit in the prompt. Each new summary
+// builds on the previous one, so the latest always covers the conversation from the start.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    pub start: usize,
+    pub end: usize,
+    pub content: String,
+    // The model that wrote it, none when the user did.
+    pub model: Option<String>,
+    pub created_time: DateTime<Utc>,
+}
+
+impl Summary {
+    // Written by the user before anything was summarized, the first summary builds on it.
+    pub fn written(content: String) -> Self {
+        Self {
+            start: 0,
+            end: 0,
+            content,
+            model: None,
+            created_time: Utc::now(),
+        }
+    }
+
+    // A summary the user wrote or corrected, still covering the same messages.
+    pub fn edited(&self, content: String) -> Self {
+        Self {
+            content,
+            model: None,
+            ..self.clone()
+        }
+    }
+
+    // The summary once the message at `index` is deleted. Later messages move down one, and the
+    // positions with them.
+    pub fn without_message(&self, index: usize) -> Self {
+        let shift = |position: usize| match index < position {
+            true => position - 1,
+            false => position,
+        };
+        Self {
+            start: shift(self.start),
+            end: shift(self.end),
+            ..self.clone()
+        }
+    }
+
+    // The summary once the message at `index` is edited. When it covered the message it's cut back
+    // to just before it, so the edit is summarized again.
+    pub fn with_replaced_message(&self, index: usize) -> Self {
+        Self {
+            end: match (self.start..self.end).contains(&index) {
+                true => index,
+                false => self.end,
+            },
+            ..self.clone()
+        }
+    }
+}
+
+// The index of the oldest message that still fits in `budget` tokens, counting back from the
+// latest. The latest message is always kept, even when it doesn't fit on its own. `content` is
+// what a message is sent as, with its attachments inlined.
+pub fn visible_from(
+    messages: &[Message],
+    budget: usize,
+    content: impl Fn(&Message) -> String,
+) -> usize {
+    let mut spent = 0;
+    for (index, message) in messages.iter().enumerate().rev() {
+        spent += estimate_tokens(&content(message)) + MESSAGE_OVERHEAD;
+        if spent > budget && index + 1 < messages.len() {
+            return index + 1;
+        }
+    }
+    0
+}
+
+// What the model is asked to summarize the evicted messages with, `transcript` being them with
+// their authors' names.
+pub fn request(previous: Option<&Summary>, transcript: &str) -> String {
+    let mut request = String::from(
+        "Summarize the conversation below in a few short paragraphs. Keep names, facts, decisions \
+         and unresolved threads, and leave out small talk.\n\n",
+    );
+    if let Some(previous) = previous.filter(|previous| !previous.content.trim().is_empty()) {
+        request.push_str(&format!(
+            "Summary of what happened before it, to be merged into the new one:\n{}\n\n",
+            previous.content.trim()
+        ));
+    }
+    request.push_str(&format!("Conversation:\n{}", transcript.trim()));
+    request
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(contents: &[&str]) -> Vec<Message> {
+        contents
+            .iter()
+            .map(|content| Message {
+                timestamp: Utc::now(),
+                role: "user".into(),
+                content: content.to_string(),
+                author: None,
+                citations: Vec::new(),
+                attachments: Vec::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_visible_from() {
+        // Every message is one token and the overhead.
+        let history = messages(&["one", "two", "six", "ten"]);
+        let content = |message: &Message| message.content.clone();
+        assert_eq!(visible_from(&history, 100, content), 0);
+        assert_eq!(visible_from(&history, 12, content), 2);
+        assert_eq!(visible_from(&history, 0, content), 3);
+        assert_eq!(visible_from(&[], 0, content), 0);
+        // A long inlined file pushes the messages before it out.
+        let inlined = |message: &Message| match message.content.as_str() {
+            "six" => "word ".repeat(20),
+            _ => message.content.clone(),
+        };
+        assert_eq!(visible_from(&history, 20, inlined), 3);
+    }
+
+    #[test]
+    fn test_request() {
+        let previous = Summary {
+            start: 0,
+            end: 4,
+            content: "They met at the lake.".into(),
+            model: Some("llama".into()),
+            created_time: Utc::now(),
+        };
+        let request = request(Some(&previous), "Alex: Where to now?\n");
+        assert!(request.contains("They met at the lake."));
+        assert!(request.ends_with("Conversation:\nAlex: Where to now?"));
+        assert!(!super::request(None, "Alex: Hi").contains("before it"));
+
+        let edited = previous.edited("They met at the tower.".into());
+        assert_eq!((edited.start, edited.end), (0, 4));
+        assert_eq!(edited.model, None);
+    }
+
+    #[test]
+    fn test_message_changes() {
+        let summary = Summary {
+            start: 2,
+            end: 5,
+            content: "They met at the lake.".into(),
+            model: None,
+            created_time: Utc::now(),
+        };
+        let positions = |summary: Summary| (summary.start, summary.end);
+        assert_eq!(positions(summary.without_message(0)), (1, 4));
+        assert_eq!(positions(summary.without_message(3)), (2, 4));
+        assert_eq!(positions(summary.without_message(4)), (2, 4));
+        assert_eq!(positions(summary.without_message(5)), (2, 5));
+        assert_eq!(positions(summary.with_replaced_message(1)), (2, 5));
+        assert_eq!(positions(summary.with_replaced_message(3)), (2, 3));
+        assert_eq!(positions(summary.with_replaced_message(5)), (2, 5));
+    }
+}
@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::message::Message;
+
+// How the next character to speak in a group chat is chosen.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TurnOrder {
+    // Whoever's turn is next after the last character who spoke.
+    #[default]
+    RoundRobin,
+    // The user picks every time.
+    Manual,
+    // Whoever the last message names first, falling back to `Random` when nobody is named.
+    Mention,
+    // A random character, more talkative ones more often.
+    Random,
+}
+
+// A character that can take the next turn.
+#[derive(Debug, Clone)]
+pub struct Speaker<'a> {
+    pub id: &'a str,
+    pub name: &'a str,
+    pub talkativeness: f32,
+}
+
+// Index of the next speaker, or `None` when the user has to pick. `roll` is a random number in
+// `[0, 1)`, passed in so the choice can be reproduced.
+pub fn next_speaker(
+    order: TurnOrder,
+    speakers: &[Speaker],
+    messages: &[Message],
+    roll: f32,
+) -> Option<usize> {
+    if speakers.is_empty() {
+        return None;
+    }
+    match order {
+        TurnOrder::Manual => None,
+        TurnOrder::RoundRobin => Some(round_robin(speakers, messages)),
+        TurnOrder::Mention => {
+            mentioned(speakers, messages).or_else(|| Some(weighted(speakers, roll)))
+        }
+        TurnOrder::Random => Some(weighted(speakers, roll)),
+    }
+}
+
+fn round_robin(speakers: &[Speaker], messages: &[Message]) -> usize {
+    messages
+        .iter()
+        .rev()
+        .find_map(|message| {
+            let author = message.author.as_deref()?;
+            speakers.iter().position(|speaker| speaker.id == author)
+        })
+        .map_or(0, |last| (last + 1) % speakers.len())
+}
+
+// The speaker named earliest in the last message, other than its author. Either the full name or
+// the first word of it counts, so "Nika" calls on "Nika Orchid".
+fn mentioned(speakers: &[Speaker], messages: &[Message]) -> Option<usize> {
+    let last = messages.last()?;
+    let text = last.content.to_lowercase();
+    speakers
+        .iter()
+        .enumerate()
+        .filter(|(_, speaker)| last.author.as_deref() != Some(speaker.id))
+        .filter_map(|(index, speaker)| {
+            let name = speaker.name.to_lowercase();
+            let first = name
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            [name, first]
+                .iter()
+                .filter_map(|name| mention(&text, name))
+                .min()
+                .map(|position| (position, index))
+        })
+        .min()
+        .map(|(_, index)| index)
+}
+
+// Position of the first whole-word occurrence of `name`.
+fn mention(text: &str, name: &str) -> Option<usize> {
+    if name.is_empty() {
+        return None;
+    }
+    let is_word = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+    text.match_indices(name)
+        .find(|(start, _)| {
+            !is_word(text[..*start].chars().next_back())
+                && !is_word(text[start + name.len()..].chars().next())
+        })
+        .map(|(start, _)| start)
+}
+
+fn weighted(speakers: &[Speaker], roll: f32) -> usize {
+    let total: f32 = speakers
+        .iter()
+        .map(|speaker| speaker.talkativeness.max(0.0))
+        .sum();
+    if total <= 0.0 {
+        return ((roll * speakers.len() as f32) as usize).min(speakers.len() - 1);
+    }
+    let mut target = roll * total;
+    for (index, speaker) in speakers.iter().enumerate() {
+        let weight = speaker.talkativeness.max(0.0);
+        if target < weight {
+            return index;
+        }
+        target -= weight;
+    }
+    // Rounding can leave `target` just past the last weight.
+    speakers
+        .iter()
+        .rposition(|speaker| speaker.talkativeness > 0.0)
+        .unwrap_or(speakers.len() - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn speakers() -> Vec<Speaker<'static>> {
+        vec![
+            Speaker {
+                id: "nika",
+                name: "Nika Orchid",
+                talkativeness: 0.5,
+            },
+            Speaker {
+                id: "mira",
+                name: "Mira",
+                talkativeness: 0.0,
+            },
+            Speaker {
+                id: "sol",
+                name: "Sol",
+                talkativeness: 1.5,
+            },
+        ]
+    }
+
+    fn message(author: Option<&str>, content: &str) -> Message {
+        Message {
+            timestamp: Utc::now(),
+            role: if author.is_some() {
+                "assistant"
+            } else {
+                "user"
+            }
+            .into(),
+            content: content.into(),
+            author: author.map(str::to_string),
+            citations: Vec::new(),
+            attachments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let speakers = speakers();
+        let next =
+            |messages: &[Message]| next_speaker(TurnOrder::RoundRobin, &speakers, messages, 0.0);
+        assert_eq!(next(&[]), Some(0));
+        assert_eq!(
+            next(&[message(Some("nika"), "Hi."), message(None, "Hello!")]),
+            Some(1)
+        );
+        assert_eq!(next(&[message(Some("sol"), "Yo.")]), Some(0));
+        assert_eq!(next(&[message(Some("someone else"), "Hey.")]), Some(0));
+    }
+
+    #[test]
+    fn test_mention() {
+        let speakers = speakers();
+        let next =
+            |messages: &[Message]| next_speaker(TurnOrder::Mention, &speakers, messages, 0.0);
+        assert_eq!(
+            next(&[message(None, "What do you think, Sol? And nika?")]),
+            Some(2)
+        );
+        assert_eq!(next(&[message(None, "Over to you, Nika.")]), Some(0));
+        // Not a whole word, so nobody's named and the roll picks the first talkative speaker.
+        assert_eq!(next(&[message(None, "Admiral Solstice")]), Some(0));
+        // Speakers don't call on themselves.
+        assert_eq!(
+            next(&[message(Some("mira"), "I'm Mira, and this is Sol.")]),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_weighted() {
+        let speakers = speakers();
+        let next = |roll| next_speaker(TurnOrder::Random, &speakers, &[], roll);
+        assert_eq!(next(0.0), Some(0));
+        assert_eq!(next(0.24), Some(0));
+        assert_eq!(next(0.26), Some(2));
+        assert_eq!(next(0.999_999), Some(2));
+        assert_eq!(next_speaker(TurnOrder::Manual, &speakers, &[], 0.5), None);
+        assert_eq!(next_speaker(TurnOrder::Random, &[], &[], 0.5), None);
+    }
+}
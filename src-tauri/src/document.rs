@@ -0,0 +1,278 @@
+use crate::prelude::*;
+
+use crate::{API_MANAGER, DB};
+
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::RecordId;
+
+use crate::conversation::Conversation;
+use crate::memory::chunk as memory_chunk;
+use crate::models::message::Citation;
+use crate::vector::{self, QUERY_MESSAGES};
+
+pub mod text;
+
+use text::{DocumentKind, MAX_CHUNK_TOKENS, OVERLAP_TOKENS};
+
+// Chunks embedded per request, so importing a book doesn't send it all at once.
+static EMBED_BATCH: usize = 32;
+
+// How many passages go into a prompt.
+static TOP_K: usize = 4;
+
+// Cosine similarity below which a passage isn't relevant enough to retrieve.
+static MIN_SCORE: f32 = 0.5;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LeanDocument {
+    pub id: RecordId,
+    pub name: String,
+    pub kind: DocumentKind,
+    pub conversation: Option<RecordId>,
+    pub created_time: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InsertableDocument {
+    pub name: String,
+    pub kind: DocumentKind,
+    pub conversation: Option<RecordId>,
+    pub chunks: usize,
+    pub created_time: DateTime<Utc>,
+}
+
+// An imported file, in the knowledge base of one conversation or, without one, of all of them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Document {
+    pub id: RecordId,
+    pub name: String,
+    pub kind: DocumentKind,
+    pub conversation: Option<RecordId>,
+    pub chunks: usize,
+    pub created_time: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InsertableDocumentChunk {
+    pub document: RecordId,
+    pub conversation: Option<RecordId>,
+    pub chunk: usize,
+    pub heading: Option<String>,
+    pub content: String,
+    pub embedding: Vec<f32>,
+}
+
+// A chunk found for the current prompt, and how similar it is to the latest messages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Passage {
+    pub document: RecordId,
+    pub conversation: Option<RecordId>,
+    pub name: String,
+    pub chunk: usize,
+    pub heading: Option<String>,
+    pub content: String,
+    pub score: f32,
+}
+
+impl Passage {
+    // How the passage is listed in the prompt, under the number replies cite it by.
+    pub fn prompt(&self, number: usize) -> String {
+        match &self.heading {
+            Some(heading) => format!("[{}] {} › {}\n{}", number, self.name, heading, self.content),
+            None => format!("[{}] {}\n{}", number, self.name, self.content),
+        }
+    }
+
+    pub fn citation(&self, number: usize) -> Citation {
+        Citation {
+            number,
+            document: self.document.to_string(),
+            name: self.name.clone(),
+            chunk: self.chunk,
+            heading: self.heading.clone(),
+        }
+    }
+}
+
+impl Document {
+    // Extracts the file's text, splits it into chunks and embeds them with the active backend.
+    // Everything is embedded before anything is stored, and the document is deleted again when
+    // storing its chunks fails, so a failure leaves nothing behind.
+    pub async fn import(path: &str, conversation: Option<String>) -> Result<Self> {
+        let kind = DocumentKind::from_path(path)
+            .ok_or_else(|| AliceError::UnsupportedDocument(path.to_string()))?;
+        let bytes = tokio::fs::read(path).await?;
+        let extracted = match kind {
+            DocumentKind::Pdf => {
+                tokio::task::spawn_blocking(move || pdf_extract::extract_text_from_mem(&bytes))
+                    .await
+                    .map_err(|e| AliceError::Other(e.to_string()))?
+                    .map_err(|e| AliceError::UnsupportedDocument(e.to_string()))?
+            }
+            DocumentKind::Text | DocumentKind::Markdown | DocumentKind::Html => {
+                String::from_utf8_lossy(&bytes).into_owned()
+            }
+        };
+        let chunks = text::chunks(
+            &text::normalize(kind, &extracted),
+            MAX_CHUNK_TOKENS,
+            OVERLAP_TOKENS,
+        );
+        if chunks.is_empty() {
+            return Err(AliceError::UnsupportedDocument(format!(
+                "no text found in {}",
+                path
+            )));
+        }
+
+        let mut embeddings = Vec::with_capacity(chunks.len());
+        for batch in chunks.chunks(EMBED_BATCH) {
+            let texts = batch
+                .iter()
+                .map(|chunk| chunk.embedded())
+                .collect::<Vec<_>>();
+            embeddings.extend(api!().embed(&texts).await?);
+        }
+        if embeddings.len() != chunks.len() {
+            return Err(AliceError::Other(format!(
+                "Expected {} embeddings, got {}",
+                chunks.len(),
+                embeddings.len()
+            )));
+        }
+
+        let conversation =
+            conversation.map(|id| RecordId::from_table_key("conversation", id.as_str()));
+        let name = Path::new(path)
+            .file_name()
+            .map_or(path.to_string(), |name| name.to_string_lossy().into_owned());
+        let document: Self = db!()
+            .create("document")
+            .content(InsertableDocument {
+                name,
+                kind,
+                conversation: conversation.clone(),
+                chunks: chunks.len(),
+                created_time: Utc::now(),
+            })
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "create".into(),
+                "document".into(),
+            ))?;
+        let rows = chunks
+            .into_iter()
+            .zip(embeddings)
+            .enumerate()
+            .map(|(index, (chunk, embedding))| InsertableDocumentChunk {
+                document: document.id.clone(),
+                conversation: conversation.clone(),
+                chunk: index,
+                heading: chunk.heading,
+                content: chunk.text,
+                embedding,
+            })
+            .collect::<Vec<_>>();
+        if let Err(e) = Self::store_chunks(rows).await {
+            // Deleting takes whatever chunks made it in along.
+            let _ = document.delete().await;
+            return Err(e);
+        }
+        Ok(document)
+    }
+
+    async fn store_chunks(rows: Vec<InsertableDocumentChunk>) -> Result<()> {
+        let redefined = match rows.first() {
+            Some(row) => vector::define_index("document_chunk", row.embedding.len()).await?,
+            None => false,
+        };
+        // The chunks embedded with a model of another size are gone, and their documents have
+        // to be imported again.
+        if redefined {
+            db!()
+                .query("DELETE document WHERE id NOTINSIDE (SELECT VALUE document FROM document_chunk)")
+                .await?
+                .check()?;
+        }
+        let _: Vec<InsertableDocumentChunk> = db!().insert("document_chunk").content(rows).await?;
+        Ok(())
+    }
+
+    pub async fn find(id: String) -> Result<Self> {
+        db!()
+            .select(("document", &id))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "select".into(),
+                id.to_string(),
+            ))
+    }
+
+    // The global documents, and the conversation's own ones when given one.
+    pub async fn name_sorted_lean(conversation: Option<String>) -> Result<Vec<LeanDocument>> {
+        let conversation =
+            conversation.map(|id| RecordId::from_table_key("conversation", id.as_str()));
+        Self::available(conversation).await
+    }
+
+    async fn available(conversation: Option<RecordId>) -> Result<Vec<LeanDocument>> {
+        let result = db!()
+            .query("SELECT id, name, kind, conversation, created_time FROM document WHERE conversation = NONE OR conversation = $conversation ORDER BY name ASC")
+            .bind(("conversation", conversation))
+            .await?
+            .take(0)?;
+        Ok(result)
+    }
+
+    pub async fn delete(self) -> Result<()> {
+        db!()
+            .query("DELETE document_chunk WHERE document = $document")
+            .bind(("document", self.id.clone()))
+            .await?
+            .check()?;
+        let deleted: Option<Self> = db!().delete(self.id).await?;
+        deleted.ok_or(AliceError::DatabaseOperation(
+            "delete".into(),
+            "document".into(),
+        ))?;
+        Ok(())
+    }
+
+    // The passages most relevant to the latest messages, from the documents the conversation can
+    // see. Nothing is embedded when there are no documents to search.
+    pub async fn retrieve(conversation: &Conversation) -> Result<Vec<Passage>> {
+        if Self::available(Some(conversation.id.clone()))
+            .await?
+            .is_empty()
+        {
+            return Ok(vec![]);
+        }
+        let query = memory_chunk::query(&conversation.messages, QUERY_MESSAGES);
+        if query.trim().is_empty() {
+            return Ok(vec![]);
+        }
+        let Some(embedding) = api!().embed(&[query]).await?.pop() else {
+            return Ok(vec![]);
+        };
+        let sql = vector::knn(
+            "document_chunk",
+            "document, document.name AS name, conversation, chunk, heading, content",
+            TOP_K,
+            Some("conversation = NONE OR conversation = $conversation"),
+        );
+        let passages: Vec<Passage> = db!()
+            .query(sql)
+            .bind(("embedding", embedding))
+            .bind(("conversation", conversation.id.clone()))
+            .await?
+            .take(0)?;
+        Ok(passages
+            .into_iter()
+            .filter(|passage| passage.score >= MIN_SCORE)
+            .take(TOP_K)
+            .collect())
+    }
+}
@@ -0,0 +1,437 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{models::message::Citation, wpp::format::estimate_tokens};
+
+// Upper bound on the tokens in a chunk, small enough that a few of them fit in a prompt.
+pub static MAX_CHUNK_TOKENS: usize = 384;
+
+// Tokens repeated from the end of one chunk at the start of the next, so a passage split between
+// them can still be found whole.
+pub static OVERLAP_TOKENS: usize = 48;
+
+// Elements whose text never ends up in the document.
+static SKIPPED_ELEMENTS: [&str; 5] = ["script", "style", "head", "noscript", "template"];
+
+// Elements that start a new paragraph.
+static BLOCK_ELEMENTS: [&str; 21] = [
+    "p",
+    "div",
+    "section",
+    "article",
+    "header",
+    "footer",
+    "nav",
+    "aside",
+    "main",
+    "ul",
+    "ol",
+    "li",
+    "dl",
+    "dt",
+    "dd",
+    "table",
+    "tr",
+    "blockquote",
+    "pre",
+    "figure",
+    "hr",
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentKind {
+    Text,
+    Markdown,
+    Html,
+    Pdf,
+}
+
+impl DocumentKind {
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "txt" | "text" => Some(Self::Text),
+            "md" | "markdown" => Some(Self::Markdown),
+            "html" | "htm" | "xhtml" => Some(Self::Html),
+            "pdf" => Some(Self::Pdf),
+            _ => None,
+        }
+    }
+}
+
+// A piece of a document as it's embedded, under the headings it's nested in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextChunk {
+    pub heading: Option<String>,
+    pub text: String,
+}
+
+impl TextChunk {
+    // The heading goes into the embedding too, chunks often only make sense under it.
+    pub fn embedded(&self) -> String {
+        match &self.heading {
+            Some(heading) => format!("{}\n{}", heading, self.text),
+            None => self.text.clone(),
+        }
+    }
+}
+
+// The extracted text as Markdown, which is what chunking splits headings out of. Plain text and
+// PDF text go through as they are.
+pub fn normalize(kind: DocumentKind, text: &str) -> String {
+    match kind {
+        DocumentKind::Html => html_to_markdown(text),
+        DocumentKind::Text | DocumentKind::Markdown | DocumentKind::Pdf => {
+            text.replace("\r\n", "\n")
+        }
+    }
+}
+
+// Keeps the text of an HTML page, with headings turned into Markdown ones and blocks into
+// paragraphs. Anything fancier than that doesn't matter for retrieval.
+pub fn html_to_markdown(html: &str) -> String {
+    let mut output = String::new();
+    let mut skipping: Option<String> = None;
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        if skipping.is_none() {
+            output.push_str(&decode_entities(&rest[..start]));
+        }
+        rest = &rest[start..];
+        if rest.starts_with("<!--") {
+            rest = rest.find("-->").map_or("", |end| &rest[end + 3..]);
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        if let Some(skipped) = &skipping {
+            if closing && *skipped == name {
+                skipping = None;
+            }
+            continue;
+        }
+        if SKIPPED_ELEMENTS.contains(&name.as_str()) {
+            if !closing && !tag.ends_with('/') {
+                skipping = Some(name);
+            }
+            continue;
+        }
+        match name.as_str() {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" if !closing => {
+                let level = name[1..].parse::<usize>().unwrap_or(1);
+                output.push_str(&format!("\n\n{} ", "#".repeat(level)));
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => output.push_str("\n\n"),
+            "br" => output.push('\n'),
+            name if BLOCK_ELEMENTS.contains(&name) => output.push_str("\n\n"),
+            _ => {}
+        }
+    }
+    if skipping.is_none() {
+        output.push_str(&decode_entities(rest));
+    }
+    tidy(&output)
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+// Collapses the whitespace markup leaves behind, keeping single line breaks and at most one blank
+// line between paragraphs.
+fn tidy(text: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    lines.join("\n").trim().to_string()
+}
+
+// Splits Markdown into chunks of at most `max_tokens`, never across headings. Paragraphs are kept
+// whole when they fit, and the last `overlap` tokens of a chunk start the next one.
+pub fn chunks(text: &str, max_tokens: usize, overlap: usize) -> Vec<TextChunk> {
+    let mut chunks = Vec::new();
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let mut paragraphs: Vec<String> = Vec::new();
+    let mut paragraph = String::new();
+    for line in text.lines() {
+        if let Some((level, heading)) = heading(line) {
+            paragraphs.extend(take_paragraph(&mut paragraph));
+            chunks.extend(pack(
+                &paragraphs,
+                &heading_path(&headings),
+                max_tokens,
+                overlap,
+            ));
+            paragraphs.clear();
+            headings.retain(|(other, _)| *other < level);
+            headings.push((level, heading));
+        } else if line.trim().is_empty() {
+            paragraphs.extend(take_paragraph(&mut paragraph));
+        } else {
+            if !paragraph.is_empty() {
+                paragraph.push('\n');
+            }
+            paragraph.push_str(line.trim_end());
+        }
+    }
+    paragraphs.extend(take_paragraph(&mut paragraph));
+    chunks.extend(pack(
+        &paragraphs,
+        &heading_path(&headings),
+        max_tokens,
+        overlap,
+    ));
+    chunks
+}
+
+fn heading(line: &str) -> Option<(usize, String)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let heading = line[level..]
+        .strip_prefix(' ')?
+        .trim()
+        .trim_end_matches('#');
+    if !(1..=6).contains(&level) || heading.trim().is_empty() {
+        return None;
+    }
+    Some((level, heading.trim().to_string()))
+}
+
+fn heading_path(headings: &[(usize, String)]) -> Option<String> {
+    if headings.is_empty() {
+        return None;
+    }
+    Some(
+        headings
+            .iter()
+            .map(|(_, heading)| heading.as_str())
+            .collect::<Vec<_>>()
+            .join(" › "),
+    )
+}
+
+fn take_paragraph(paragraph: &mut String) -> Option<String> {
+    let taken = std::mem::take(paragraph);
+    let trimmed = taken.trim();
+    (!trimmed.is_empty()).then(|| trimmed.to_string())
+}
+
+// Packs a section's paragraphs into chunks, splitting the ones too long for a chunk by words.
+fn pack(
+    paragraphs: &[String],
+    heading: &Option<String>,
+    max_tokens: usize,
+    overlap: usize,
+) -> Vec<TextChunk> {
+    let pieces = paragraphs
+        .iter()
+        .flat_map(|paragraph| split_words(paragraph, max_tokens))
+        .map(|piece| {
+            let tokens = estimate_tokens(&piece);
+            (piece, tokens)
+        })
+        .collect::<Vec<_>>();
+    let mut chunks = Vec::new();
+    let mut current: Vec<&(String, usize)> = Vec::new();
+    for piece in &pieces {
+        let used = current.iter().map(|(_, tokens)| tokens).sum::<usize>();
+        if !current.is_empty() && used + piece.1 > max_tokens {
+            chunks.push(chunk(&current, heading));
+            let mut kept = 0;
+            let mut carried = Vec::new();
+            for previous in current.iter().rev() {
+                if kept + previous.1 > overlap || kept + previous.1 + piece.1 > max_tokens {
+                    break;
+                }
+                kept += previous.1;
+                carried.insert(0, *previous);
+            }
+            current = carried;
+        }
+        current.push(piece);
+    }
+    if !current.is_empty() {
+        chunks.push(chunk(&current, heading));
+    }
+    chunks
+}
+
+fn chunk(pieces: &[&(String, usize)], heading: &Option<String>) -> TextChunk {
+    TextChunk {
+        heading: heading.clone(),
+        text: pieces
+            .iter()
+            .map(|(piece, _)| piece.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+    }
+}
+
+fn split_words(paragraph: &str, max_tokens: usize) -> Vec<String> {
+    if estimate_tokens(paragraph) <= max_tokens {
+        return vec![paragraph.to_string()];
+    }
+    let mut pieces = Vec::new();
+    let mut piece = String::new();
+    let mut used = 0;
+    for word in paragraph.split_whitespace() {
+        let tokens = estimate_tokens(word).max(1);
+        if used + tokens > max_tokens && !piece.is_empty() {
+            pieces.push(std::mem::take(&mut piece));
+            used = 0;
+        }
+        if !piece.is_empty() {
+            piece.push(' ');
+        }
+        piece.push_str(word);
+        used += tokens;
+    }
+    if !piece.is_empty() {
+        pieces.push(piece);
+    }
+    pieces
+}
+
+// The passages a reply refers to with markers like `[2]` or `[1, 3]`, in the order they're
+// numbered. Markers without a passage behind them are ignored.
+pub fn cited(content: &str, passages: &[Citation]) -> Vec<Citation> {
+    let mut numbers = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find('[') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find(']') else {
+            break;
+        };
+        let marker = &rest[..end];
+        let parsed = marker
+            .split(',')
+            .map(|number| number.trim().parse::<usize>())
+            .collect::<Result<Vec<_>, _>>();
+        if let Ok(parsed) = parsed {
+            numbers.extend(parsed);
+            rest = &rest[end + 1..];
+        }
+    }
+    numbers.sort_unstable();
+    numbers.dedup();
+    numbers
+        .into_iter()
+        .filter_map(|number| {
+            passages
+                .iter()
+                .find(|passage| passage.number == number)
+                .cloned()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_markdown() {
+        let html = r#"<html><head><title>Ignored</title></head><body>
+            <h1>Madou</h1><p>The tower is <b>north</b> of the lake &amp; tall.</p>
+            <script>let ignored = "<p>";</script><!-- <h2>Hidden</h2> -->
+            <h2 class="sub">Floors</h2><ul><li>First</li><li>Second<br>floor</li></ul>
+            </body></html>"#;
+        assert_eq!(
+            html_to_markdown(html),
+            "# Madou\n\nThe tower is north of the lake & tall.\n\n## Floors\n\nFirst\n\nSecond\nfloor"
+        );
+    }
+
+    #[test]
+    fn test_chunks() {
+        let text =
+            "Preface.\n\n# Madou\n\nThe tower.\n\n## Floors\n\nOne.\nTwo.\n\n# Lake\n\nWater.";
+        let chunks = chunks(text, MAX_CHUNK_TOKENS, OVERLAP_TOKENS);
+        assert_eq!(
+            chunks,
+            vec![
+                TextChunk {
+                    heading: None,
+                    text: "Preface.".into()
+                },
+                TextChunk {
+                    heading: Some("Madou".into()),
+                    text: "The tower.".into()
+                },
+                TextChunk {
+                    heading: Some("Madou › Floors".into()),
+                    text: "One.\nTwo.".into()
+                },
+                TextChunk {
+                    heading: Some("Lake".into()),
+                    text: "Water.".into()
+                },
+            ]
+        );
+        assert_eq!(chunks[2].embedded(), "Madou › Floors\nOne.\nTwo.");
+        assert!(super::chunks("#hashtag\n\n", MAX_CHUNK_TOKENS, 0)[0]
+            .heading
+            .is_none());
+    }
+
+    #[test]
+    fn test_chunk_overlap() {
+        // Every paragraph is two tokens.
+        let text = "one.\n\ntwo.\n\nsix.\n\nten.\n\nbig.";
+        let texts = chunks(text, 6, 2)
+            .into_iter()
+            .map(|chunk| chunk.text)
+            .collect::<Vec<_>>();
+        assert_eq!(texts, vec!["one.\n\ntwo.\n\nsix.", "six.\n\nten.\n\nbig."]);
+
+        let long = "word ".repeat(20);
+        let split = chunks(&long, 8, 0);
+        assert!(split.len() > 1);
+        assert!(split.iter().all(|chunk| estimate_tokens(&chunk.text) <= 8));
+    }
+
+    #[test]
+    fn test_cited() {
+        let passages = (1..=3)
+            .map(|number| Citation {
+                number,
+                document: format!("document:{}", number),
+                name: "madou.md".into(),
+                chunk: number - 1,
+                heading: None,
+            })
+            .collect::<Vec<_>>();
+        let cited = cited(
+            "North of the lake [2], and tall [1, 2][7] [note]",
+            &passages,
+        );
+        assert_eq!(
+            cited.iter().map(|c| c.number).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert!(super::cited("No sources.", &passages).is_empty());
+    }
+}
@@ -2,8 +2,45 @@ use crate::prelude::*;
 
 use tauri::Emitter;
 
-use crate::APP;
+use crate::{models::parameters::ParameterError, speech::synthesis::SpeechClip, APP};
 
 pub async fn emit_connection_status(is_alive: bool) -> Result<()> {
     Ok(app!().emit("connection_status", is_alive)?)
 }
+
+pub async fn emit_stripped_parameters(stripped: &[ParameterError]) -> Result<()> {
+    Ok(app!().emit("stripped_parameters", stripped)?)
+}
+
+// Memorizing happens after a reply is saved, so a failure there is reported without failing it.
+pub async fn emit_memory_error(error: &str) -> Result<()> {
+    Ok(app!().emit("memory_error", error)?)
+}
+
+// Documents are looked up while building the prompt, a failure there leaves them out of it.
+pub async fn emit_document_error(error: &str) -> Result<()> {
+    Ok(app!().emit("document_error", error)?)
+}
+
+// Attachments are read while building the prompt, one that can't be is left out of it.
+pub async fn emit_attachment_error(error: &str) -> Result<()> {
+    Ok(app!().emit("attachment_error", error)?)
+}
+
+// Replies are read out in the background as they're generated.
+pub async fn emit_speech_clip(clip: &SpeechClip) -> Result<()> {
+    Ok(app!().emit("speech_clip", clip)?)
+}
+
+pub async fn emit_speech_error(error: &str) -> Result<()> {
+    Ok(app!().emit("speech_error", error)?)
+}
+
+// Summaries are written in the background after a reply, so the UI hears about them here.
+pub async fn emit_conversation_summarized(id: &str) -> Result<()> {
+    Ok(app!().emit("conversation_summarized", id)?)
+}
+
+pub async fn emit_summary_error(error: &str) -> Result<()> {
+    Ok(app!().emit("summary_error", error)?)
+}
@@ -0,0 +1,964 @@
+use crate::prelude::*;
+
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use surrealdb::RecordId;
+use tauri::Emitter;
+
+use crate::{
+    attachment::media::{self, AttachmentKind, ImageData},
+    character::Character,
+    conversation::{
+        summary::{self, Summary},
+        turn::{self, Speaker},
+        Conversation, Participant,
+    },
+    document::{text, Document, Passage},
+    events,
+    imaging::prompt::{self as image_prompts, ImageSubject},
+    lorebook::{
+        scan::{self, LoreActivation},
+        Lorebook,
+    },
+    manager,
+    memory::{Memory, Recollection},
+    models::{
+        constraints::Constraints,
+        message::{Citation, Message},
+        model::Model,
+        parameters::EngineParameters,
+    },
+    persona::Persona,
+    preset::Preset,
+    speech::synthesis::Narration,
+    tools::{self, ToolRegistry, TOOL_CALL_END},
+    wpp::{
+        chat::{ChatPrompt, ChatPromptContext},
+        format::estimate_tokens,
+        prompting::Prompt,
+    },
+    API_MANAGER, APP, LLAMA3_PROMPT_TEMPLATE,
+};
+
+// Upper bound on tool call round trips for a single generation, in case the model loops.
+const MAX_TOOL_ROUNDS: usize = 5;
+
+static SYSTEM_PROMPT: &str = "You are an intelligent assistant.";
+
+static SUMMARY_SYSTEM_PROMPT: &str =
+    "You summarize conversations accurately and briefly, without adding anything.";
+
+// Upper bound on the length of a summary.
+static SUMMARY_TOKENS: i64 = 400;
+
+static IMAGE_PROMPT_SYSTEM_PROMPT: &str =
+    "You write prompts for an image generator, describing only what can be seen.";
+
+// Upper bound on the length of an image prompt.
+static IMAGE_PROMPT_TOKENS: i64 = 150;
+
+// How many messages, up to the pictured one, the model reads to describe a scene.
+static IMAGE_PROMPT_MESSAGES: usize = 8;
+
+// Conversations with a summary being written, one at a time each.
+static SUMMARIZING: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// Who `{{user}}` refers to when neither the conversation nor the settings pick a persona.
+static USER_NAME: &str = "User";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GenerationMode {
+    // A new assistant message.
+    Respond,
+    // More tokens for the last assistant message, without a new turn.
+    Continue,
+    // A draft of the user's next message.
+    Impersonate,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GenerationTokens {
+    pub id: String,
+    pub mode: GenerationMode,
+    pub tokens: String,
+}
+
+// The prompt a generation would send, and why each lorebook entry was or wasn't injected into it.
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptPreview {
+    pub prompt: String,
+    pub speaker: Option<Character>,
+    pub lore: Vec<LoreActivation>,
+    // Where among the prompt's messages the author's note went, if it was due this turn.
+    pub authors_note: Option<usize>,
+    pub memories: Vec<Recollection>,
+    // Numbered in the order they're listed in the prompt.
+    pub passages: Vec<Passage>,
+    // Messages before this one didn't fit and are left to the summary.
+    pub visible_from: usize,
+    pub summary: Option<Summary>,
+    // How many images are sent along with the prompt.
+    pub images: usize,
+}
+
+// Who a prompt is for and what it's built from besides the messages.
+struct Scene {
+    characters: Vec<Character>,
+    // Index into `characters` of the one replying, if any.
+    speaker: Option<usize>,
+    persona: Option<Persona>,
+    lore: Vec<LoreActivation>,
+    memories: Vec<Recollection>,
+    passages: Vec<Passage>,
+    // Left out when nothing has been evicted, the messages are all there.
+    summary: Option<Summary>,
+    params: EngineParameters,
+    model: Option<Model>,
+    // Index of the oldest message that fits in the prompt.
+    visible_from: usize,
+    // The visible messages' images, in the order they're first attached.
+    images: Vec<ImageData>,
+    // Attachment hash to index into `images`.
+    image_indices: HashMap<String, usize>,
+    // Attachment hash to the content of a text file.
+    texts: HashMap<String, String>,
+}
+
+// Generates the next assistant message, as `speaker` or whoever the turn order picks in chats with
+// characters. When the model calls tools, their results are added as `tool` messages and
+// generation continues until it answers without calling any, or runs out of rounds and has to.
+pub async fn generate(id: String, speaker: Option<String>) -> Result<Conversation> {
+    let tools = ToolRegistry::for_conversation(&id).await?;
+    let no_tools = ToolRegistry::new();
+    let mut conversation = Conversation::find(id.clone()).await?;
+    let mut scene = Scene::new(&conversation, GenerationMode::Respond, speaker, &tools).await?;
+    for round in 0..=MAX_TOOL_ROUNDS {
+        let tools = match round < MAX_TOOL_ROUNDS {
+            true => &tools,
+            false => &no_tools,
+        };
+        let mut completion = complete(&conversation, &scene, tools, GenerationMode::Respond, &id)
+            .await?
+            .trim()
+            .to_string();
+        let calls = match tools.is_empty() {
+            true => vec![],
+            false => tools::parse_tool_calls(&completion),
+        };
+        if !calls.is_empty() && !completion.ends_with(TOOL_CALL_END) {
+            completion.push_str(TOOL_CALL_END);
+        }
+        let citations = text::cited(&completion, &scene.citations());
+        conversation = match scene.speaker() {
+            Some(speaker) => {
+                conversation
+                    .with_character_message(&speaker.id, completion)
+                    .await?
+            }
+            None => {
+                conversation
+                    .with_message("assistant".into(), completion)
+                    .await?
+            }
+        };
+        if !citations.is_empty() {
+            let index = conversation.messages.len() - 1;
+            conversation = conversation.with_citations(index, citations).await?;
+        }
+        if calls.is_empty() {
+            break;
+        }
+        for call in calls {
+            let output = tools.call(&call).await;
+            conversation = conversation
+                .with_message(
+                    "tool".into(),
+                    json!({ "name": call.name, "content": output }).to_string(),
+                )
+                .await?;
+        }
+        scene
+            .refit(&conversation, GenerationMode::Respond, &tools)
+            .await?;
+    }
+    // Only a new turn counts towards stickiness and cooldowns, so continuing or impersonating
+    // leaves them alone.
+    let remembered = Lorebook::remembered(&conversation, &scene.lore);
+    let conversation = conversation.with_lore_activations(remembered).await?;
+    if let Err(e) = Memory::memorize(&conversation).await {
+        let _ = events::emit_memory_error(&e.to_string()).await;
+    }
+    summarize_evicted(&conversation, &scene, id)?;
+    Ok(conversation)
+}
+
+// Summarizes the messages the prompt had no room for, on top of the latest summary. It runs in the
+// background, the reply doesn't wait for it. While one is being written for the conversation the
+// next reply doesn't start another, it'll be summarized after the reply following that.
+fn summarize_evicted(conversation: &Conversation, scene: &Scene, id: String) -> Result<()> {
+    let previous = conversation.summary.as_ref();
+    let start = previous.map_or(0, |summary| summary.end);
+    let end = scene.visible_from.min(conversation.messages.len());
+    if end <= start {
+        return Ok(());
+    }
+    {
+        let mut summarizing = SUMMARIZING.lock().unwrap_or_else(|e| e.into_inner());
+        if summarizing.contains(&id) {
+            return Ok(());
+        }
+        summarizing.push(id.clone());
+    }
+    let request = summary::request(
+        previous,
+        &scene.transcript(&conversation.messages[start..end]),
+    );
+    let snippet = chat_prompt(
+        vec![Message {
+            timestamp: Utc::now(),
+            role: "user".into(),
+            content: request,
+            author: None,
+            citations: Vec::new(),
+            attachments: Vec::new(),
+        }],
+        SUMMARY_SYSTEM_PROMPT,
+        "assistant",
+    )?
+    .render()?;
+    let mut params = scene.params.clone();
+    params.max_tokens = SUMMARY_TOKENS;
+    params.stop_sequences = vec!["<|eot_id|>".to_string()];
+    let model = scene.model.as_ref().map(|model| model.name.clone());
+    tokio::spawn(async move {
+        let summarized = async {
+            // Only the api stays locked while the summary is written, not the whole manager.
+            let api = api_manager!().api.clone();
+            let content = manager::complete(
+                api,
+                &snippet,
+                &[],
+                params,
+                Constraints::default(),
+                Box::new(|_| Ok(())),
+            )
+            .await?;
+            let conversation = Conversation::find(id.clone()).await?;
+            // The user may have written a summary in the meantime.
+            let stored = conversation
+                .summary
+                .as_ref()
+                .map_or(0, |summary| summary.end);
+            if stored >= end {
+                return Ok(conversation);
+            }
+            conversation
+                .with_summary(Some(Summary {
+                    start: 0,
+                    end,
+                    content: content.trim().to_string(),
+                    model,
+                    created_time: Utc::now(),
+                }))
+                .await
+        }
+        .await;
+        SUMMARIZING
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|summarizing| summarizing != &id);
+        let _ = match summarized {
+            Ok(_) => events::emit_conversation_summarized(&id).await,
+            Err(e) => events::emit_summary_error(&e.to_string()).await,
+        };
+    });
+    Ok(())
+}
+
+// Appends to the last message, which has to be the assistant's.
+pub async fn continue_last(id: String) -> Result<Conversation> {
+    let tools = ToolRegistry::for_conversation(&id).await?;
+    let conversation = Conversation::find(id.clone()).await?;
+    let index = match conversation.messages.last() {
+        Some(message) if message.role == "assistant" => conversation.messages.len() - 1,
+        _ => return Err(AliceError::NothingToContinue),
+    };
+    let scene = Scene::new(&conversation, GenerationMode::Continue, None, &tools).await?;
+    let completion = complete(&conversation, &scene, &tools, GenerationMode::Continue, &id).await?;
+    let content = format!("{}{}", conversation.messages[index].content, completion);
+    conversation
+        .with_replaced_message(index, content.trim_end().to_string())
+        .await
+}
+
+// Drafts the user's next message without adding it to the conversation.
+pub async fn impersonate(id: String) -> Result<String> {
+    let tools = ToolRegistry::new();
+    let conversation = Conversation::find(id.clone()).await?;
+    let scene = Scene::new(&conversation, GenerationMode::Impersonate, None, &tools).await?;
+    Ok(complete(
+        &conversation,
+        &scene,
+        &tools,
+        GenerationMode::Impersonate,
+        &id,
+    )
+    .await?
+    .trim()
+    .to_string())
+}
+
+// Renders the prompt without generating anything, with who it's for and the lorebook activation
+// trace. The turn order may pick someone else when actually generating.
+pub async fn preview(
+    id: String,
+    mode: GenerationMode,
+    speaker: Option<String>,
+) -> Result<PromptPreview> {
+    let tools = match mode {
+        GenerationMode::Impersonate => ToolRegistry::new(),
+        GenerationMode::Respond | GenerationMode::Continue => ToolRegistry::enabled().await?,
+    };
+    let conversation = Conversation::find(id).await?;
+    let scene = Scene::new(&conversation, mode, speaker, &tools).await?;
+    let (_, authors_note) = scene.messages(&conversation, mode);
+    Ok(PromptPreview {
+        prompt: prompt(&conversation, &scene, &tools, mode)?,
+        speaker: scene.speaker().cloned(),
+        lore: scene.lore,
+        authors_note,
+        memories: scene.memories,
+        passages: scene.passages,
+        visible_from: scene.visible_from,
+        summary: scene.summary,
+        images: scene.images.len(),
+    })
+}
+
+// Has the model describe the scene at the message `index`, or its author, as an image prompt.
+pub async fn image_prompt(
+    conversation: &Conversation,
+    index: usize,
+    subject: ImageSubject,
+) -> Result<String> {
+    let message = conversation
+        .messages
+        .get(index)
+        .ok_or(AliceError::IndexOutOfBounds(index))?;
+    // Only what the request needs, a whole scene would recall memories and passages for nothing.
+    let participants = conversation
+        .characters()
+        .await?
+        .into_iter()
+        .map(|(_, character)| character)
+        .collect::<Vec<_>>();
+    let persona = Persona::resolve(conversation).await?;
+    let user_name = user_name(persona.as_ref());
+    let model = api!().status().await?;
+    let mut params = Preset::resolve(conversation, model.as_ref()).await?;
+    let context = ChatPromptContext::new(
+        participants
+            .iter()
+            .map(|character| character.name.clone())
+            .collect(),
+        user_name.to_string(),
+    );
+    let characters = match subject {
+        ImageSubject::Character => {
+            let author = message
+                .author
+                .as_deref()
+                .and_then(|author| {
+                    participants
+                        .iter()
+                        .find(|character| character.id.to_string() == author)
+                })
+                .or(participants.first())
+                .ok_or(AliceError::NothingToPicture)?;
+            vec![author]
+        }
+        ImageSubject::Message | ImageSubject::Scene => participants.iter().collect(),
+    };
+    let characters = characters
+        .into_iter()
+        .map(|character| {
+            (
+                character.name.as_str(),
+                context.substitute(&character.sheet()),
+            )
+        })
+        .collect::<Vec<_>>();
+    let start = (index + 1).saturating_sub(IMAGE_PROMPT_MESSAGES);
+    let request = image_prompts::request(
+        subject,
+        &characters,
+        &transcript(
+            &conversation.messages[start..=index],
+            &participants,
+            user_name,
+        ),
+    );
+    let snippet = chat_prompt(
+        vec![Message {
+            timestamp: Utc::now(),
+            role: "user".into(),
+            content: request,
+            author: None,
+            citations: Vec::new(),
+            attachments: Vec::new(),
+        }],
+        IMAGE_PROMPT_SYSTEM_PROMPT,
+        "assistant",
+    )?
+    .render()?;
+    params.max_tokens = IMAGE_PROMPT_TOKENS;
+    params.stop_sequences = vec!["<|eot_id|>".to_string()];
+    let api = api_manager!().api.clone();
+    let completion = manager::complete(
+        api,
+        &snippet,
+        &[],
+        params,
+        Constraints::default(),
+        Box::new(|_| Ok(())),
+    )
+    .await?;
+    Ok(image_prompts::clean(&completion))
+}
+
+// The character the turn order picks to reply next, if the conversation has any.
+pub async fn next_speaker(id: String) -> Result<Option<Character>> {
+    let conversation = Conversation::find(id).await?;
+    let participants = conversation.characters().await?;
+    let speaker = pick_speaker(&conversation, &participants, None)?;
+    Ok(speaker.map(|index| participants[index].1.clone()))
+}
+
+fn pick_speaker(
+    conversation: &Conversation,
+    participants: &[(Participant, Character)],
+    requested: Option<String>,
+) -> Result<Option<usize>> {
+    if participants.is_empty() {
+        return Ok(None);
+    }
+    if let Some(requested) = requested {
+        let id = RecordId::from_table_key("character", requested.as_str());
+        return participants
+            .iter()
+            .position(|(_, character)| character.id == id)
+            .map(Some)
+            .ok_or(AliceError::NotAParticipant(requested));
+    }
+    let ids = participants
+        .iter()
+        .map(|(_, character)| character.id.to_string())
+        .collect::<Vec<_>>();
+    let (indices, speakers): (Vec<usize>, Vec<Speaker>) = participants
+        .iter()
+        .enumerate()
+        .filter(|(_, (participant, _))| !participant.muted)
+        .map(|(index, (participant, character))| {
+            (
+                index,
+                Speaker {
+                    id: &ids[index],
+                    name: &character.name,
+                    talkativeness: participant.talkativeness,
+                },
+            )
+        })
+        .unzip();
+    let next = turn::next_speaker(
+        conversation.turn_order,
+        &speakers,
+        &conversation.messages,
+        rand::random(),
+    )
+    .ok_or(AliceError::NoSpeaker)?;
+    Ok(Some(indices[next]))
+}
+
+impl Scene {
+    async fn new(
+        conversation: &Conversation,
+        mode: GenerationMode,
+        speaker: Option<String>,
+        tools: &ToolRegistry,
+    ) -> Result<Self> {
+        let participants = conversation.characters().await?;
+        let speaker = match mode {
+            GenerationMode::Respond => pick_speaker(conversation, &participants, speaker)?,
+            // Whoever wrote the message being continued.
+            GenerationMode::Continue => conversation
+                .messages
+                .last()
+                .and_then(|message| message.author.as_deref())
+                .and_then(|author| {
+                    participants
+                        .iter()
+                        .position(|(_, character)| character.id.to_string() == author)
+                }),
+            GenerationMode::Impersonate => None,
+        };
+        let characters = participants
+            .into_iter()
+            .map(|(_, character)| character)
+            .collect::<Vec<_>>();
+        let persona = Persona::resolve(conversation).await?;
+        let lore = Lorebook::activate(conversation, &characters).await?;
+        let model = api!().status().await?;
+        let params = Preset::resolve(conversation, model.as_ref()).await?;
+        let mut scene = Self {
+            characters,
+            speaker,
+            persona,
+            lore,
+            memories: vec![],
+            passages: vec![],
+            summary: conversation.summary.clone(),
+            params,
+            model,
+            visible_from: 0,
+            images: vec![],
+            image_indices: HashMap::new(),
+            texts: HashMap::new(),
+        };
+        // Memories and passages only take room from the messages, so whatever is evicted without
+        // them is evicted with them too, and memories of it can be recalled.
+        let evicted = scene.fit(conversation, mode, tools)?;
+        scene.memories = match Memory::recall(conversation, evicted).await {
+            Ok(memories) => memories,
+            Err(e) => {
+                let _ = events::emit_memory_error(&e.to_string()).await;
+                vec![]
+            }
+        };
+        scene.passages = match Document::retrieve(conversation).await {
+            Ok(passages) => passages,
+            Err(e) => {
+                let _ = events::emit_document_error(&e.to_string()).await;
+                vec![]
+            }
+        };
+        scene.refit(conversation, mode, tools).await?;
+        Ok(scene)
+    }
+
+    // Where the messages start, once again after messages were added like tool results are
+    // between rounds. The summary is only there when something was left out. Text files only
+    // count once they're read, so the messages are fitted again after reading what's attached to
+    // them, which can only leave more out.
+    async fn refit(
+        &mut self,
+        conversation: &Conversation,
+        mode: GenerationMode,
+        tools: &ToolRegistry,
+    ) -> Result<()> {
+        self.visible_from = self.fit(conversation, mode, tools)?;
+        self.load_attachments(conversation).await;
+        let visible_from = self.fit(conversation, mode, tools)?;
+        if visible_from != self.visible_from {
+            self.visible_from = visible_from;
+            self.load_attachments(conversation).await;
+        }
+        self.summary = match self.visible_from {
+            0 => None,
+            _ => conversation.summary.clone(),
+        };
+        Ok(())
+    }
+
+    // Reads the files attached to the visible messages, each once however often it's attached.
+    // Images are numbered again from the first visible message, text files stay read.
+    async fn load_attachments(&mut self, conversation: &Conversation) {
+        self.images.clear();
+        self.image_indices.clear();
+        let visible = &conversation.messages[self.visible_from.min(conversation.messages.len())..];
+        // Generated images are for the user to look at, sending them back would make every later
+        // prompt a vision prompt.
+        let attachments = visible
+            .iter()
+            .flat_map(|message| &message.attachments)
+            .filter(|attachment| attachment.generation.is_none());
+        for attachment in attachments {
+            if self.image_indices.contains_key(&attachment.hash)
+                || self.texts.contains_key(&attachment.hash)
+            {
+                continue;
+            }
+            let loaded = match attachment.kind {
+                AttachmentKind::Image => attachment.image().await.map(|image| {
+                    self.image_indices
+                        .insert(attachment.hash.clone(), self.images.len());
+                    self.images.push(image);
+                }),
+                AttachmentKind::Text => attachment.text().await.map(|text| {
+                    self.texts.insert(attachment.hash.clone(), text);
+                }),
+            };
+            if let Err(e) = loaded {
+                let _ = events::emit_attachment_error(&format!("{}: {}", attachment.name, e)).await;
+            }
+        }
+    }
+
+    // The message's content after its attachments, images as markers and text files inlined.
+    // Markers the user typed are escaped.
+    fn content(&self, message: &Message) -> String {
+        let mut parts = message
+            .attachments
+            .iter()
+            .filter_map(|attachment| match attachment.kind {
+                AttachmentKind::Image => self
+                    .image_indices
+                    .get(&attachment.hash)
+                    .map(|index| media::image_marker(*index)),
+                AttachmentKind::Text => self
+                    .texts
+                    .get(&attachment.hash)
+                    .map(|text| media::text_block(&attachment.name, text)),
+            })
+            .collect::<Vec<_>>();
+        let content = media::escape_markers(&message.content);
+        if parts.is_empty() {
+            return content;
+        }
+        parts.push(content);
+        parts.join("\n\n")
+    }
+
+    // Where the messages have to start for the prompt to leave room for the reply.
+    fn fit(
+        &self,
+        conversation: &Conversation,
+        mode: GenerationMode,
+        tools: &ToolRegistry,
+    ) -> Result<usize> {
+        let reserved = self.params.max_tokens.max(0) as usize
+            + estimate_tokens(&self.system_prompt(mode)?)
+            + estimate_tokens(&serde_json::to_string(&tools.describe())?)
+            + conversation
+                .authors_note
+                .as_ref()
+                .map_or(0, |note| estimate_tokens(&note.content));
+        let budget = (self.params.context_window.max(0) as usize).saturating_sub(reserved);
+        Ok(summary::visible_from(
+            &conversation.messages,
+            budget,
+            |message| self.content(message),
+        ))
+    }
+
+    // The messages as a plain transcript with who wrote each, for summarizing.
+    fn transcript(&self, messages: &[Message]) -> String {
+        transcript(messages, &self.characters, self.user_name())
+    }
+
+    fn user_name(&self) -> &str {
+        user_name(self.persona.as_ref())
+    }
+
+    // What the reply can cite, numbered the way the passages are listed in the prompt.
+    fn citations(&self) -> Vec<Citation> {
+        self.passages
+            .iter()
+            .enumerate()
+            .map(|(index, passage)| passage.citation(index + 1))
+            .collect()
+    }
+
+    fn speaker(&self) -> Option<&Character> {
+        self.speaker.map(|index| &self.characters[index])
+    }
+
+    // With more than one character every reply is prefixed with its author's name, so the model
+    // can tell them apart.
+    fn is_group(&self) -> bool {
+        self.characters.len() > 1
+    }
+
+    // The chat the prompt is made of, without its messages.
+    fn chat(&self, mode: GenerationMode) -> ChatPrompt {
+        let (before, after) = scan::contents(&self.lore);
+        let names = self
+            .characters
+            .iter()
+            .map(|character| character.name.clone())
+            .collect::<Vec<_>>();
+        let persona = self
+            .persona
+            .as_ref()
+            .map(|persona| persona.header_item(names.clone()));
+        let chat = if self.characters.is_empty() {
+            ChatPrompt::new(vec![], self.user_name()).with_system(SYSTEM_PROMPT)
+        } else {
+            let mut system = format!(
+                "This is a roleplay chat between {} and {}. Stay in character.",
+                self.user_name(),
+                names.join(", ")
+            );
+            match (mode, self.speaker()) {
+                (GenerationMode::Impersonate, _) => {
+                    system.push_str(&format!(" Write only {}'s next reply.", self.user_name()))
+                }
+                (_, Some(speaker)) => {
+                    system.push_str(&format!(" Write only {}'s next reply.", speaker.name))
+                }
+                (_, None) => {}
+            }
+            let scenario = self
+                .speaker()
+                .or(self.characters.first())
+                .and_then(|character| character.scenario.as_deref())
+                .unwrap_or_default();
+            ChatPrompt::new(
+                self.characters
+                    .iter()
+                    .map(|character| character.header_item(self.user_name()))
+                    .collect(),
+                self.user_name(),
+            )
+            .with_system(&system)
+            .with_scenario(scenario)
+            .with_examples(
+                self.characters
+                    .iter()
+                    .flat_map(|character| character.examples(self.user_name()))
+                    .collect(),
+            )
+        };
+        chat.with_persona(persona)
+            .with_lore(before, after)
+            .with_memories(
+                self.memories
+                    .iter()
+                    .map(|memory| memory.content.clone())
+                    .collect(),
+            )
+            .with_documents(
+                self.passages
+                    .iter()
+                    .enumerate()
+                    .map(|(index, passage)| passage.prompt(index + 1))
+                    .collect(),
+            )
+            .with_summary(
+                self.summary
+                    .as_ref()
+                    .map(|summary| summary.content.clone())
+                    .filter(|content| !content.trim().is_empty()),
+            )
+    }
+
+    fn system_prompt(&self, mode: GenerationMode) -> Result<String> {
+        self.chat(mode)
+            .prompt()
+            .map_err(|e| AliceError::Other(e.to_string()))
+    }
+
+    // The messages to render, with the author's note and where it went.
+    fn messages(
+        &self,
+        conversation: &Conversation,
+        mode: GenerationMode,
+    ) -> (Vec<Message>, Option<usize>) {
+        let visible = &conversation.messages[self.visible_from.min(conversation.messages.len())..];
+        let mut messages = visible
+            .iter()
+            .map(|message| {
+                let content = self.content(message);
+                let author = message
+                    .author
+                    .as_deref()
+                    .filter(|_| self.is_group())
+                    .and_then(|author| {
+                        self.characters
+                            .iter()
+                            .find(|character| character.id.to_string() == author)
+                    });
+                Message {
+                    content: match author {
+                        Some(author) => format!("{}: {}", author.name, content),
+                        None => content,
+                    },
+                    ..message.clone()
+                }
+            })
+            .collect::<Vec<_>>();
+        let Some(note) = &conversation.authors_note else {
+            return (messages, None);
+        };
+        let context = ChatPromptContext::new(
+            self.characters
+                .iter()
+                .map(|character| character.name.clone())
+                .collect(),
+            self.user_name().to_string(),
+        );
+        let content = context.substitute(&note.content);
+        let turn = Lorebook::turn(conversation);
+        // The message being continued has to stay last.
+        let open = match mode {
+            GenerationMode::Continue => messages.pop(),
+            GenerationMode::Respond | GenerationMode::Impersonate => None,
+        };
+        let index = note.inject(&mut messages, &content, turn);
+        messages.extend(open);
+        (messages, index)
+    }
+
+    // Stops the model from carrying on as another character.
+    fn stop_sequences(&self) -> Vec<String> {
+        if !self.is_group() {
+            return vec![];
+        }
+        self.characters
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| Some(*index) != self.speaker)
+            .map(|(_, character)| format!("\n{}:", character.name))
+            .collect()
+    }
+}
+
+fn prompt(
+    conversation: &Conversation,
+    scene: &Scene,
+    tools: &ToolRegistry,
+    mode: GenerationMode,
+) -> Result<String> {
+    let next_role = match mode {
+        GenerationMode::Impersonate => "user",
+        GenerationMode::Respond | GenerationMode::Continue => "assistant",
+    };
+    let chat = scene
+        .chat(mode)
+        .with_history(scene.messages(conversation, mode).0);
+    let prompt = template(next_role)
+        .with_chat(&chat)?
+        .with_tools(tools.describe())?;
+    match mode {
+        GenerationMode::Continue => prompt.with_open_last_message().render(),
+        GenerationMode::Respond | GenerationMode::Impersonate => prompt.render(),
+    }
+}
+
+fn transcript(messages: &[Message], characters: &[Character], user_name: &str) -> String {
+    messages
+        .iter()
+        .filter_map(|message| {
+            let name = match message.role.as_str() {
+                "user" => user_name,
+                "assistant" => message
+                    .author
+                    .as_deref()
+                    .and_then(|author| {
+                        characters
+                            .iter()
+                            .find(|character| character.id.to_string() == author)
+                    })
+                    .or(characters.first())
+                    .map_or("Assistant", |character| character.name.as_str()),
+                _ => return None,
+            };
+            Some(format!("{}: {}", name, message.content.trim()))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn user_name(persona: Option<&Persona>) -> &str {
+    persona.map_or(USER_NAME, |persona| persona.name.as_str())
+}
+
+fn chat_prompt(messages: Vec<Message>, system_prompt: &str, next_role: &str) -> Result<Prompt> {
+    template(next_role)
+        .with_str_var("system_prompt", system_prompt)
+        .with_messages(messages)
+}
+
+// The instruct template, for the system prompt and messages to be filled in.
+fn template(next_role: &str) -> Prompt {
+    Prompt::new(LLAMA3_PROMPT_TEMPLATE.to_string())
+        .with_str_var("system", "system")
+        .with_str_var("suffix", "<|eot_id|>")
+        .with_str_var("sequence_start", "<|start_header_id|>")
+        .with_str_var("sequence_end", "<|end_header_id|>")
+        .with_str_var("next_role", next_role)
+}
+
+async fn complete(
+    conversation: &Conversation,
+    scene: &Scene,
+    tools: &ToolRegistry,
+    mode: GenerationMode,
+    id: &str,
+) -> Result<String> {
+    let mut params = scene.params.clone();
+    params.stop_sequences.push("<|eot_id|>".to_string());
+    if !tools.is_empty() {
+        params.stop_sequences.push(TOOL_CALL_END.to_string());
+    }
+    params.stop_sequences.extend(scene.stop_sequences());
+
+    let snippet = prompt(conversation, scene, tools, mode)?;
+    let index = match mode {
+        GenerationMode::Respond => Some(conversation.messages.len()),
+        GenerationMode::Continue => conversation.messages.len().checked_sub(1),
+        GenerationMode::Impersonate => None,
+    };
+    let narration = match index {
+        Some(index) => {
+            let name = scene.speaker().map(|speaker| speaker.name.clone());
+            let voice = scene.speaker().and_then(|speaker| speaker.voice.clone());
+            match Narration::start(id.to_string(), index, name, voice).await {
+                Ok(narration) => narration,
+                Err(e) => {
+                    let _ = events::emit_speech_error(&e.to_string()).await;
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+    let id = id.to_string();
+    // Only the api stays locked while the reply streams, so the manager can still be asked about
+    // models and connections.
+    let api = api_manager!().api.clone();
+    let completion = manager::complete(
+        api,
+        &snippet,
+        &scene.images,
+        params,
+        Constraints::default(),
+        Box::new(move |tokens| {
+            if let Some(narration) = &narration {
+                narration.push(&tokens);
+            }
+            app!().emit(
+                "generation_tokens",
+                GenerationTokens {
+                    id: id.clone(),
+                    mode,
+                    tokens,
+                },
+            )?;
+            Ok(())
+        }),
+    )
+    .await?;
+    // Models often echo the name prefix they've seen on every other reply.
+    match scene.speaker() {
+        Some(speaker) if scene.is_group() && mode == GenerationMode::Respond => Ok(completion
+            .trim_start()
+            .strip_prefix(&format!("{}:", speaker.name))
+            .map(str::to_string)
+            .unwrap_or(completion)),
+        _ => Ok(completion),
+    }
+}
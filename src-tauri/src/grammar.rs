@@ -0,0 +1,476 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::prelude::*;
+
+use serde_json::{Map, Value};
+
+// Converts a JSON Schema into a llama.cpp GBNF grammar, following the same rule layout as
+// llama.cpp's own `json_schema_to_grammar`. Only the structural keywords are supported:
+// `type`, `properties`, `required`, `items`, `enum`, `const`, `oneOf`/`anyOf`, `$ref`,
+// `minItems`/`maxItems` and `minLength`/`maxLength`. Anything else is treated as unconstrained.
+pub fn json_schema_to_gbnf(schema: &Value) -> Result<String> {
+    let mut converter = Converter {
+        root: schema,
+        rules: BTreeMap::new(),
+        refs: HashMap::new(),
+    };
+    let root = converter.visit(schema, "root")?;
+    if root != "root" {
+        converter.rules.insert("root".into(), root);
+    }
+
+    let mut grammar = String::new();
+    if let Some(root) = converter.rules.remove("root") {
+        grammar.push_str(&format!("root ::= {}\n", root));
+    }
+    for (name, body) in converter.rules {
+        grammar.push_str(&format!("{} ::= {}\n", name, body));
+    }
+    Ok(grammar)
+}
+
+static PRIMITIVES: [(&str, &str); 10] = [
+    ("space", r#"| " " | "\n" [ \t]{0,20}"#),
+    ("boolean", r#"("true" | "false") space"#),
+    ("decimal-part", "[0-9]{1,16}"),
+    ("integral-part", "[0] | [1-9] [0-9]{0,15}"),
+    (
+        "number",
+        r#"("-"? integral-part) ("." decimal-part)? ([eE] [-+]? integral-part)? space"#,
+    ),
+    ("integer", r#"("-"? integral-part) space"#),
+    (
+        "char",
+        r#"[^"\\\x7F\x00-\x1F] | [\\] (["\\bfnrt] | "u" [0-9a-fA-F]{4})"#,
+    ),
+    ("string", r#""\"" char* "\"" space"#),
+    ("null", r#""null" space"#),
+    ("value", "object | array | string | number | boolean | null"),
+];
+
+static COMPOSITES: [(&str, &str); 2] = [
+    (
+        "object",
+        r#""{" space ( string ":" space value ("," space string ":" space value)* )? "}" space"#,
+    ),
+    (
+        "array",
+        r#""[" space ( value ("," space value)* )? "]" space"#,
+    ),
+];
+
+struct Converter<'a> {
+    root: &'a Value,
+    rules: BTreeMap<String, String>,
+    // The rule each `$ref` was converted to.
+    refs: HashMap<String, String>,
+}
+
+impl<'a> Converter<'a> {
+    // Adds a rule, reusing an existing one with the same body and renaming on collisions.
+    fn add_rule(&mut self, name: &str, body: String) -> String {
+        let name = sanitize(name);
+        let mut candidate = name.clone();
+        let mut i = 0;
+        while let Some(existing) = self.rules.get(&candidate) {
+            if *existing == body {
+                return candidate;
+            }
+            i += 1;
+            candidate = format!("{}{}", name, i);
+        }
+        self.rules.insert(candidate.clone(), body);
+        candidate
+    }
+
+    fn add_primitive(&mut self, name: &str) -> String {
+        if self.rules.contains_key(name) {
+            return name.to_string();
+        }
+        let body = PRIMITIVES
+            .iter()
+            .chain(COMPOSITES.iter())
+            .find(|(primitive, _)| *primitive == name)
+            .map(|(_, body)| body.to_string())
+            .unwrap_or_default();
+        self.rules.insert(name.to_string(), body.clone());
+        // Pull in every primitive the body refers to.
+        for (dependency, _) in PRIMITIVES.iter().chain(COMPOSITES.iter()) {
+            if *dependency != name && references(&body, dependency) {
+                self.add_primitive(dependency);
+            }
+        }
+        name.to_string()
+    }
+
+    fn visit(&mut self, schema: &Value, name: &str) -> Result<String> {
+        let schema = match schema {
+            Value::Bool(true) => return Ok(self.add_primitive("value")),
+            Value::Bool(false) => {
+                return Err(AliceError::InvalidSchema(
+                    "`false` schemas can't be matched".into(),
+                ))
+            }
+            Value::Object(schema) => schema,
+            _ => {
+                return Err(AliceError::InvalidSchema(format!(
+                    "expected a schema object, got {}",
+                    schema
+                )))
+            }
+        };
+
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            return self.visit_ref(reference);
+        }
+
+        if let Some(Value::Array(alternatives)) = schema.get("oneOf").or(schema.get("anyOf")) {
+            let mut rules = Vec::new();
+            for (i, alternative) in alternatives.iter().enumerate() {
+                rules.push(self.visit(alternative, &format!("{}-{}", name, i))?);
+            }
+            self.add_primitive("space");
+            return Ok(self.add_rule(name, rules.join(" | ")));
+        }
+
+        if let Some(value) = schema.get("const") {
+            self.add_primitive("space");
+            return Ok(self.add_rule(name, format!("{} space", literal(value))));
+        }
+
+        if let Some(Value::Array(values)) = schema.get("enum") {
+            self.add_primitive("space");
+            let values = values.iter().map(literal).collect::<Vec<_>>().join(" | ");
+            return Ok(self.add_rule(name, format!("({}) space", values)));
+        }
+
+        match schema.get("type") {
+            Some(Value::String(r#type)) => self.visit_type(schema, r#type, name),
+            Some(Value::Array(types)) => {
+                let mut rules = Vec::new();
+                for r#type in types.iter().filter_map(Value::as_str) {
+                    rules.push(self.visit_type(schema, r#type, &format!("{}-{}", name, r#type))?);
+                }
+                Ok(self.add_rule(name, rules.join(" | ")))
+            }
+            _ if schema.contains_key("properties") => self.visit_type(schema, "object", name),
+            _ if schema.contains_key("items") => self.visit_type(schema, "array", name),
+            _ => Ok(self.add_primitive("value")),
+        }
+    }
+
+    fn visit_ref(&mut self, reference: &str) -> Result<String> {
+        let name = reference
+            .rsplit('/')
+            .next()
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| AliceError::InvalidSchema(format!("invalid `$ref` {}", reference)))?;
+        // Already converted, or currently being converted higher up in a recursive schema.
+        if let Some(rule) = self.refs.get(reference) {
+            return Ok(rule.clone());
+        }
+        // Prefixed so definitions named like a primitive, e.g. `string`, don't replace it. The
+        // name is taken right away, before the body is known, so rules added while converting it
+        // can't claim it.
+        let base = format!("ref-{}", sanitize(name));
+        let mut rule = base.clone();
+        let mut i = 0;
+        while self.rules.contains_key(&rule) {
+            i += 1;
+            rule = format!("{}{}", base, i);
+        }
+        self.rules.insert(rule.clone(), String::new());
+        self.refs.insert(reference.to_string(), rule.clone());
+        let target = reference
+            .strip_prefix('#')
+            .and_then(|pointer| self.root.pointer(pointer))
+            .ok_or_else(|| {
+                AliceError::InvalidSchema(format!("unresolvable `$ref` {}", reference))
+            })?;
+        let body = self.visit(target, &format!("{}-inner", rule))?;
+        self.rules.insert(rule.clone(), body);
+        Ok(rule)
+    }
+
+    fn visit_type(
+        &mut self,
+        schema: &Map<String, Value>,
+        r#type: &str,
+        name: &str,
+    ) -> Result<String> {
+        match r#type {
+            "object" => match schema.get("properties") {
+                Some(Value::Object(properties)) => self.visit_object(schema, properties, name),
+                _ => Ok(self.add_primitive("object")),
+            },
+            "array" => self.visit_array(schema, name),
+            "string" => {
+                let min = schema.get("minLength").and_then(Value::as_u64);
+                let max = schema.get("maxLength").and_then(Value::as_u64);
+                if min.is_none() && max.is_none() {
+                    return Ok(self.add_primitive("string"));
+                }
+                self.add_primitive("char");
+                self.add_primitive("space");
+                Ok(self.add_rule(
+                    name,
+                    format!(
+                        r#""\"" {} "\"" space"#,
+                        repeat("char", min.unwrap_or(0), max)
+                    ),
+                ))
+            }
+            "number" | "integer" | "boolean" | "null" => Ok(self.add_primitive(r#type)),
+            _ => Err(AliceError::InvalidSchema(format!(
+                "unsupported type `{}`",
+                r#type
+            ))),
+        }
+    }
+
+    fn visit_object(
+        &mut self,
+        schema: &Map<String, Value>,
+        properties: &Map<String, Value>,
+        name: &str,
+    ) -> Result<String> {
+        let required: Vec<&str> = schema
+            .get("required")
+            .and_then(Value::as_array)
+            .map(|required| required.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+
+        self.add_primitive("space");
+        let mut required_rules = Vec::new();
+        let mut optional_rules = Vec::new();
+        for (key, property) in properties {
+            let value = self.visit(property, &format!("{}-{}", name, key))?;
+            let kv = self.add_rule(
+                &format!("{}-{}-kv", name, key),
+                format!(
+                    r#"{} space ":" space {}"#,
+                    literal(&Value::String(key.clone())),
+                    value
+                ),
+            );
+            if required.contains(&key.as_str()) {
+                required_rules.push(kv);
+            } else {
+                optional_rules.push((key.clone(), kv));
+            }
+        }
+
+        let mut body = String::from(r#""{" space "#);
+        body.push_str(&required_rules.join(r#" "," space "#));
+        if !optional_rules.is_empty() {
+            body.push_str(" (");
+            if !required_rules.is_empty() {
+                body.push_str(r#" "," space ( "#);
+            }
+            let mut alternatives = Vec::new();
+            for i in 0..optional_rules.len() {
+                alternatives.push(self.optional_tail(&optional_rules[i..], false, name));
+            }
+            body.push_str(&alternatives.join(" | "));
+            if !required_rules.is_empty() {
+                body.push_str(" )");
+            }
+            body.push_str(" )?");
+        }
+        body.push_str(r#" "}" space"#);
+        Ok(self.add_rule(name, body))
+    }
+
+    // Builds the rule for a run of optional properties, any of which may be left out, while
+    // keeping them in declaration order so the commas stay balanced.
+    fn optional_tail(&mut self, rules: &[(String, String)], optional: bool, name: &str) -> String {
+        let (key, kv) = &rules[0];
+        let mut body = if optional {
+            format!(r#"( "," space {} )?"#, kv)
+        } else {
+            kv.clone()
+        };
+        if rules.len() > 1 {
+            let rest = self.optional_tail(&rules[1..], true, name);
+            let rest = self.add_rule(&format!("{}-{}-rest", name, key), rest);
+            body.push(' ');
+            body.push_str(&rest);
+        }
+        body
+    }
+
+    fn visit_array(&mut self, schema: &Map<String, Value>, name: &str) -> Result<String> {
+        let item = match schema.get("items") {
+            Some(items) => self.visit(items, &format!("{}-item", name))?,
+            None => self.add_primitive("value"),
+        };
+        let min = schema.get("minItems").and_then(Value::as_u64).unwrap_or(0);
+        let max = schema.get("maxItems").and_then(Value::as_u64);
+        if let Some(max) = max.filter(|max| *max < min) {
+            return Err(AliceError::InvalidSchema(format!(
+                "`maxItems` {} is less than `minItems` {}",
+                max, min
+            )));
+        }
+        self.add_primitive("space");
+
+        let items = match (min, max) {
+            (_, Some(0)) => String::new(),
+            (0, max) => format!(
+                "( {} {} )?",
+                item,
+                repeat(
+                    &format!(r#"( "," space {} )"#, item),
+                    0,
+                    max.map(|max| max - 1)
+                )
+            ),
+            (min, max) => format!(
+                "{} {}",
+                item,
+                repeat(
+                    &format!(r#"( "," space {} )"#, item),
+                    min - 1,
+                    max.map(|max| max - 1)
+                )
+            ),
+        };
+        Ok(self.add_rule(name, format!(r#""[" space {} "]" space"#, items)))
+    }
+}
+
+fn repeat(rule: &str, min: u64, max: Option<u64>) -> String {
+    match (min, max) {
+        (0, None) => format!("{}*", rule),
+        (1, None) => format!("{}+", rule),
+        (min, None) => format!("{}{{{},}}", rule, min),
+        (min, Some(max)) if min == max => format!("{}{{{}}}", rule, min),
+        (min, Some(max)) => format!("{}{{{},{}}}", rule, min, max),
+    }
+}
+
+// A GBNF string literal matching the JSON encoding of `value`.
+fn literal(value: &Value) -> String {
+    let json = value.to_string();
+    let mut escaped = String::with_capacity(json.len() + 2);
+    escaped.push('"');
+    for c in json.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+// Whether `body` refers to the rule `name`, as opposed to merely containing it.
+fn references(body: &str, name: &str) -> bool {
+    body.split(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+        .any(|word| word == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn test_object() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "title": { "type": "string", "maxLength": 64 },
+                "tags": { "type": "array", "items": { "type": "string" }, "maxItems": 3 },
+                "mood": { "enum": ["happy", "sad"] }
+            },
+            "required": ["title"]
+        });
+        let grammar = json_schema_to_gbnf(&schema).unwrap();
+        assert!(grammar.starts_with("root ::= \"{\" space root-title-kv ( \",\" space ( "));
+        assert!(grammar.contains("root-title ::= \"\\\"\" char{0,64} \"\\\"\" space\n"));
+        assert!(grammar.contains(
+            "root-tags ::= \"[\" space ( string ( \",\" space string ){0,2} )? \"]\" space\n"
+        ));
+        assert!(grammar.contains("root-mood ::= (\"\\\"happy\\\"\" | \"\\\"sad\\\"\") space\n"));
+        assert!(grammar.contains("string ::= "));
+        assert!(grammar.contains("char ::= "));
+        assert!(grammar.contains("space ::= "));
+    }
+
+    #[test]
+    fn test_optional_properties() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "a": { "type": "integer" },
+                "b": { "type": "boolean" }
+            }
+        });
+        let grammar = json_schema_to_gbnf(&schema).unwrap();
+        assert!(grammar
+            .contains("root ::= \"{\" space  (root-a-kv root-a-rest | root-b-kv )? \"}\" space\n"));
+        assert!(grammar.contains("root-a-rest ::= ( \",\" space root-b-kv )?\n"));
+    }
+
+    #[test]
+    fn test_recursive_ref() {
+        let schema = json!({
+            "$ref": "#/$defs/node",
+            "$defs": {
+                "node": {
+                    "type": "object",
+                    "properties": {
+                        "children": { "type": "array", "items": { "$ref": "#/$defs/node" } }
+                    },
+                    "required": ["children"]
+                }
+            }
+        });
+        let grammar = json_schema_to_gbnf(&schema).unwrap();
+        assert!(grammar.starts_with("root ::= ref-node\n"));
+        assert!(grammar.contains("ref-node ::= ref-node-inner\n"));
+        assert!(grammar.contains(
+            "ref-node-inner-children ::= \"[\" space ( ref-node ( \",\" space ref-node )* )? \"]\" space\n"
+        ));
+    }
+
+    #[test]
+    fn test_ref_named_like_primitive() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "name": { "$ref": "#/$defs/string" },
+                "nickname": { "type": "string" }
+            },
+            "$defs": { "string": { "enum": ["Nika", "Mira"] } }
+        });
+        let grammar = json_schema_to_gbnf(&schema).unwrap();
+        assert!(grammar.contains("ref-string ::= ref-string-inner\n"));
+        assert!(grammar.contains("string ::= \"\\\"\" char* \"\\\"\" space\n"));
+    }
+
+    #[test]
+    fn test_array_bounds() {
+        let schema = json!({ "type": "array", "minItems": 3, "maxItems": 1 });
+        assert!(json_schema_to_gbnf(&schema).is_err());
+        let schema = json!({ "type": "array", "minItems": 2, "maxItems": 2 });
+        assert!(json_schema_to_gbnf(&schema).is_ok());
+    }
+
+    #[test]
+    fn test_false_schema() {
+        assert!(json_schema_to_gbnf(&Value::Bool(false)).is_err());
+    }
+}
@@ -0,0 +1,274 @@
+use crate::prelude::*;
+
+use crate::DB;
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::{attachment::media::Attachment, conversation::Conversation, generation};
+
+pub mod prompt;
+
+use prompt::{ImageParameters, ImageSubject};
+
+// An image as the backend made it.
+pub struct GeneratedImage {
+    pub bytes: Vec<u8>,
+    // The seed it was actually made with, when a random one was asked for.
+    pub seed: i64,
+}
+
+#[async_trait]
+pub trait ImageGenerator: Send + Sync {
+    async fn generate(&mut self, parameters: &ImageParameters) -> Result<GeneratedImage>;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct A1111Config {
+    pub url: String,
+}
+
+impl Default for A1111Config {
+    fn default() -> Self {
+        Self {
+            url: "http://127.0.0.1:7860".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ImageBackend {
+    A1111(A1111Config),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageGeneratorConfig {
+    pub backend: ImageBackend,
+    // Put in front of every prompt, like an art style or quality tags.
+    #[serde(default)]
+    pub style: String,
+    // Everything but the prompt, which is written for each image.
+    #[serde(default)]
+    pub parameters: ImageParameters,
+}
+
+impl Default for ImageGeneratorConfig {
+    fn default() -> Self {
+        Self {
+            backend: ImageBackend::A1111(A1111Config::default()),
+            style: String::new(),
+            parameters: ImageParameters::default(),
+        }
+    }
+}
+
+impl ImageGeneratorConfig {
+    pub async fn get() -> Result<Self> {
+        let config: Option<Self> = db!().select(("image_generator", "default")).await?;
+        Ok(config.unwrap_or_default())
+    }
+
+    pub async fn set(self) -> Result<Self> {
+        db!()
+            .upsert(("image_generator", "default"))
+            .content(self)
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "upsert".into(),
+                "image_generator".into(),
+            ))
+    }
+
+    pub fn into_generator(self) -> Box<dyn ImageGenerator> {
+        match self.backend {
+            ImageBackend::A1111(config) => Box::new(A1111Generator::new(config.url)),
+        }
+    }
+}
+
+// The prompt an image of the message at `index` would be generated with, the message itself or
+// what the model makes of it.
+pub async fn write_prompt(
+    conversation: &Conversation,
+    index: usize,
+    subject: ImageSubject,
+) -> Result<String> {
+    let message = conversation
+        .messages
+        .get(index)
+        .ok_or(AliceError::IndexOutOfBounds(index))?;
+    let prompt = match subject {
+        ImageSubject::Message => prompt::clean(&message.content),
+        ImageSubject::Scene | ImageSubject::Character => {
+            generation::image_prompt(conversation, index, subject).await?
+        }
+    };
+    match prompt.is_empty() {
+        true => Err(AliceError::NothingToPicture),
+        false => Ok(prompt),
+    }
+}
+
+// Generates an image for the message at `index` with the configured backend and attaches it to
+// the message. Without a `prompt` one is written for the `subject`.
+pub async fn generate_image(
+    id: String,
+    index: usize,
+    subject: ImageSubject,
+    prompt: Option<String>,
+) -> Result<Conversation> {
+    let conversation = Conversation::find(id.clone()).await?;
+    // Checked before the backend runs and the image is stored, which are wasted on a bad index.
+    if index >= conversation.messages.len() {
+        return Err(AliceError::IndexOutOfBounds(index));
+    }
+    let prompt = match prompt.map(|prompt| prompt.trim().to_string()) {
+        Some(prompt) if !prompt.is_empty() => prompt,
+        _ => write_prompt(&conversation, index, subject).await?,
+    };
+    let config = ImageGeneratorConfig::get().await?;
+    let mut parameters = ImageParameters {
+        prompt: prompt::styled(&config.style, &prompt),
+        ..config.parameters.clone()
+    };
+    let image = config.into_generator().generate(&parameters).await?;
+    parameters.seed = image.seed;
+    let attachment = Attachment::store(format!("image-{}.png", image.seed), &image.bytes).await?;
+    // The conversation may have changed while the image was generated.
+    Conversation::find(id)
+        .await?
+        .with_attachment(
+            index,
+            Attachment {
+                generation: Some(parameters),
+                ..attachment
+            },
+        )
+        .await
+}
+
+#[derive(Debug, Serialize)]
+struct Txt2ImgRequest<'a> {
+    prompt: &'a str,
+    negative_prompt: &'a str,
+    width: u32,
+    height: u32,
+    steps: u32,
+    cfg_scale: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sampler_name: Option<&'a str>,
+    seed: i64,
+    batch_size: u32,
+    n_iter: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct Txt2ImgResponse {
+    // Base64 PNGs.
+    images: Vec<String>,
+    // The parameters actually used, as a JSON string.
+    #[serde(default)]
+    info: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Txt2ImgInfo {
+    seed: Option<i64>,
+}
+
+// Stable Diffusion web UI's `/sdapi/v1/txt2img`, which Forge and SD.Next serve too. The web UI
+// has to be started with `--api`.
+pub struct A1111Generator {
+    client: Client,
+    url: String,
+}
+
+impl A1111Generator {
+    pub fn new(url: String) -> Self {
+        Self {
+            client: Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl ImageGenerator for A1111Generator {
+    async fn generate(&mut self, parameters: &ImageParameters) -> Result<GeneratedImage> {
+        let request = Txt2ImgRequest {
+            prompt: &parameters.prompt,
+            negative_prompt: &parameters.negative_prompt,
+            width: parameters.width,
+            height: parameters.height,
+            steps: parameters.steps,
+            cfg_scale: parameters.cfg_scale,
+            sampler_name: parameters.sampler.as_deref(),
+            seed: parameters.seed,
+            batch_size: 1,
+            n_iter: 1,
+        };
+        let response = self
+            .client
+            .post(format!("{}/sdapi/v1/txt2img", self.url))
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Txt2ImgResponse>()
+            .await?;
+        let image = response.images.first().ok_or(AliceError::NoImageReturned)?;
+        // Some versions prefix the image with its data URL header.
+        let image = image.rsplit(',').next().unwrap_or_default();
+        let info = serde_json::from_str::<Txt2ImgInfo>(&response.info).unwrap_or_default();
+        Ok(GeneratedImage {
+            bytes: STANDARD
+                .decode(image)
+                .map_err(|e| AliceError::Other(e.to_string()))?,
+            seed: info.seed.unwrap_or(parameters.seed),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::{self, StubResponse};
+
+    #[tokio::test]
+    async fn test_a1111_generator() {
+        let (url, mut requests) = testing::serve(|_| {
+            StubResponse::json(json!({
+                "images": [STANDARD.encode(b"\x89PNG\r\n\x1a\n")],
+                "parameters": {},
+                "info": json!({"seed": 1234, "all_seeds": [1234]}).to_string(),
+            }))
+        })
+        .await;
+        let mut generator = A1111Generator::new(format!("{}/", url));
+        let parameters = ImageParameters {
+            prompt: "a lake at dusk".into(),
+            sampler: Some("Euler a".into()),
+            ..ImageParameters::default()
+        };
+        let image = generator.generate(&parameters).await.unwrap();
+        assert_eq!(image.bytes, b"\x89PNG\r\n\x1a\n");
+        assert_eq!(image.seed, 1234);
+
+        let request = requests.recv().await.unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/sdapi/v1/txt2img");
+        let body = serde_json::from_slice::<serde_json::Value>(&request.body).unwrap();
+        assert_eq!(body["prompt"], "a lake at dusk");
+        assert_eq!(body["sampler_name"], "Euler a");
+        assert_eq!(body["seed"], -1);
+        assert_eq!(body["batch_size"], 1);
+
+        let (url, _) = testing::serve(|_| StubResponse::json(json!({"images": []}))).await;
+        let mut generator = A1111Generator::new(url);
+        assert!(generator.generate(&parameters).await.is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+
+// Words models like to start the prompt with, which the image generator would take literally.
+static PREAMBLES: [&str; 3] = ["image prompt:", "prompt:", "here is the prompt:"];
+
+// What an image of a message shows.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageSubject {
+    // The message's text, used as the prompt as it is.
+    Message,
+    // What's happening at that point of the conversation, described by the model.
+    Scene,
+    // What the message's author looks like, described by the model.
+    Character,
+}
+
+// Everything an image was generated with, kept on its attachment so it can be made again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageParameters {
+    pub prompt: String,
+    #[serde(default)]
+    pub negative_prompt: String,
+    pub width: u32,
+    pub height: u32,
+    pub steps: u32,
+    pub cfg_scale: f64,
+    // The backend's default when none.
+    #[serde(default)]
+    pub sampler: Option<String>,
+    // -1 for a random one. Generated images record the seed that was actually used.
+    pub seed: i64,
+}
+
+impl Default for ImageParameters {
+    fn default() -> Self {
+        Self {
+            prompt: String::new(),
+            negative_prompt: String::new(),
+            width: 512,
+            height: 512,
+            steps: 20,
+            cfg_scale: 7.0,
+            sampler: None,
+            seed: -1,
+        }
+    }
+}
+
+// What the model is asked to write a prompt with. `characters` are the names and descriptions of
+// who takes part, `transcript` the messages leading up to the pictured one, ending with it.
+pub fn request(subject: ImageSubject, characters: &[(&str, String)], transcript: &str) -> String {
+    let mut request = match subject {
+        ImageSubject::Message | ImageSubject::Scene => String::from(
+            "Write a prompt for an image generator picturing the scene at the end of the \
+             conversation below: who is there, what they look like and do, the place and the \
+             light.",
+        ),
+        ImageSubject::Character => String::from(
+            "Write a prompt for an image generator picturing the character below: their face, \
+             body, clothes and expression.",
+        ),
+    };
+    request.push_str(
+        " Answer with the prompt alone, a single line of short comma-separated descriptions, \
+         without names or dialogue.\n\n",
+    );
+    for (name, description) in characters {
+        request.push_str(&format!("{}:\n{}\n\n", name, description.trim()));
+    }
+    if subject != ImageSubject::Character {
+        request.push_str(&format!("Conversation:\n{}", transcript.trim()));
+    }
+    request.trim_end().to_string()
+}
+
+// The prompt in a message or completion, without markdown, quotes, preambles and line breaks.
+pub fn clean(text: &str) -> String {
+    let mut text = text
+        .chars()
+        .filter(|char| !matches!(char, '*' | '_' | '`' | '#'))
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    for preamble in PREAMBLES {
+        if text.to_ascii_lowercase().starts_with(preamble) {
+            text = text[preamble.len()..].trim_start().to_string();
+        }
+    }
+    text.trim_matches(|char| matches!(char, '"' | '\'' | '“' | '”'))
+        .trim()
+        .to_string()
+}
+
+// The style goes first, generators weigh the start of a prompt the most.
+pub fn styled(style: &str, prompt: &str) -> String {
+    match style.trim() {
+        "" => prompt.to_string(),
+        style => format!("{}, {}", style.trim_end_matches(','), prompt),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request() {
+        let characters = [("Nika", "Purple eyes, black hair.\n".to_string())];
+        let scene = request(ImageSubject::Scene, &characters, "Alex: Look, the lake!\n");
+        assert!(scene.contains("Nika:\nPurple eyes, black hair.\n\n"));
+        assert!(scene.ends_with("Conversation:\nAlex: Look, the lake!"));
+        let character = request(ImageSubject::Character, &characters, "Alex: Hi");
+        assert!(character.ends_with("Purple eyes, black hair."));
+        assert!(!character.contains("Alex"));
+    }
+
+    #[test]
+    fn test_clean() {
+        assert_eq!(
+            clean("Prompt: \"*girl*, purple eyes,\n  lakeside at dusk\""),
+            "girl, purple eyes, lakeside at dusk"
+        );
+        assert_eq!(clean("*She smiles.*"), "She smiles.");
+        assert_eq!(styled("anime style,", "a lake"), "anime style, a lake");
+        assert_eq!(styled(" ", "a lake"), "a lake");
+    }
+}
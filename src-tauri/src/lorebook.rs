@@ -0,0 +1,213 @@
+use crate::prelude::*;
+
+use crate::DB;
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use surrealdb::opt::PatchOp;
+use surrealdb::RecordId;
+
+use crate::{character::Character, conversation::Conversation};
+
+pub mod import;
+pub mod scan;
+
+use scan::{LoreActivation, LoreEntry, LoreSettings};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LeanLorebook {
+    pub id: RecordId,
+    pub name: String,
+    pub modified_time: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InsertableLorebook {
+    pub name: String,
+    pub created_time: DateTime<Utc>,
+    pub modified_time: DateTime<Utc>,
+    pub settings: LoreSettings,
+    pub entries: Vec<LoreEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Lorebook {
+    pub id: RecordId,
+    pub name: String,
+    pub created_time: DateTime<Utc>,
+    pub modified_time: DateTime<Utc>,
+    pub settings: LoreSettings,
+    pub entries: Vec<LoreEntry>,
+}
+
+impl InsertableLorebook {
+    pub fn new(name: String) -> Self {
+        let time = Utc::now();
+        Self {
+            name,
+            created_time: time,
+            modified_time: time,
+            settings: LoreSettings::default(),
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl Lorebook {
+    pub async fn new(name: String) -> Result<Self> {
+        db!()
+            .create("lorebook")
+            .content(InsertableLorebook::new(name))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "create".into(),
+                "lorebook".into(),
+            ))
+    }
+
+    // The lorebook of an imported character card.
+    pub async fn imported(
+        name: String,
+        settings: LoreSettings,
+        entries: Vec<LoreEntry>,
+    ) -> Result<Self> {
+        let mut lorebook = InsertableLorebook::new(name);
+        lorebook.settings = settings;
+        lorebook.entries = entries;
+        db!()
+            .create("lorebook")
+            .content(lorebook)
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "create".into(),
+                "lorebook".into(),
+            ))
+    }
+
+    pub async fn find(id: String) -> Result<Self> {
+        db!()
+            .select(("lorebook", &id))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "select".into(),
+                id.to_string(),
+            ))
+    }
+
+    pub async fn name_sorted_lean(limit: usize, offset: usize) -> Result<Vec<LeanLorebook>> {
+        let result = db!()
+            .query("SELECT id, name, modified_time FROM lorebook ORDER BY name ASC LIMIT $limit START $offset")
+            .bind(("limit", limit))
+            .bind(("offset", offset))
+            .await?
+            .take(0)?;
+        Ok(result)
+    }
+
+    pub async fn with_name(self, name: String) -> Result<Self> {
+        let db = db!();
+        db.update(self.id)
+            .patch(PatchOp::replace("/name", name))
+            .patch(PatchOp::replace("/modified_time", Utc::now()))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "lorebook".into(),
+            ))
+    }
+
+    pub async fn with_settings(self, settings: LoreSettings) -> Result<Self> {
+        let db = db!();
+        db.update(self.id)
+            .patch(PatchOp::replace("/settings", settings))
+            .patch(PatchOp::replace("/modified_time", Utc::now()))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "lorebook".into(),
+            ))
+    }
+
+    // Patterns are checked here so a typo doesn't silently disable an entry during generation.
+    pub async fn with_entries(self, entries: Vec<LoreEntry>) -> Result<Self> {
+        for entry in &entries {
+            if let Some(pattern) = &entry.regex {
+                Regex::new(pattern).map_err(|e| AliceError::InvalidLorebook(e.to_string()))?;
+            }
+        }
+        let db = db!();
+        db.update(self.id)
+            .patch(PatchOp::replace("/entries", entries))
+            .patch(PatchOp::replace("/modified_time", Utc::now()))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "lorebook".into(),
+            ))
+    }
+
+    pub async fn delete(self) -> Result<()> {
+        let deleted: Option<Self> = db!().delete(self.id).await?;
+        deleted.ok_or(AliceError::DatabaseOperation(
+            "delete".into(),
+            "lorebook".into(),
+        ))?;
+        Ok(())
+    }
+
+    // Scans every lorebook attached to the conversation or one of its characters against the
+    // latest messages. A turn is a user message, which is what stickiness and cooldowns count.
+    pub async fn activate(
+        conversation: &Conversation,
+        characters: &[Character],
+    ) -> Result<Vec<LoreActivation>> {
+        let turn = Self::turn(conversation);
+        let mut ids: Vec<&RecordId> = Vec::new();
+        for id in conversation
+            .lorebooks
+            .iter()
+            .chain(characters.iter().flat_map(|character| &character.lorebooks))
+        {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+        let mut activations = Vec::new();
+        for id in ids {
+            let lorebook: Option<Self> = db!().select(id.clone()).await?;
+            let Some(lorebook) = lorebook else {
+                continue;
+            };
+            activations.extend(scan::scan(
+                &lorebook.name,
+                &lorebook.entries,
+                &lorebook.settings,
+                &conversation.messages,
+                turn,
+                &conversation.lore_activations,
+            ));
+        }
+        Ok(activations)
+    }
+
+    pub fn turn(conversation: &Conversation) -> usize {
+        conversation
+            .messages
+            .iter()
+            .filter(|message| message.role == "user")
+            .count()
+    }
+
+    // The activation turns to remember once the activated entries have been used in a prompt.
+    pub fn remembered(
+        conversation: &Conversation,
+        activations: &[LoreActivation],
+    ) -> HashMap<String, usize> {
+        let mut remembered = conversation.lore_activations.clone();
+        remembered.extend(scan::triggered(activations, Self::turn(conversation)));
+        remembered
+    }
+}
@@ -0,0 +1,177 @@
+use crate::prelude::*;
+
+use serde::Deserialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::scan::{LoreEntry, LorePosition, LoreSettings};
+
+// The `character_book` of a V2 or V3 character card. Anything the lorebook has no use for is
+// ignored, missing fields keep the spec's defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct CharacterBook {
+    name: Option<String>,
+    scan_depth: Option<usize>,
+    token_budget: Option<usize>,
+    recursive_scanning: Option<bool>,
+    entries: Vec<BookEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct BookEntry {
+    keys: Vec<String>,
+    secondary_keys: Vec<String>,
+    // The secondary keys only count for selective entries.
+    selective: bool,
+    content: String,
+    enabled: bool,
+    case_sensitive: bool,
+    constant: bool,
+    use_regex: bool,
+    insertion_order: i64,
+    priority: Option<i64>,
+    position: Option<String>,
+}
+
+impl Default for BookEntry {
+    fn default() -> Self {
+        Self {
+            keys: Vec::new(),
+            secondary_keys: Vec::new(),
+            selective: false,
+            content: String::new(),
+            enabled: true,
+            case_sensitive: false,
+            constant: false,
+            use_regex: false,
+            insertion_order: 0,
+            priority: None,
+            position: None,
+        }
+    }
+}
+
+// A card's character book as a lorebook name, settings and entries. Entries without content are
+// dropped.
+pub fn from_character_book(book: &Value) -> Result<(Option<String>, LoreSettings, Vec<LoreEntry>)> {
+    let book: CharacterBook = serde_json::from_value(book.clone())
+        .map_err(|e| AliceError::InvalidLorebook(e.to_string()))?;
+    let defaults = LoreSettings::default();
+    let settings = LoreSettings {
+        scan_depth: book.scan_depth.unwrap_or(defaults.scan_depth),
+        token_budget: book.token_budget.unwrap_or(defaults.token_budget),
+        recursive: book.recursive_scanning.unwrap_or(defaults.recursive),
+    };
+    let entries = book
+        .entries
+        .into_iter()
+        .filter(|entry| !entry.content.trim().is_empty())
+        .map(entry)
+        .collect();
+    Ok((
+        book.name.filter(|name| !name.trim().is_empty()),
+        settings,
+        entries,
+    ))
+}
+
+fn entry(entry: BookEntry) -> LoreEntry {
+    // Constant entries are always inserted, which a pattern matching anything gives. Regex keys
+    // become one alternation.
+    let (keys, regex) = if entry.constant {
+        (Vec::new(), Some(".*".to_string()))
+    } else if entry.use_regex {
+        let patterns = entry
+            .keys
+            .iter()
+            .map(|key| format!("(?:{})", key))
+            .collect::<Vec<_>>();
+        (
+            Vec::new(),
+            Some(patterns.join("|")).filter(|_| !patterns.is_empty()),
+        )
+    } else {
+        (entry.keys, None)
+    };
+    LoreEntry {
+        id: Uuid::new_v4().to_string(),
+        keys,
+        secondary_keys: match entry.selective {
+            true => entry.secondary_keys,
+            false => Vec::new(),
+        },
+        regex,
+        content: entry.content,
+        position: match entry.position.as_deref() {
+            Some("after_char") => LorePosition::AfterCharacters,
+            _ => LorePosition::BeforeCharacters,
+        },
+        priority: entry.priority.unwrap_or(entry.insertion_order),
+        enabled: entry.enabled,
+        case_sensitive: entry.case_sensitive,
+        sticky: 0,
+        cooldown: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_character_book() {
+        let book = serde_json::json!({
+            "name": "Orchid Manor",
+            "scan_depth": 8,
+            "recursive_scanning": true,
+            "extensions": {},
+            "entries": [
+                {
+                    "keys": ["manor"],
+                    "secondary_keys": ["garden"],
+                    "selective": true,
+                    "content": "The manor has a garden.",
+                    "insertion_order": 5,
+                    "position": "after_char"
+                },
+                {
+                    "keys": ["cat"],
+                    "secondary_keys": ["ignored"],
+                    "content": "Nika is a cat.",
+                    "enabled": false,
+                    "priority": 10
+                },
+                { "keys": [], "content": "Always here.", "constant": true },
+                { "keys": ["dragons?", "wyrm"], "content": "Dragons.", "use_regex": true },
+                { "keys": ["empty"], "content": " " }
+            ]
+        });
+        let (name, settings, entries) = from_character_book(&book).unwrap();
+        assert_eq!(name.as_deref(), Some("Orchid Manor"));
+        assert_eq!(settings.scan_depth, 8);
+        assert_eq!(settings.token_budget, LoreSettings::default().token_budget);
+        assert!(settings.recursive);
+        assert_eq!(entries.len(), 4);
+
+        assert_eq!(entries[0].keys, vec!["manor"]);
+        assert_eq!(entries[0].secondary_keys, vec!["garden"]);
+        assert_eq!(entries[0].position, LorePosition::AfterCharacters);
+        assert_eq!(entries[0].priority, 5);
+        assert!(entries[0].enabled);
+
+        assert!(entries[1].secondary_keys.is_empty());
+        assert_eq!(entries[1].priority, 10);
+        assert!(!entries[1].enabled);
+
+        assert_eq!(entries[2].regex.as_deref(), Some(".*"));
+        assert_eq!(entries[3].regex.as_deref(), Some("(?:dragons?)|(?:wyrm)"));
+        assert!(entries[3].keys.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_character_book() {
+        assert!(from_character_book(&serde_json::json!({ "entries": "none" })).is_err());
+    }
+}
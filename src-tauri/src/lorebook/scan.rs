@@ -0,0 +1,466 @@
+use std::collections::HashMap;
+
+use regex::RegexBuilder;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{models::message::Message, wpp::format::estimate_tokens};
+
+// How many times the content of activated entries is rescanned for further triggers.
+static MAX_RECURSION: usize = 3;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LorePosition {
+    // Before the character definitions in the header.
+    #[default]
+    BeforeCharacters,
+    // After the definitions and the scenario.
+    AfterCharacters,
+}
+
+fn new_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
+fn enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoreEntry {
+    #[serde(default = "new_id")]
+    pub id: String,
+    #[serde(default)]
+    pub keys: Vec<String>,
+    // When set, at least one of these has to match as well as a key.
+    #[serde(default)]
+    pub secondary_keys: Vec<String>,
+    // Matched in addition to the keys.
+    #[serde(default)]
+    pub regex: Option<String>,
+    pub content: String,
+    #[serde(default)]
+    pub position: LorePosition,
+    // Higher goes first, and is the last to be dropped when over the token budget.
+    #[serde(default)]
+    pub priority: i64,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    // Turns the entry stays active after triggering.
+    #[serde(default)]
+    pub sticky: usize,
+    // Turns the entry can't trigger again once it's no longer active.
+    #[serde(default)]
+    pub cooldown: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoreSettings {
+    // How many of the last messages are scanned for triggers.
+    pub scan_depth: usize,
+    // Upper bound on the tokens injected from this lorebook, 0 for no bound.
+    pub token_budget: usize,
+    // Whether activated entries' content can trigger further entries.
+    pub recursive: bool,
+}
+
+impl Default for LoreSettings {
+    fn default() -> Self {
+        Self {
+            scan_depth: 4,
+            token_budget: 512,
+            recursive: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ActivationReason {
+    Keyword { key: String },
+    Regex { pattern: String },
+    // Still active from an earlier turn.
+    Sticky { remaining: usize },
+    // Triggered by the content of another activated entry.
+    Recursive { key: String, from: String },
+}
+
+// An entry that triggered, and whether it made it into the prompt. Entries left out because of
+// the token budget are kept so the prompt preview can show why they're missing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoreActivation {
+    pub entry: String,
+    pub lorebook: String,
+    pub content: String,
+    pub position: LorePosition,
+    pub priority: i64,
+    pub reason: ActivationReason,
+    pub tokens: usize,
+    pub included: bool,
+}
+
+impl LoreEntry {
+    // The key or pattern that matches the text, if any.
+    fn matches(&self, text: &str) -> Option<ActivationReason> {
+        let key = self
+            .keys
+            .iter()
+            .find(|key| contains_word(text, key, self.case_sensitive));
+        let reason = match key {
+            Some(key) => ActivationReason::Keyword { key: key.clone() },
+            None => {
+                let pattern = self.regex.as_ref()?;
+                let regex = RegexBuilder::new(pattern)
+                    .case_insensitive(!self.case_sensitive)
+                    .build()
+                    .ok()?;
+                if !regex.is_match(text) {
+                    return None;
+                }
+                ActivationReason::Regex {
+                    pattern: pattern.clone(),
+                }
+            }
+        };
+        let secondary = self.secondary_keys.is_empty()
+            || self
+                .secondary_keys
+                .iter()
+                .any(|key| contains_word(text, key, self.case_sensitive));
+        secondary.then_some(reason)
+    }
+}
+
+// Whether `key` appears in `text` as a whole word, e.g. `cat` matches "a cat." but not "catalog".
+fn contains_word(text: &str, key: &str, case_sensitive: bool) -> bool {
+    let key = key.trim();
+    if key.is_empty() {
+        return false;
+    }
+    let (text, key) = if case_sensitive {
+        (text.to_string(), key.to_string())
+    } else {
+        (text.to_lowercase(), key.to_lowercase())
+    };
+    let is_word = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+    text.match_indices(&key).any(|(start, found)| {
+        !is_word(text[..start].chars().next_back())
+            && !is_word(text[start + found.len()..].chars().next())
+    })
+}
+
+// Which entries of a lorebook are active this turn. `activations` holds the turn each entry last
+// triggered on, which drives stickiness and cooldowns.
+pub fn scan(
+    lorebook: &str,
+    entries: &[LoreEntry],
+    settings: &LoreSettings,
+    messages: &[Message],
+    turn: usize,
+    activations: &HashMap<String, usize>,
+) -> Vec<LoreActivation> {
+    let window = messages
+        .iter()
+        .rev()
+        .take(settings.scan_depth)
+        .rev()
+        .map(|message| message.content.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut active: Vec<(&LoreEntry, ActivationReason)> = Vec::new();
+    let mut candidates = Vec::new();
+    for entry in entries.iter().filter(|entry| entry.enabled) {
+        // An entry is active at least on the turn it triggered, so scanning that turn again
+        // doesn't find it on cooldown.
+        let active_for = entry.sticky.max(1);
+        match activations.get(&entry.id) {
+            Some(&last) if turn < last + entry.sticky => active.push((
+                entry,
+                ActivationReason::Sticky {
+                    remaining: last + entry.sticky - turn,
+                },
+            )),
+            Some(&last)
+                if turn >= last + active_for && turn < last + active_for + entry.cooldown => {}
+            _ => match entry.matches(&window) {
+                Some(reason) => active.push((entry, reason)),
+                None => candidates.push(entry),
+            },
+        }
+    }
+
+    if settings.recursive {
+        let mut scanned = 0;
+        for _ in 0..MAX_RECURSION {
+            let triggered = active[scanned..]
+                .iter()
+                .map(|(entry, _)| *entry)
+                .collect::<Vec<_>>();
+            scanned = active.len();
+            for from in triggered {
+                candidates.retain(|entry| match entry.matches(&from.content) {
+                    Some(reason) => {
+                        let key = match reason {
+                            ActivationReason::Keyword { key } => key,
+                            ActivationReason::Regex { pattern } => pattern,
+                            _ => unreachable!("matches only returns keywords and patterns"),
+                        };
+                        active.push((
+                            *entry,
+                            ActivationReason::Recursive {
+                                key,
+                                from: from.id.clone(),
+                            },
+                        ));
+                        false
+                    }
+                    None => true,
+                });
+            }
+            if scanned == active.len() {
+                break;
+            }
+        }
+    }
+
+    active.sort_by_key(|(entry, _)| std::cmp::Reverse(entry.priority));
+    let mut spent = 0;
+    active
+        .into_iter()
+        .map(|(entry, reason)| {
+            let tokens = estimate_tokens(&entry.content);
+            let included = settings.token_budget == 0 || spent + tokens <= settings.token_budget;
+            if included {
+                spent += tokens;
+            }
+            LoreActivation {
+                entry: entry.id.clone(),
+                lorebook: lorebook.to_string(),
+                content: entry.content.clone(),
+                position: entry.position,
+                priority: entry.priority,
+                reason,
+                tokens,
+                included,
+            }
+        })
+        .collect()
+}
+
+// The turn every freshly triggered entry should be remembered with. Sticky entries keep the turn
+// they first triggered on, so they expire on time.
+pub fn triggered(activations: &[LoreActivation], turn: usize) -> HashMap<String, usize> {
+    activations
+        .iter()
+        .filter(|activation| activation.included)
+        .filter(|activation| !matches!(activation.reason, ActivationReason::Sticky { .. }))
+        .map(|activation| (activation.entry.clone(), turn))
+        .collect()
+}
+
+// The included entries' contents, split by where they go in the header.
+pub fn contents(activations: &[LoreActivation]) -> (Vec<String>, Vec<String>) {
+    let (before, after): (Vec<_>, Vec<_>) = activations
+        .iter()
+        .filter(|activation| activation.included)
+        .partition(|activation| activation.position == LorePosition::BeforeCharacters);
+    let content = |activations: Vec<&LoreActivation>| {
+        activations
+            .into_iter()
+            .map(|activation| activation.content.clone())
+            .collect()
+    };
+    (content(before), content(after))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn entry(id: &str, keys: &[&str], content: &str) -> LoreEntry {
+        LoreEntry {
+            id: id.into(),
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+            secondary_keys: vec![],
+            regex: None,
+            content: content.into(),
+            position: LorePosition::default(),
+            priority: 0,
+            enabled: true,
+            case_sensitive: false,
+            sticky: 0,
+            cooldown: 0,
+        }
+    }
+
+    fn messages(contents: &[&str]) -> Vec<Message> {
+        contents
+            .iter()
+            .map(|content| Message {
+                timestamp: Utc::now(),
+                role: "user".into(),
+                content: content.to_string(),
+                author: None,
+                citations: Vec::new(),
+                attachments: Vec::new(),
+            })
+            .collect()
+    }
+
+    fn ids(activations: &[LoreActivation]) -> Vec<&str> {
+        activations
+            .iter()
+            .map(|activation| activation.entry.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_keywords() {
+        let mut secondary = entry("secondary", &["tower"], "Needs both.");
+        secondary.secondary_keys = vec!["madou".into()];
+        let mut regex = entry("regex", &[], "Matched by pattern.");
+        regex.regex = Some(r"floor \d+".into());
+        let entries = vec![
+            entry("word", &["Cat"], "Cats are liquid."),
+            entry("partial", &["cat"], "Shouldn't match catalog."),
+            secondary,
+            regex,
+        ];
+        let settings = LoreSettings::default();
+
+        let found = scan(
+            "book",
+            &entries,
+            &settings,
+            &messages(&["Look, a cat!", "The tower on FLOOR 12."]),
+            1,
+            &HashMap::new(),
+        );
+        assert_eq!(ids(&found), vec!["word", "partial", "regex"]);
+
+        let found = scan(
+            "book",
+            &entries[1..3],
+            &settings,
+            &messages(&["A catalog of the Madou tower."]),
+            1,
+            &HashMap::new(),
+        );
+        assert_eq!(ids(&found), vec!["secondary"]);
+    }
+
+    #[test]
+    fn test_scan_depth() {
+        let entries = vec![entry("old", &["dragon"], "Dragons exist.")];
+        let settings = LoreSettings {
+            scan_depth: 1,
+            ..Default::default()
+        };
+        let found = scan(
+            "book",
+            &entries,
+            &settings,
+            &messages(&["A dragon!", "Anyway."]),
+            1,
+            &HashMap::new(),
+        );
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn test_recursion() {
+        let entries = vec![
+            entry("tower", &["tower"], "The Madou tower is home to the guild."),
+            entry("guild", &["guild"], "The guild trains mages."),
+            entry("mage", &["mages"], "Mages use mana."),
+        ];
+        let mut settings = LoreSettings::default();
+        let chat = messages(&["Where is the tower?"]);
+        let found = scan("book", &entries, &settings, &chat, 1, &HashMap::new());
+        assert_eq!(ids(&found), vec!["tower"]);
+
+        settings.recursive = true;
+        let found = scan("book", &entries, &settings, &chat, 1, &HashMap::new());
+        assert_eq!(ids(&found), vec!["tower", "guild", "mage"]);
+        assert_eq!(
+            found[2].reason,
+            ActivationReason::Recursive {
+                key: "mages".into(),
+                from: "guild".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_priority_and_budget() {
+        let mut important = entry("important", &["tower"], "Short.");
+        important.priority = 10;
+        let entries = vec![entry("long", &["tower"], &"word ".repeat(20)), important];
+        let settings = LoreSettings {
+            token_budget: 10,
+            ..Default::default()
+        };
+        let found = scan(
+            "book",
+            &entries,
+            &settings,
+            &messages(&["The tower."]),
+            1,
+            &HashMap::new(),
+        );
+        assert_eq!(ids(&found), vec!["important", "long"]);
+        assert!(found[0].included);
+        assert!(!found[1].included);
+        assert_eq!(triggered(&found, 1).len(), 1);
+    }
+
+    #[test]
+    fn test_sticky_and_cooldown() {
+        let mut dragon = entry("dragon", &["dragon"], "Dragons exist.");
+        dragon.sticky = 2;
+        dragon.cooldown = 2;
+        let entries = vec![dragon];
+        let settings = LoreSettings::default();
+        let quiet = messages(&["Nothing here."]);
+        let loud = messages(&["A dragon!"]);
+        let activations = HashMap::from([("dragon".to_string(), 5)]);
+
+        // Active on turns 5 and 6 whatever is said, on cooldown for 7 and 8.
+        let found = scan("book", &entries, &settings, &quiet, 6, &activations);
+        assert_eq!(found[0].reason, ActivationReason::Sticky { remaining: 1 });
+        assert!(triggered(&found, 6).is_empty());
+        assert!(scan("book", &entries, &settings, &loud, 8, &activations).is_empty());
+        assert_eq!(
+            ids(&scan("book", &entries, &settings, &loud, 9, &activations)),
+            vec!["dragon"]
+        );
+    }
+
+    #[test]
+    fn test_cooldown_same_turn() {
+        let mut dragon = entry("dragon", &["dragon"], "Dragons exist.");
+        dragon.cooldown = 2;
+        let entries = vec![dragon];
+        let settings = LoreSettings::default();
+        let loud = messages(&["A dragon!"]);
+        let activations = HashMap::from([("dragon".to_string(), 5)]);
+
+        // Scanning turn 5 again, say after a regeneration, still finds it. Turns 6 and 7 are the
+        // cooldown.
+        assert_eq!(
+            ids(&scan("book", &entries, &settings, &loud, 5, &activations)),
+            vec!["dragon"]
+        );
+        assert!(scan("book", &entries, &settings, &loud, 7, &activations).is_empty());
+        assert_eq!(
+            ids(&scan("book", &entries, &settings, &loud, 8, &activations)),
+            vec!["dragon"]
+        );
+    }
+}
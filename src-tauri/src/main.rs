@@ -46,15 +46,32 @@ macro_rules! db {
 }
 
 mod api;
+mod attachment;
+mod browsing;
+mod character;
 mod commands;
 mod config;
 mod conversation;
+mod document;
 mod events;
+mod generation;
+mod grammar;
+mod imaging;
+mod lorebook;
+mod memory;
 mod models;
+mod persona;
+mod png;
 mod prelude;
+mod preset;
 mod responses;
+mod speech;
+#[cfg(test)]
+mod testing;
+mod tools;
 // mod sockets;
 mod manager;
+mod vector;
 mod wpp;
 
 static DATA_DIR: &str = "alice";
@@ -67,19 +84,29 @@ static DB: OnceLock<Surreal<Db>> = OnceLock::new();
 static LLAMA3_PROMPT_TEMPLATE: &str =
 r#"{{{sequence_start}}}{{{system}}}{{{sequence_end}}}
 
-{{{system_prompt}}}{{{suffix}}}{{#each messages}}{{{../sequence_start}}}{{{this.author}}}{{{../sequence_end}}}
+{{{system_prompt}}}{{#if tools}}
 
-{{{this.content}}}{{{../suffix}}}{{/each}}{{{sequence_start}}}{{{next_role}}}{{{sequence_end}}}
-"#;
+You can call the following tools. To call one, reply with <tool_call>{"name": "tool name", "arguments": {...}}</tool_call> and wait for the result.
+{{#each tools}}
+- {{{this.name}}}: {{{this.description}}} Arguments schema: {{{this.parameters}}}
+{{/each}}{{/if}}{{{suffix}}}{{#each messages}}{{{../sequence_start}}}{{{this.role}}}{{{../sequence_end}}}
 
-#[tokio::main]
-async fn main() -> Result<()> {
+{{{this.content}}}{{#unless (and @last ../open_last_message)}}{{{../suffix}}}{{/unless}}{{/each}}{{#unless open_last_message}}{{{sequence_start}}}{{{next_role}}}{{{sequence_end}}}
+{{/unless}}"#;
+
+// Where the database and imported files live, `$XDG_DATA_HOME/alice` by default.
+pub fn data_dir() -> String {
     let expanded_data_xdg_data = std::env::var("XDG_DATA_HOME").unwrap_or_else(|_| {
         let mut path = std::env::var("HOME").unwrap();
         path.push_str("/.local/share");
         path
     });
-    let db_path = format!("{}/{}/db", expanded_data_xdg_data, DATA_DIR);
+    format!("{}/{}", expanded_data_xdg_data, DATA_DIR)
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let db_path = format!("{}/db", data_dir());
     let db = Surreal::new::<RocksDb>(db_path).await?;
     db.use_ns("alice").await?;
     db.use_db("local").await?;
@@ -105,6 +132,7 @@ async fn main() -> Result<()> {
             commands::models::status,
             commands::models::load_model,
             commands::models::unload_model,
+            commands::models::validate_parameters,
             // Conversation commands
             commands::conversation::new_conversation,
             commands::conversation::conversations_date_sorted,
@@ -113,7 +141,92 @@ async fn main() -> Result<()> {
             commands::conversation::new_message,
             commands::conversation::delete_message,
             commands::conversation::with_replaced_message,
-
+            commands::conversation::set_conversation_preset,
+            commands::conversation::set_conversation_persona,
+            commands::conversation::set_conversation_authors_note,
+            commands::conversation::conversation_summary,
+            commands::conversation::set_conversation_summary,
+            commands::conversation::set_conversation_memory,
+            commands::conversation::forget_conversation_memories,
+            commands::conversation::set_conversation_lorebooks,
+            commands::conversation::set_conversation_participants,
+            commands::conversation::set_conversation_greeting,
+            commands::conversation::set_participant_settings,
+            commands::conversation::set_conversation_turn_order,
+            // Attachment commands
+            commands::attachment::import_attachment,
+            commands::attachment::remove_orphaned_attachments,
+            // Generation commands
+            commands::generation::generate,
+            commands::generation::continue_generation,
+            commands::generation::impersonate,
+            commands::generation::preview_prompt,
+            commands::generation::next_speaker,
+            commands::generation::list_tools,
+            // Character commands
+            commands::character::new_character,
+            commands::character::characters_sorted,
+            commands::character::find_character,
+            commands::character::update_character,
+            commands::character::set_character_lorebooks,
+            commands::character::set_character_voice,
+            commands::character::delete_character,
+            commands::character::import_character_card,
+            commands::character::export_character_png,
+            commands::character::export_character_json,
+            commands::character::validate_definition,
+            commands::character::convert_definition,
+            commands::character::definition_variants,
+            commands::character::edit_character_definition,
+            commands::character::diff_character_definitions,
+            // Preset commands
+            commands::preset::new_preset,
+            commands::preset::import_preset,
+            commands::preset::presets_name_sorted,
+            commands::preset::find_preset,
+            commands::preset::set_preset_name,
+            commands::preset::set_preset_parameters,
+            commands::preset::delete_preset,
+            commands::preset::model_preset,
+            commands::preset::set_model_preset,
+            // Persona commands
+            commands::persona::new_persona,
+            commands::persona::personas_name_sorted,
+            commands::persona::find_persona,
+            commands::persona::update_persona,
+            commands::persona::delete_persona,
+            commands::persona::default_persona,
+            commands::persona::set_default_persona,
+            // Lorebook commands
+            commands::lorebook::new_lorebook,
+            commands::lorebook::lorebooks_name_sorted,
+            commands::lorebook::find_lorebook,
+            commands::lorebook::set_lorebook_name,
+            commands::lorebook::set_lorebook_settings,
+            commands::lorebook::set_lorebook_entries,
+            commands::lorebook::delete_lorebook,
+            // Document commands
+            commands::document::import_document,
+            commands::document::documents_name_sorted,
+            commands::document::find_document,
+            commands::document::delete_document,
+            // Speech commands
+            commands::speech::transcribe,
+            commands::speech::transcriber_config,
+            commands::speech::set_transcriber_config,
+            commands::speech::speak_message,
+            commands::speech::synthesizer_config,
+            commands::speech::set_synthesizer_config,
+            // Browsing commands
+            commands::browsing::fetch_page,
+            commands::browsing::web_search,
+            commands::browsing::browsing_config,
+            commands::browsing::set_browsing_config,
+            // Image commands
+            commands::imaging::write_image_prompt,
+            commands::imaging::generate_image,
+            commands::imaging::image_generator_config,
+            commands::imaging::set_image_generator_config,
         ])
         .run(tauri::generate_context!())
         .map_err(|e| anyhow::anyhow!("Failed to run tauri: {}", e))?;
@@ -122,6 +235,9 @@ async fn main() -> Result<()> {
         timestamp: Utc::now(),
         role: "user".to_string(),
         content: "Where is the Madou tower?".to_string(),
+        author: None,
+        citations: Vec::new(),
+        attachments: Vec::new(),
     }];
 
     let prompt = Prompt::new(LLAMA3_PROMPT_TEMPLATE.to_string())
@@ -1,4 +1,10 @@
-use crate::{api::Api, events, prelude::*};
+use crate::{
+    api::Api,
+    attachment::media::ImageData,
+    events,
+    models::{constraints::Constraints, parameters::EngineParameters},
+    prelude::*,
+};
 
 use std::{fmt::Debug, sync::Arc, time::Duration};
 
@@ -72,6 +78,25 @@ impl Manager {
         Ok(())
     }
 
+    pub async fn complete(
+        &self,
+        snippet: &str,
+        images: &[ImageData],
+        engine_parameters: EngineParameters,
+        constraints: Constraints,
+        streaming_callback: Box<dyn Fn(String) -> Result<()> + Send + Sync>,
+    ) -> Result<String> {
+        complete(
+            self.api.clone(),
+            snippet,
+            images,
+            engine_parameters,
+            constraints,
+            streaming_callback,
+        )
+        .await
+    }
+
     pub async fn stop_keep_alive(&mut self) -> Result<()> {
         // Notify the current task to stop.
         if let Some(tx) = self.keep_alive_stop_signal.lock().await.take() {
@@ -91,3 +116,126 @@ impl Debug for Manager {
         f.debug_struct("Manager").finish()
     }
 }
+
+// Validates the parameters and strips samplers the loaded engine doesn't support before
+// handing the completion to the api. Constraints are translated to what the engine accepts.
+// Background jobs call this with a clone of the manager's api, so the manager isn't locked for
+// the whole completion.
+pub async fn complete(
+    api: Arc<Mutex<dyn Api>>,
+    snippet: &str,
+    images: &[ImageData],
+    engine_parameters: EngineParameters,
+    constraints: Constraints,
+    streaming_callback: Box<dyn Fn(String) -> Result<()> + Send + Sync>,
+) -> Result<String> {
+    engine_parameters
+        .validate()
+        .map_err(AliceError::InvalidParameters)?;
+    let mut api = api.lock().await;
+    let (engine_parameters, constraints) = match api.status().await? {
+        Some(model) => {
+            let (engine_parameters, stripped) = engine_parameters.for_engine(model.engine);
+            if !stripped.is_empty() {
+                let _ = events::emit_stripped_parameters(&stripped).await;
+            }
+            (engine_parameters, constraints.for_engine(model.engine)?)
+        }
+        None => (engine_parameters, constraints),
+    };
+    api.complete(
+        snippet,
+        images,
+        engine_parameters,
+        constraints,
+        streaming_callback,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::{models::model::Model, preset::import};
+
+    // Answers every completion with the sampling mode it was asked for.
+    struct StubApi;
+
+    #[async_trait]
+    impl Api for StubApi {
+        async fn connect(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn disconnect(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn is_alive(&mut self) -> Result<bool> {
+            Ok(true)
+        }
+
+        async fn load(
+            &mut self,
+            _model: &Model,
+            _preload_callback: Box<dyn Fn(String) -> Result<()> + Send + Sync>,
+        ) -> Result<String> {
+            Ok(String::new())
+        }
+
+        async fn unload(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn status(&mut self) -> Result<Option<Model>> {
+            Ok(None)
+        }
+
+        async fn list(&mut self) -> Result<Vec<Model>> {
+            Ok(vec![])
+        }
+
+        async fn complete(
+            &mut self,
+            _snippet: &str,
+            _images: &[ImageData],
+            engine_parameters: EngineParameters,
+            _constraints: Constraints,
+            _streaming_callback: Box<dyn Fn(String) -> Result<()> + Send + Sync>,
+        ) -> Result<String> {
+            Ok(match (engine_parameters.mirostat, engine_parameters.bs) {
+                (true, _) => "mirostat".into(),
+                (_, true) => "beams".into(),
+                _ => "sampled".into(),
+            })
+        }
+
+        async fn embed(&mut self, _texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(vec![])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_complete_with_imported_presets() {
+        let api: Arc<Mutex<dyn Api>> = Arc::new(Mutex::new(StubApi));
+        for (preset, mode) in [
+            (r#"{"mirostat_mode": 2, "mirostat_tau": 4}"#, "mirostat"),
+            (r#"{"num_beams": 4, "length_penalty": 1.2}"#, "beams"),
+        ] {
+            let params = import::from_json(preset).unwrap();
+            let completion = complete(
+                api.clone(),
+                "Hi",
+                &[],
+                params,
+                Constraints::default(),
+                Box::new(|_| Ok(())),
+            )
+            .await
+            .unwrap();
+            assert_eq!(completion, mode);
+        }
+    }
+}
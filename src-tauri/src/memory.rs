@@ -0,0 +1,164 @@
+use crate::prelude::*;
+
+use crate::{API_MANAGER, DB};
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::RecordId;
+
+use crate::conversation::Conversation;
+use crate::vector::{self, OVERFETCH, QUERY_MESSAGES};
+
+pub mod chunk;
+
+use chunk::MAX_CHUNK_TOKENS;
+
+// A chunk of messages and its embedding.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InsertableMemory {
+    pub conversation: RecordId,
+    pub start: usize,
+    pub end: usize,
+    // Hashes of the messages it holds.
+    pub messages: Vec<String>,
+    pub content: String,
+    pub embedding: Vec<f32>,
+    pub created_time: DateTime<Utc>,
+}
+
+// A memory found for the current prompt, and how similar it is to the latest messages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recollection {
+    pub conversation: RecordId,
+    pub start: usize,
+    pub end: usize,
+    #[serde(default)]
+    pub messages: Vec<String>,
+    pub content: String,
+    pub score: f32,
+}
+
+pub struct Memory;
+
+impl Memory {
+    // Embeds the messages that aren't memorized yet and stores them as memories. Memories of
+    // messages that were edited or deleted since are dropped first, so they're memorized again as
+    // they are now.
+    pub async fn memorize(conversation: &Conversation) -> Result<()> {
+        if !conversation.memory.enabled {
+            return Ok(());
+        }
+        let current = conversation
+            .messages
+            .iter()
+            .map(chunk::hash)
+            .collect::<Vec<_>>();
+        let memorized: Vec<Option<Vec<String>>> = db!()
+            .query("DELETE memory WHERE conversation = $conversation AND !(messages ALLINSIDE $current)")
+            .query("SELECT VALUE messages FROM memory WHERE conversation = $conversation")
+            .bind(("conversation", conversation.id.clone()))
+            .bind(("current", current))
+            .await?
+            .take(1)?;
+        let memorized = memorized
+            .into_iter()
+            .flatten()
+            .flatten()
+            .collect::<HashSet<_>>();
+        let chunks = chunk::chunks(&conversation.messages, &memorized, MAX_CHUNK_TOKENS);
+        if chunks.is_empty() {
+            return Ok(());
+        }
+        let texts = chunks
+            .iter()
+            .map(|chunk| chunk.text.clone())
+            .collect::<Vec<_>>();
+        let embeddings = api!().embed(&texts).await?;
+        if embeddings.len() != chunks.len() {
+            return Err(AliceError::Other(format!(
+                "Expected {} embeddings, got {}",
+                chunks.len(),
+                embeddings.len()
+            )));
+        }
+        // Memories embedded with a model of another size are deleted with the index, the next
+        // reply memorizes their messages again.
+        if let Some(embedding) = embeddings.first() {
+            vector::define_index("memory", embedding.len()).await?;
+        }
+        let time = Utc::now();
+        let memories = chunks
+            .into_iter()
+            .zip(embeddings)
+            .map(|(chunk, embedding)| InsertableMemory {
+                conversation: conversation.id.clone(),
+                start: chunk.start,
+                end: chunk.end,
+                messages: chunk.messages,
+                content: chunk.text,
+                embedding,
+                created_time: time,
+            })
+            .collect::<Vec<_>>();
+        let _: Vec<InsertableMemory> = db!().insert("memory").content(memories).await?;
+        Ok(())
+    }
+
+    // The memories most relevant to the latest messages. Memories of this conversation's messages
+    // from `visible_from` on are already in the prompt, so they're left out after the search.
+    pub async fn recall(
+        conversation: &Conversation,
+        visible_from: usize,
+    ) -> Result<Vec<Recollection>> {
+        let settings = &conversation.memory;
+        if !settings.enabled || settings.top_k == 0 {
+            return Ok(vec![]);
+        }
+        let query = chunk::query(&conversation.messages, QUERY_MESSAGES);
+        if query.trim().is_empty() {
+            return Ok(vec![]);
+        }
+        let Some(embedding) = api!().embed(&[query]).await?.pop() else {
+            return Ok(vec![]);
+        };
+        let sql = vector::knn(
+            "memory",
+            "conversation, start, end, messages, content",
+            settings.top_k * OVERFETCH,
+            (!settings.shared).then_some("conversation = $conversation"),
+        );
+        let recollections: Vec<Recollection> = db!()
+            .query(sql)
+            .bind(("embedding", embedding))
+            .bind(("conversation", conversation.id.clone()))
+            .await?
+            .take(0)?;
+        let visible = conversation.messages[visible_from.min(conversation.messages.len())..]
+            .iter()
+            .map(chunk::hash)
+            .collect::<HashSet<_>>();
+        Ok(recollections
+            .into_iter()
+            .filter(|recollection| recollection.score >= settings.min_score)
+            .filter(|recollection| {
+                recollection.conversation != conversation.id
+                    || !recollection
+                        .messages
+                        .iter()
+                        .any(|message| visible.contains(message))
+            })
+            .take(settings.top_k)
+            .collect())
+    }
+
+    pub async fn forget(conversation: Conversation) -> Result<Conversation> {
+        db!()
+            .query("DELETE memory WHERE conversation = $conversation")
+            .bind(("conversation", conversation.id.clone()))
+            .await?
+            .check()?;
+        Ok(conversation)
+    }
+}
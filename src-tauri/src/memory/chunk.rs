@@ -0,0 +1,190 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{models::message::Message, wpp::format::estimate_tokens};
+
+// Upper bound on the tokens in a chunk, so each embedding covers roughly one exchange.
+pub static MAX_CHUNK_TOKENS: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MemorySettings {
+    // Whether messages are memorized and memories recalled. Off by default, not every backend
+    // serves embeddings.
+    pub enabled: bool,
+    // How many memories are recalled into the prompt.
+    pub top_k: usize,
+    // Cosine similarity below which a memory isn't relevant enough to recall.
+    pub min_score: f32,
+    // Whether memories from other conversations can be recalled too.
+    pub shared: bool,
+}
+
+impl Default for MemorySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            top_k: 4,
+            min_score: 0.5,
+            shared: true,
+        }
+    }
+}
+
+// Consecutive messages embedded together, `start..end` being their indices in the conversation
+// when they were memorized and `messages` their hashes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    pub messages: Vec<String>,
+}
+
+// Identifies a message by what it says and when, so an edited message is memorized again and the
+// memories of a deleted one can be told apart.
+pub fn hash(message: &Message) -> String {
+    format!(
+        "{:x}",
+        Sha256::digest(format!(
+            "{}\n{}\n{}\n{}",
+            message.timestamp.to_rfc3339(),
+            message.role,
+            message.author.as_deref().unwrap_or_default(),
+            message.content
+        ))
+    )
+}
+
+// Splits the messages not `memorized` yet, by hash, into chunks of at most `max_tokens`. Only the
+// user's and the characters' messages are memorized, and a message longer than the limit is a
+// chunk of its own. A memorized message between two new ones ends the chunk.
+pub fn chunks(messages: &[Message], memorized: &HashSet<String>, max_tokens: usize) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut current: Option<(Chunk, usize)> = None;
+    for (index, message) in messages.iter().enumerate() {
+        if message.role != "user" && message.role != "assistant" {
+            continue;
+        }
+        let hash = hash(message);
+        if memorized.contains(&hash) {
+            chunks.extend(current.take().map(|(chunk, _)| chunk));
+            continue;
+        }
+        let line = format!("{}: {}", message.role, message.content.trim());
+        let tokens = estimate_tokens(&line);
+        match current.as_mut() {
+            Some((chunk, used)) if *used + tokens <= max_tokens => {
+                chunk.text.push('\n');
+                chunk.text.push_str(&line);
+                chunk.end = index + 1;
+                chunk.messages.push(hash);
+                *used += tokens;
+            }
+            _ => {
+                chunks.extend(current.take().map(|(chunk, _)| chunk));
+                current = Some((
+                    Chunk {
+                        start: index,
+                        end: index + 1,
+                        text: line,
+                        messages: vec![hash],
+                    },
+                    tokens,
+                ));
+            }
+        }
+    }
+    chunks.extend(current.map(|(chunk, _)| chunk));
+    chunks
+}
+
+// What to look up memories with: the last few messages, most recent last.
+pub fn query(messages: &[Message], count: usize) -> String {
+    messages
+        .iter()
+        .rev()
+        .filter(|message| message.role == "user" || message.role == "assistant")
+        .take(count)
+        .map(|message| message.content.trim())
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn message(role: &str, content: &str) -> Message {
+        Message {
+            timestamp: Utc::now(),
+            role: role.into(),
+            content: content.into(),
+            author: None,
+            citations: Vec::new(),
+            attachments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_chunks() {
+        let messages = vec![
+            message("user", "Where is the Madou tower?"),
+            message("assistant", "North of the lake."),
+            message("tool", "{\"name\": \"search\"}"),
+            message("user", "How tall is it?"),
+            message("assistant", "Very."),
+        ];
+        let chunks = chunks(&messages, &HashSet::new(), 21);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!((chunks[0].start, chunks[0].end), (0, 2));
+        assert_eq!(
+            chunks[0].text,
+            "user: Where is the Madou tower?\nassistant: North of the lake."
+        );
+        assert_eq!(
+            chunks[0].messages,
+            vec![hash(&messages[0]), hash(&messages[1])]
+        );
+        assert_eq!((chunks[1].start, chunks[1].end), (3, 5));
+
+        let memorized = chunks
+            .iter()
+            .flat_map(|chunk| chunk.messages.clone())
+            .collect::<HashSet<_>>();
+        assert!(super::chunks(&messages, &memorized, MAX_CHUNK_TOKENS).is_empty());
+
+        // Editing a message memorizes it again, apart from its neighbours.
+        let mut edited = messages.clone();
+        edited[1].content = "South of the lake.".into();
+        let rechunked = super::chunks(&edited, &memorized, MAX_CHUNK_TOKENS);
+        assert_eq!(rechunked.len(), 1);
+        assert_eq!((rechunked[0].start, rechunked[0].end), (1, 2));
+        assert_eq!(rechunked[0].text, "assistant: South of the lake.");
+
+        let mut around = memorized.clone();
+        around.remove(&hash(&messages[0]));
+        around.remove(&hash(&messages[4]));
+        let split = super::chunks(&messages, &around, MAX_CHUNK_TOKENS);
+        assert_eq!(split.len(), 2);
+        assert_eq!((split[1].start, split[1].end), (4, 5));
+    }
+
+    #[test]
+    fn test_query() {
+        let messages = vec![
+            message("user", "First"),
+            message("assistant", "Second"),
+            message("tool", "Ignored"),
+            message("user", "Third"),
+        ];
+        assert_eq!(query(&messages, 2), "Second\nThird");
+        assert_eq!(query(&[], 2), "");
+    }
+}
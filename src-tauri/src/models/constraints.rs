@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{grammar, prelude::*};
+
+use super::model::Engine;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Constraints {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grammar: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json_schema: Option<Value>,
+}
+
+impl Constraints {
+    pub fn grammar(grammar: &str) -> Self {
+        Self {
+            grammar: Some(grammar.to_string()),
+            json_schema: None,
+        }
+    }
+
+    pub fn json_schema(schema: Value) -> Self {
+        Self {
+            grammar: None,
+            json_schema: Some(schema),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.grammar.is_none() && self.json_schema.is_none()
+    }
+
+    // llama.cpp only understands GBNF, so schemas get converted locally. The other engines
+    // enforce schemas themselves and have no grammar support.
+    pub fn for_engine(self, engine: Engine) -> Result<Self> {
+        match engine {
+            Engine::LlamaCpp => match (self.grammar, self.json_schema) {
+                (Some(grammar), _) => Ok(Self::grammar(&grammar)),
+                (None, Some(schema)) => Ok(Self::grammar(&grammar::json_schema_to_gbnf(&schema)?)),
+                (None, None) => Ok(Self::default()),
+            },
+            Engine::ExllamaV2 | Engine::Transformers | Engine::Ollama if self.grammar.is_some() => {
+                Err(AliceError::UnsupportedConstraint(format!(
+                    "{} doesn't support grammars",
+                    engine
+                )))
+            }
+            Engine::ExllamaV2 | Engine::Transformers | Engine::OpenAi | Engine::Ollama => Ok(self),
+        }
+    }
+
+    // The OpenAI `response_format` equivalent of the schema, if any.
+    pub fn response_format(&self) -> Option<Value> {
+        self.json_schema.as_ref().map(|schema| {
+            json!({
+                "type": "json_schema",
+                "json_schema": {
+                    "name": "response",
+                    "strict": true,
+                    "schema": schema,
+                },
+            })
+        })
+    }
+}
@@ -60,11 +60,17 @@ mod tests {
                     timestamp: Utc::now(),
                     role: "Alice".to_string(),
                     content: "Hello".to_string(),
+                    author: None,
+                    citations: Vec::new(),
+                    attachments: Vec::new(),
                 },
                 Message {
                     timestamp: Utc::now(),
                     role: "Bob".to_string(),
                     content: "Hi, how are you?".to_string(),
+                    author: None,
+                    citations: Vec::new(),
+                    attachments: Vec::new(),
                 },
             ],
         };
@@ -83,11 +89,17 @@ mod tests {
                     timestamp: Utc::now(),
                     role: "Alice".to_string(),
                     content: "Hello".to_string(),
+                    author: None,
+                    citations: Vec::new(),
+                    attachments: Vec::new(),
                 },
                 Message {
                     timestamp: Utc::now(),
                     role: "Bob".to_string(),
                     content: "Hi, how are you?".to_string(),
+                    author: None,
+                    citations: Vec::new(),
+                    attachments: Vec::new(),
                 },
             ],
         };
@@ -99,11 +111,17 @@ mod tests {
                     timestamp: Utc::now(),
                     role: "Bob".to_string(),
                     content: "Hi, how are you?".to_string(),
+                    author: None,
+                    citations: Vec::new(),
+                    attachments: Vec::new(),
                 },
                 Message {
                     timestamp: Utc::now(),
                     role: "Alice".to_string(),
                     content: "I'm good, thanks!".to_string(),
+                    author: None,
+                    citations: Vec::new(),
+                    attachments: Vec::new(),
                 },
             ],
         };
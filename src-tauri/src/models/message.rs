@@ -1,9 +1,31 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::attachment::media::Attachment;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub timestamp: DateTime<Utc>,
     pub role: String,
     pub content: String,
+    // The record id of the character who wrote it, for assistant messages in group chats.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    // The document passages the reply refers to, by the numbers it cites them with.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub citations: Vec<Citation>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+}
+
+// Where a numbered passage in a prompt came from, so the source of a reply can be shown.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Citation {
+    pub number: usize,
+    // The record id of the document.
+    pub document: String,
+    pub name: String,
+    // Which of the document's chunks the passage is.
+    pub chunk: usize,
+    pub heading: Option<String>,
 }
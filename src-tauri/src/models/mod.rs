@@ -1,4 +1,5 @@
 pub mod chat;
+pub mod constraints;
 pub mod history;
 pub mod history2;
 pub mod message;
@@ -3,6 +3,8 @@ use std::fmt::Formatter;
 
 use serde::{Deserialize, Serialize};
 
+use super::parameters::Sampler;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Model {
     pub name: String,
@@ -14,7 +16,7 @@ impl Model {
     }
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Engine {
     #[serde(rename = "llama-cpp")]
     LlamaCpp,
@@ -22,6 +24,86 @@ pub enum Engine {
     ExllamaV2,
     #[serde(rename = "transformers")]
     Transformers,
+    #[serde(rename = "openai")]
+    OpenAi,
+    #[serde(rename = "ollama")]
+    Ollama,
+}
+
+impl Engine {
+    // The samplers each engine implements, anything else gets stripped before completion.
+    pub fn samplers(&self) -> &'static [Sampler] {
+        match self {
+            Engine::LlamaCpp => &[
+                Sampler::TopK,
+                Sampler::TopP,
+                Sampler::TypicalP,
+                Sampler::MinP,
+                Sampler::Tfs,
+                Sampler::RepetitionPenalty,
+                Sampler::FrequencyPenalty,
+                Sampler::PresencePenalty,
+                Sampler::Dry,
+                Sampler::DynamicTemperature,
+                Sampler::Mirostat,
+                Sampler::BannedTokens,
+            ],
+            Engine::ExllamaV2 => &[
+                Sampler::TopK,
+                Sampler::TopP,
+                Sampler::TypicalP,
+                Sampler::MinP,
+                Sampler::TopA,
+                Sampler::Tfs,
+                Sampler::RepetitionPenalty,
+                Sampler::FrequencyPenalty,
+                Sampler::PresencePenalty,
+                Sampler::Smoothing,
+                Sampler::Dry,
+                Sampler::DynamicTemperature,
+                Sampler::Mirostat,
+                Sampler::BannedTokens,
+                Sampler::BannedStrings,
+                Sampler::TemperatureLast,
+            ],
+            Engine::Transformers => &[
+                Sampler::TopK,
+                Sampler::TopP,
+                Sampler::TypicalP,
+                Sampler::MinP,
+                Sampler::EpsilonCutoff,
+                Sampler::EtaCutoff,
+                Sampler::RepetitionPenalty,
+                Sampler::EncoderPenalty,
+                Sampler::NoRepeatNgram,
+                Sampler::BeamSearch,
+                Sampler::ContrastiveSearch,
+                Sampler::BannedTokens,
+            ],
+            // Only what OpenAI compatible servers commonly accept, banned tokens go through
+            // `logit_bias`.
+            Engine::OpenAi => &[
+                Sampler::TopK,
+                Sampler::TopP,
+                Sampler::TypicalP,
+                Sampler::MinP,
+                Sampler::RepetitionPenalty,
+                Sampler::FrequencyPenalty,
+                Sampler::PresencePenalty,
+                Sampler::BannedTokens,
+            ],
+            Engine::Ollama => &[
+                Sampler::TopK,
+                Sampler::TopP,
+                Sampler::TypicalP,
+                Sampler::MinP,
+                Sampler::RepetitionPenalty,
+                Sampler::FrequencyPenalty,
+                Sampler::PresencePenalty,
+                Sampler::Mirostat,
+            ],
+        }
+    }
 }
 
 impl fmt::Display for Engine {
@@ -1,4 +1,7 @@
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::model::Engine;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EngineParameters {
@@ -28,7 +31,9 @@ pub struct EngineParameters {
     pub smoothing_factor: f64,
     pub smoothing_curve: f64,
 
-    pub dry_muiltiplier: f64,
+    // µLLM still takes the misspelled names.
+    #[serde(rename = "dry_muiltiplier", alias = "dry_multiplier")]
+    pub dry_multiplier: f64,
     pub dry_base: f64,
     pub dry_allowed_length: i64,
     pub dry_sequence_breakers: Vec<String>,
@@ -44,7 +49,8 @@ pub struct EngineParameters {
 
     pub bs: bool,
     pub bs_n: i64,
-    pub bs_lenth_penalty: f64,
+    #[serde(rename = "bs_lenth_penalty", alias = "bs_length_penalty")]
+    pub bs_length_penalty: f64,
     pub bs_early_stopping: bool,
 
     pub cs: bool,
@@ -91,7 +97,7 @@ impl Default for EngineParameters {
             smoothing_factor: 0.33,
             smoothing_curve: 1.0,
 
-            dry_muiltiplier: 0.8,
+            dry_multiplier: 0.8,
             dry_base: 1.75,
             dry_allowed_length: 2,
             dry_sequence_breakers: vec![
@@ -112,7 +118,7 @@ impl Default for EngineParameters {
 
             bs: false,
             bs_n: 1,
-            bs_lenth_penalty: 0.0,
+            bs_length_penalty: 0.0,
             bs_early_stopping: false,
 
             cs: false,
@@ -131,3 +137,335 @@ impl Default for EngineParameters {
         }
     }
 }
+
+#[derive(Debug, Clone, PartialEq, Serialize, Error)]
+pub enum ParameterError {
+    #[error("`{field}` must be between {min} and {max}, got {value}")]
+    OutOfRange {
+        field: &'static str,
+        value: f64,
+        min: f64,
+        max: f64,
+    },
+    #[error("`{0}` conflicts with `{1}`")]
+    Conflict(&'static str, &'static str),
+    #[error("`{sampler:?}` is not supported by {engine}")]
+    Unsupported { sampler: Sampler, engine: Engine },
+}
+
+// Groups of fields that are turned on and off together, as far as a backend is concerned.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Sampler {
+    TopK,
+    TopP,
+    TypicalP,
+    MinP,
+    TopA,
+    Tfs,
+    EpsilonCutoff,
+    EtaCutoff,
+    RepetitionPenalty,
+    EncoderPenalty,
+    FrequencyPenalty,
+    PresencePenalty,
+    NoRepeatNgram,
+    Smoothing,
+    Dry,
+    DynamicTemperature,
+    Mirostat,
+    BeamSearch,
+    ContrastiveSearch,
+    BannedTokens,
+    BannedStrings,
+    TemperatureLast,
+}
+
+impl Sampler {
+    pub const ALL: [Sampler; 22] = [
+        Sampler::TopK,
+        Sampler::TopP,
+        Sampler::TypicalP,
+        Sampler::MinP,
+        Sampler::TopA,
+        Sampler::Tfs,
+        Sampler::EpsilonCutoff,
+        Sampler::EtaCutoff,
+        Sampler::RepetitionPenalty,
+        Sampler::EncoderPenalty,
+        Sampler::FrequencyPenalty,
+        Sampler::PresencePenalty,
+        Sampler::NoRepeatNgram,
+        Sampler::Smoothing,
+        Sampler::Dry,
+        Sampler::DynamicTemperature,
+        Sampler::Mirostat,
+        Sampler::BeamSearch,
+        Sampler::ContrastiveSearch,
+        Sampler::BannedTokens,
+        Sampler::BannedStrings,
+        Sampler::TemperatureLast,
+    ];
+
+    // Whether the sampler would change the output with these parameters.
+    pub fn is_active(&self, params: &EngineParameters) -> bool {
+        match self {
+            Sampler::TopK => params.top_k > 0,
+            Sampler::TopP => params.top_p < 1.0,
+            Sampler::TypicalP => params.typical_p < 1.0,
+            Sampler::MinP => params.min_p > 0.0,
+            Sampler::TopA => params.top_a > 0.0,
+            Sampler::Tfs => params.tfs < 1.0,
+            Sampler::EpsilonCutoff => params.epsilon_cutoff > 0.0,
+            Sampler::EtaCutoff => params.eta_cutoff > 0.0,
+            Sampler::RepetitionPenalty => params.repetition_penalty != 1.0,
+            Sampler::EncoderPenalty => params.encoder_penalty != 1.0,
+            Sampler::FrequencyPenalty => params.frequency_penalty != 0.0,
+            Sampler::PresencePenalty => params.presence_penalty != 0.0,
+            Sampler::NoRepeatNgram => params.no_repeat_ngram_size > 0.0,
+            Sampler::Smoothing => params.smoothing_factor > 0.0,
+            Sampler::Dry => params.dry_multiplier > 0.0,
+            Sampler::DynamicTemperature => params.dt,
+            Sampler::Mirostat => params.mirostat,
+            Sampler::BeamSearch => params.bs,
+            Sampler::ContrastiveSearch => params.cs,
+            Sampler::BannedTokens => !params.banned_tokens.is_empty(),
+            Sampler::BannedStrings => !params.banned_strings.is_empty(),
+            Sampler::TemperatureLast => params.temperature_last,
+        }
+    }
+
+    // Resets the sampler's fields to values that leave the output untouched.
+    pub fn disable(&self, params: &mut EngineParameters) {
+        match self {
+            Sampler::TopK => params.top_k = 0,
+            Sampler::TopP => params.top_p = 1.0,
+            Sampler::TypicalP => params.typical_p = 1.0,
+            Sampler::MinP => params.min_p = 0.0,
+            Sampler::TopA => params.top_a = 0.0,
+            Sampler::Tfs => params.tfs = 1.0,
+            Sampler::EpsilonCutoff => params.epsilon_cutoff = 0.0,
+            Sampler::EtaCutoff => params.eta_cutoff = 0.0,
+            Sampler::RepetitionPenalty => params.repetition_penalty = 1.0,
+            Sampler::EncoderPenalty => params.encoder_penalty = 1.0,
+            Sampler::FrequencyPenalty => params.frequency_penalty = 0.0,
+            Sampler::PresencePenalty => params.presence_penalty = 0.0,
+            Sampler::NoRepeatNgram => params.no_repeat_ngram_size = 0.0,
+            Sampler::Smoothing => params.smoothing_factor = 0.0,
+            Sampler::Dry => params.dry_multiplier = 0.0,
+            Sampler::DynamicTemperature => params.dt = false,
+            Sampler::Mirostat => {
+                params.mirostat = false;
+                params.mirostat_mode = 0;
+            }
+            Sampler::BeamSearch => {
+                params.bs = false;
+                params.bs_n = 1;
+            }
+            Sampler::ContrastiveSearch => {
+                params.cs = false;
+                params.cs_penalty_alpha = 0.0;
+            }
+            Sampler::BannedTokens => params.banned_tokens.clear(),
+            Sampler::BannedStrings => params.banned_strings.clear(),
+            Sampler::TemperatureLast => params.temperature_last = false,
+        }
+    }
+}
+
+impl EngineParameters {
+    pub fn validate(&self) -> Result<(), Vec<ParameterError>> {
+        let mut errors = Vec::new();
+        let mut range = |field: &'static str, value: f64, min: f64, max: f64| {
+            if !(min..=max).contains(&value) {
+                errors.push(ParameterError::OutOfRange {
+                    field,
+                    value,
+                    min,
+                    max,
+                });
+            }
+        };
+
+        range(
+            "max_tokens",
+            self.max_tokens as f64,
+            1.0,
+            self.context_window as f64,
+        );
+        range(
+            "context_window",
+            self.context_window as f64,
+            1.0,
+            1_048_576.0,
+        );
+
+        range("temperature", self.temperature, 0.0, 5.0);
+        range("top_k", self.top_k as f64, 0.0, 200.0);
+        range("top_p", self.top_p, 0.0, 1.0);
+
+        range("typical_p", self.typical_p, 0.0, 1.0);
+        range("min_p", self.min_p, 0.0, 1.0);
+        range("top_a", self.top_a, 0.0, 1.0);
+
+        range("tfs", self.tfs, 0.0, 1.0);
+        range("epsilon_cutoff", self.epsilon_cutoff, 0.0, 9.0);
+        range("eta_cutoff", self.eta_cutoff, 0.0, 20.0);
+
+        range("repetition_penalty", self.repetition_penalty, 0.0, 3.0);
+        range(
+            "repetition_penalty_range",
+            self.repetition_penalty_range as f64,
+            0.0,
+            self.context_window as f64,
+        );
+        range("encoder_penalty", self.encoder_penalty, 0.0, 3.0);
+
+        range("frequency_penalty", self.frequency_penalty, -2.0, 2.0);
+        range("presence_penalty", self.presence_penalty, -2.0, 2.0);
+        range("no_repeat_ngram_size", self.no_repeat_ngram_size, 0.0, 20.0);
+
+        range("smoothing_factor", self.smoothing_factor, 0.0, 10.0);
+        range("smoothing_curve", self.smoothing_curve, 1.0, 10.0);
+
+        range("dry_multiplier", self.dry_multiplier, 0.0, 5.0);
+        range("dry_base", self.dry_base, 1.0, 4.0);
+        range(
+            "dry_allowed_length",
+            self.dry_allowed_length as f64,
+            1.0,
+            20.0,
+        );
+
+        range("dt_min_temperature", self.dt_min_temperature, 0.0, 5.0);
+        range("dt_max_temperature", self.dt_max_temperature, 0.0, 5.0);
+
+        range("mirostat_mode", self.mirostat_mode as f64, 0.0, 2.0);
+        range("mirostat_tau", self.mirostat_tau, 0.0, 20.0);
+        range("mirostat_eta", self.mirostat_eta, 0.0, 1.0);
+
+        range("bs_n", self.bs_n as f64, 1.0, 20.0);
+        range("bs_length_penalty", self.bs_length_penalty, -5.0, 5.0);
+
+        range("cs_penalty_alpha", self.cs_penalty_alpha, 0.0, 5.0);
+
+        if self.dt && self.dt_min_temperature > self.dt_max_temperature {
+            errors.push(ParameterError::Conflict(
+                "dt_min_temperature",
+                "dt_max_temperature",
+            ));
+        }
+        if self.mirostat && self.mirostat_mode == 0 {
+            errors.push(ParameterError::Conflict("mirostat", "mirostat_mode"));
+        }
+        // Mirostat replaces the truncation samplers, it can't be combined with them.
+        if self.mirostat && self.top_k > 0 {
+            errors.push(ParameterError::Conflict("mirostat", "top_k"));
+        }
+        if self.bs && self.do_sample {
+            errors.push(ParameterError::Conflict("bs", "do_sample"));
+        }
+        if self.cs && self.do_sample {
+            errors.push(ParameterError::Conflict("cs", "do_sample"));
+        }
+        if self.bs && self.cs {
+            errors.push(ParameterError::Conflict("bs", "cs"));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    // Disables every active sampler the engine doesn't implement, returning what was stripped.
+    pub fn for_engine(mut self, engine: Engine) -> (Self, Vec<ParameterError>) {
+        let mut stripped = Vec::new();
+        for sampler in Sampler::ALL {
+            if sampler.is_active(&self) && !engine.samplers().contains(&sampler) {
+                sampler.disable(&mut self);
+                stripped.push(ParameterError::Unsupported { sampler, engine });
+            }
+        }
+        (self, stripped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_is_valid() {
+        assert_eq!(EngineParameters::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let params = EngineParameters {
+            top_p: 1.5,
+            ..Default::default()
+        };
+        assert_eq!(
+            params.validate(),
+            Err(vec![ParameterError::OutOfRange {
+                field: "top_p",
+                value: 1.5,
+                min: 0.0,
+                max: 1.0,
+            }])
+        );
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let params = EngineParameters {
+            mirostat: true,
+            mirostat_mode: 2,
+            bs: true,
+            bs_n: 4,
+            ..Default::default()
+        };
+        let errors = params.validate().unwrap_err();
+        assert!(errors.contains(&ParameterError::Conflict("mirostat", "top_k")));
+        assert!(errors.contains(&ParameterError::Conflict("bs", "do_sample")));
+    }
+
+    #[test]
+    fn test_typo_aliases() {
+        let mut value = serde_json::to_value(EngineParameters::default()).unwrap();
+        let object = value.as_object_mut().unwrap();
+        assert!(object.contains_key("dry_muiltiplier"));
+        assert!(object.contains_key("bs_lenth_penalty"));
+        object.remove("dry_muiltiplier");
+        object.remove("bs_lenth_penalty");
+        object.insert("dry_multiplier".into(), 1.5.into());
+        object.insert("bs_length_penalty".into(), 0.5.into());
+        let params: EngineParameters = serde_json::from_value(value).unwrap();
+        assert_eq!(params.dry_multiplier, 1.5);
+        assert_eq!(params.bs_length_penalty, 0.5);
+    }
+
+    #[test]
+    fn test_for_engine() {
+        let params = EngineParameters {
+            top_a: 0.2,
+            cs: true,
+            cs_penalty_alpha: 0.6,
+            ..Default::default()
+        };
+        let (params, stripped) = params.for_engine(Engine::LlamaCpp);
+        assert_eq!(params.top_a, 0.0);
+        assert!(!params.cs);
+        assert!(stripped.contains(&ParameterError::Unsupported {
+            sampler: Sampler::TopA,
+            engine: Engine::LlamaCpp,
+        }));
+        assert!(stripped.contains(&ParameterError::Unsupported {
+            sampler: Sampler::ContrastiveSearch,
+            engine: Engine::LlamaCpp,
+        }));
+        // Supported samplers are left alone.
+        assert_eq!(params.min_p, EngineParameters::default().min_p);
+    }
+}
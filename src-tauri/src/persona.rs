@@ -0,0 +1,217 @@
+use crate::prelude::*;
+
+use crate::DB;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::RecordId;
+
+use crate::conversation::Conversation;
+use crate::wpp::{chat::ChatPromptContext, header::HeaderItem, item::WppItem, parser};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LeanPersona {
+    pub id: RecordId,
+    pub name: String,
+    pub avatar: Option<String>,
+    pub modified_time: DateTime<Utc>,
+}
+
+// Everything the persona editor can change, with the definition as raw W++.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PersonaDetails {
+    pub name: String,
+    pub avatar: Option<String>,
+    pub description: Option<String>,
+    pub definition: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InsertablePersona {
+    pub name: String,
+    pub avatar: Option<String>,
+    pub description: Option<String>,
+    pub definition: Option<WppItem>,
+    pub created_time: DateTime<Utc>,
+    pub modified_time: DateTime<Utc>,
+}
+
+// Who the user plays as, what `{{user}}` resolves to and how the header describes them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Persona {
+    pub id: RecordId,
+    pub name: String,
+    pub avatar: Option<String>,
+    pub description: Option<String>,
+    pub definition: Option<WppItem>,
+    pub created_time: DateTime<Utc>,
+    pub modified_time: DateTime<Utc>,
+}
+
+// The persona used by conversations that don't pick one, stored under a single record.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DefaultPersona {
+    pub persona: RecordId,
+}
+
+impl TryFrom<PersonaDetails> for InsertablePersona {
+    type Error = AliceError;
+
+    fn try_from(details: PersonaDetails) -> Result<Self> {
+        if details.name.trim().is_empty() {
+            return Err(AliceError::InvalidPersona("name can't be empty".into()));
+        }
+        let definition = match details.definition.as_deref().map(str::trim) {
+            Some(definition) if !definition.is_empty() => Some(
+                parser::parse(definition).map_err(|e| AliceError::InvalidPersona(e.to_string()))?,
+            ),
+            _ => None,
+        };
+        let time = Utc::now();
+        Ok(Self {
+            name: details.name.trim().to_string(),
+            avatar: details.avatar,
+            description: details.description,
+            definition,
+            created_time: time,
+            modified_time: time,
+        })
+    }
+}
+
+impl Persona {
+    pub async fn new(details: PersonaDetails) -> Result<Self> {
+        db!()
+            .create("persona")
+            .content(InsertablePersona::try_from(details)?)
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "create".into(),
+                "persona".into(),
+            ))
+    }
+
+    pub async fn find(id: String) -> Result<Self> {
+        db!()
+            .select(("persona", &id))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "select".into(),
+                id.to_string(),
+            ))
+    }
+
+    pub async fn name_sorted_lean(limit: usize, offset: usize) -> Result<Vec<LeanPersona>> {
+        let result = db!()
+            .query("SELECT id, name, avatar, modified_time FROM persona ORDER BY name ASC LIMIT $limit START $offset")
+            .bind(("limit", limit))
+            .bind(("offset", offset))
+            .await?
+            .take(0)?;
+        Ok(result)
+    }
+
+    pub async fn with_details(self, details: PersonaDetails) -> Result<Self> {
+        let mut persona = InsertablePersona::try_from(details)?;
+        persona.created_time = self.created_time;
+        db!()
+            .update(self.id)
+            .content(persona)
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "persona".into(),
+            ))
+    }
+
+    pub async fn delete(self) -> Result<()> {
+        let deleted: Option<Self> = db!().delete(self.id).await?;
+        deleted.ok_or(AliceError::DatabaseOperation(
+            "delete".into(),
+            "persona".into(),
+        ))?;
+        Ok(())
+    }
+
+    pub async fn default_persona() -> Result<Option<Self>> {
+        let selection: Option<DefaultPersona> =
+            db!().select(("default_persona", "default")).await?;
+        match selection {
+            Some(selection) => Ok(db!().select(selection.persona).await?),
+            None => Ok(None),
+        }
+    }
+
+    // Only existing personas can become the default.
+    pub async fn set_default(persona: Option<String>) -> Result<()> {
+        match persona {
+            Some(persona) => {
+                Self::find(persona.clone()).await?;
+                let selection: Option<DefaultPersona> = db!()
+                    .upsert(("default_persona", "default"))
+                    .content(DefaultPersona {
+                        persona: RecordId::from_table_key("persona", persona),
+                    })
+                    .await?;
+                selection.ok_or(AliceError::DatabaseOperation(
+                    "upsert".into(),
+                    "default_persona".into(),
+                ))?;
+            }
+            None => {
+                let _: Option<DefaultPersona> =
+                    db!().delete(("default_persona", "default")).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // The conversation's persona wins over the default one. Without either the user stays
+    // anonymous.
+    pub async fn resolve(conversation: &Conversation) -> Result<Option<Self>> {
+        if let Some(id) = &conversation.persona {
+            let persona: Option<Self> = db!().select(id.clone()).await?;
+            if persona.is_some() {
+                return Ok(persona);
+            }
+        }
+        Self::default_persona().await
+    }
+
+    // The definition when there is one, otherwise the free-text description. `characters` are the
+    // names `{{char}}` and friends resolve to.
+    pub fn header_item(&self, characters: Vec<String>) -> HeaderItem {
+        let full = match &self.definition {
+            Some(definition) => definition.prompt(),
+            None => self.description.clone().unwrap_or_default(),
+        };
+        let context = ChatPromptContext::new(characters, self.name.clone());
+        HeaderItem::new(&self.name, &context.substitute(&full))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_details() {
+        let details = PersonaDetails {
+            name: " Alex ".into(),
+            description: Some("A traveller.".into()),
+            definition: Some("[Character(\"Alex\"){Age(\"20\")}]".into()),
+            ..Default::default()
+        };
+        let persona = InsertablePersona::try_from(details).unwrap();
+        assert_eq!(persona.name, "Alex");
+        assert_eq!(persona.definition.unwrap().name(), "Alex");
+
+        let invalid = PersonaDetails {
+            name: "Alex".into(),
+            definition: Some("[Character(\"Alex\"".into()),
+            ..Default::default()
+        };
+        assert!(InsertablePersona::try_from(invalid).is_err());
+        assert!(InsertablePersona::try_from(PersonaDetails::default()).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+use crate::prelude::*;
+
+// Just enough of PNG to read and write metadata chunks, image data is passed through untouched.
+// https://www.w3.org/TR/png/#5Chunk-layout
+
+pub static SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+// A fully transparent 1x1 image, for when there's no picture to embed metadata into.
+#[rustfmt::skip]
+pub static BLANK: [u8; 68] = [
+    0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52,
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1f, 0x15, 0xc4,
+    0x89, 0x00, 0x00, 0x00, 0x0b, 0x49, 0x44, 0x41, 0x54, 0x78, 0xda, 0x63, 0x60, 0x00, 0x02, 0x00,
+    0x00, 0x05, 0x00, 0x01, 0xe9, 0xfa, 0xdc, 0xd8, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44,
+    0xae, 0x42, 0x60, 0x82,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub kind: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+impl Chunk {
+    // A `tEXt` chunk, the text is Latin-1 which base64 payloads always are.
+    pub fn text(keyword: &str, text: &str) -> Self {
+        let mut data = Vec::with_capacity(keyword.len() + 1 + text.len());
+        data.extend(keyword.chars().map(latin1));
+        data.push(0);
+        data.extend(text.chars().map(latin1));
+        Self {
+            kind: *b"tEXt",
+            data,
+        }
+    }
+
+    // The keyword and text of a `tEXt` chunk.
+    pub fn as_text(&self) -> Option<(String, String)> {
+        if &self.kind != b"tEXt" {
+            return None;
+        }
+        let separator = self.data.iter().position(|byte| *byte == 0)?;
+        let keyword = self.data[..separator].iter().map(|b| *b as char).collect();
+        let text = self.data[separator + 1..]
+            .iter()
+            .map(|b| *b as char)
+            .collect();
+        Some((keyword, text))
+    }
+}
+
+fn latin1(c: char) -> u8 {
+    u8::try_from(c as u32).unwrap_or(b'?')
+}
+
+pub fn read_chunks(bytes: &[u8]) -> Result<Vec<Chunk>> {
+    let Some(mut rest) = bytes.strip_prefix(&SIGNATURE) else {
+        return Err(AliceError::InvalidPng("missing signature".into()));
+    };
+    let mut chunks = Vec::new();
+    while !rest.is_empty() {
+        if rest.len() < 12 {
+            return Err(AliceError::InvalidPng("truncated chunk".into()));
+        }
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        if rest.len() < 12 + length {
+            return Err(AliceError::InvalidPng("truncated chunk".into()));
+        }
+        let kind = [rest[4], rest[5], rest[6], rest[7]];
+        let data = &rest[8..8 + length];
+        let crc = u32::from_be_bytes([
+            rest[8 + length],
+            rest[9 + length],
+            rest[10 + length],
+            rest[11 + length],
+        ]);
+        if crc != crc32(&rest[4..8 + length]) {
+            return Err(AliceError::InvalidPng(format!(
+                "bad checksum in `{}` chunk",
+                String::from_utf8_lossy(&kind)
+            )));
+        }
+        chunks.push(Chunk {
+            kind,
+            data: data.to_vec(),
+        });
+        rest = &rest[12 + length..];
+        if &kind == b"IEND" {
+            break;
+        }
+    }
+    match chunks.first() {
+        Some(chunk) if &chunk.kind == b"IHDR" => Ok(chunks),
+        _ => Err(AliceError::InvalidPng("missing `IHDR` chunk".into())),
+    }
+}
+
+pub fn write_chunks(chunks: &[Chunk]) -> Vec<u8> {
+    let mut bytes = SIGNATURE.to_vec();
+    for chunk in chunks {
+        bytes.extend((chunk.data.len() as u32).to_be_bytes());
+        let start = bytes.len();
+        bytes.extend(chunk.kind);
+        bytes.extend(&chunk.data);
+        let crc = crc32(&bytes[start..]);
+        bytes.extend(crc.to_be_bytes());
+    }
+    bytes
+}
+
+// Every `tEXt` chunk as keyword and text, in file order.
+pub fn texts(bytes: &[u8]) -> Result<Vec<(String, String)>> {
+    Ok(read_chunks(bytes)?
+        .iter()
+        .filter_map(Chunk::as_text)
+        .collect())
+}
+
+// Replaces the `tEXt` chunks with the given keyword by a single new one, placed before `IEND`.
+pub fn with_text(bytes: &[u8], keyword: &str, text: &str) -> Result<Vec<u8>> {
+    let mut chunks = read_chunks(bytes)?;
+    chunks.retain(|chunk| !matches!(chunk.as_text(), Some((existing, _)) if existing == keyword));
+    let end = chunks
+        .iter()
+        .position(|chunk| &chunk.kind == b"IEND")
+        .unwrap_or(chunks.len());
+    chunks.insert(end, Chunk::text(keyword, text));
+    Ok(write_chunks(&chunks))
+}
+
+// CRC-32 as used by PNG (and zlib), computed bitwise since the chunks involved are small.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let chunks = read_chunks(&BLANK).unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(write_chunks(&chunks), BLANK.to_vec());
+    }
+
+    #[test]
+    fn test_with_text() {
+        let bytes = with_text(&BLANK, "chara", "first").unwrap();
+        let bytes = with_text(&bytes, "chara", "second").unwrap();
+        let bytes = with_text(&bytes, "ccv3", "third").unwrap();
+        assert_eq!(
+            texts(&bytes).unwrap(),
+            vec![
+                ("chara".to_string(), "second".to_string()),
+                ("ccv3".to_string(), "third".to_string()),
+            ]
+        );
+        let chunks = read_chunks(&bytes).unwrap();
+        assert_eq!(&chunks.last().unwrap().kind, b"IEND");
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(read_chunks(b"not a png").is_err());
+        let mut corrupted = BLANK.to_vec();
+        corrupted[40] ^= 0xff;
+        assert!(read_chunks(&corrupted).is_err());
+    }
+}
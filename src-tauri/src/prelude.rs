@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::models::parameters::ParameterError;
+
 pub type Result<T> = std::result::Result<T, AliceError>;
 
 #[derive(Debug, Error)]
@@ -35,6 +37,10 @@ pub enum AliceError {
     #[error("Handlebars error: {0}")]
     Handlebars(#[from] handlebars::RenderError),
 
+    // Http
+    #[error("Reqwest error: {0}")]
+    Reqwest(#[from] reqwest::Error),
+
     // Sockets
     #[error("Tungstenite error: {0}")]
     Tungstenite(#[from] tokio_tungstenite::tungstenite::Error),
@@ -44,6 +50,76 @@ pub enum AliceError {
     ResponseError,
     #[error("Invalid message")]
     InvalidMessage,
+
+    // Presets
+    #[error("Invalid preset: {0}")]
+    InvalidPreset(String),
+    #[error("Invalid engine parameters: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+    InvalidParameters(Vec<ParameterError>),
+
+    // Constrained generation
+    #[error("Invalid JSON schema: {0}")]
+    InvalidSchema(String),
+    #[error("Unsupported constraint: {0}")]
+    UnsupportedConstraint(String),
+
+    // Tools
+    #[error("Unknown tool: {0}")]
+    UnknownTool(String),
+    #[error("Invalid tool arguments: {0}")]
+    InvalidToolArguments(String),
+
+    // Characters
+    #[error("Invalid character: {0}")]
+    InvalidCharacter(String),
+    #[error("Invalid PNG: {0}")]
+    InvalidPng(String),
+
+    // Personas
+    #[error("Invalid persona: {0}")]
+    InvalidPersona(String),
+
+    // Lorebooks
+    #[error("Invalid lorebook: {0}")]
+    InvalidLorebook(String),
+
+    // Documents
+    #[error("Unsupported document: {0}")]
+    UnsupportedDocument(String),
+
+    // Attachments
+    #[error("Unsupported attachment: {0}, only images and text files can be attached")]
+    UnsupportedAttachment(String),
+    #[error("No attachment is stored as {0}")]
+    UnknownAttachment(String),
+
+    // Speech
+    #[error("Nothing was recorded")]
+    EmptyRecording,
+    #[error("{0} needs a reference sample for the voice")]
+    MissingVoiceSample(String),
+
+    // Browsing
+    #[error("Browsing to {0} is not allowed")]
+    BlockedUrl(String),
+    #[error("No search engine is configured")]
+    NoSearchEngine,
+    #[error("Unsupported page: {0}, only HTML and text can be read")]
+    UnsupportedPage(String),
+
+    // Images
+    #[error("There is nothing to make an image of")]
+    NothingToPicture,
+    #[error("The image generator returned no image")]
+    NoImageReturned,
+
+    // Generation
+    #[error("The last message is not the assistant's, there is nothing to continue")]
+    NothingToContinue,
+    #[error("The character {0} is not taking part in the conversation")]
+    NotAParticipant(String),
+    #[error("Pick which character speaks next")]
+    NoSpeaker,
 }
 
 impl From<AliceError> for String {
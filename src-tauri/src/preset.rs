@@ -0,0 +1,175 @@
+use crate::prelude::*;
+
+use crate::DB;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::opt::PatchOp;
+use surrealdb::RecordId;
+
+use crate::conversation::Conversation;
+use crate::models::{model::Model, parameters::EngineParameters};
+
+pub mod import;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LeanPreset {
+    pub id: RecordId,
+    pub name: String,
+    pub modified_time: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InsertablePreset {
+    pub name: String,
+    pub created_time: DateTime<Utc>,
+    pub modified_time: DateTime<Utc>,
+    pub parameters: EngineParameters,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Preset {
+    pub id: RecordId,
+    pub name: String,
+    pub created_time: DateTime<Utc>,
+    pub modified_time: DateTime<Utc>,
+    pub parameters: EngineParameters,
+}
+
+// The preset selected for a model, stored with the model name as record key.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModelPreset {
+    pub preset: RecordId,
+}
+
+impl InsertablePreset {
+    pub fn new(name: String, parameters: EngineParameters) -> Self {
+        let time = Utc::now();
+        Self {
+            name,
+            created_time: time,
+            modified_time: time,
+            parameters,
+        }
+    }
+}
+
+impl Preset {
+    // Parameters are stored as entered, conflicts and all. `validate` reports them to the UI and
+    // refuses them when completing.
+    pub async fn new(name: String, parameters: EngineParameters) -> Result<Self> {
+        db!()
+            .create("preset")
+            .content(InsertablePreset::new(name, parameters))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "create".into(),
+                "preset".into(),
+            ))
+    }
+
+    pub async fn import(name: String, json: &str) -> Result<Self> {
+        Self::new(name, import::from_json(json)?).await
+    }
+
+    pub async fn find(id: String) -> Result<Self> {
+        db!()
+            .select(("preset", &id))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "select".into(),
+                id.to_string(),
+            ))
+    }
+
+    pub async fn name_sorted_lean(limit: usize, offset: usize) -> Result<Vec<LeanPreset>> {
+        let result = db!()
+            .query("SELECT id, name, modified_time FROM preset ORDER BY name ASC LIMIT $limit START $offset")
+            .bind(("limit", limit))
+            .bind(("offset", offset))
+            .await?
+            .take(0)?;
+        Ok(result)
+    }
+
+    pub async fn with_name(self, name: String) -> Result<Self> {
+        let db = db!();
+        db.update(self.id)
+            .patch(PatchOp::replace("/name", name))
+            .patch(PatchOp::replace("/modified_time", Utc::now()))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "preset".into(),
+            ))
+    }
+
+    pub async fn with_parameters(self, parameters: EngineParameters) -> Result<Self> {
+        let db = db!();
+        db.update(self.id)
+            .patch(PatchOp::replace("/parameters", parameters))
+            .patch(PatchOp::replace("/modified_time", Utc::now()))
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "update".into(),
+                "preset".into(),
+            ))
+    }
+
+    pub async fn delete(self) -> Result<()> {
+        let deleted: Option<Self> = db!().delete(self.id).await?;
+        deleted.ok_or(AliceError::DatabaseOperation(
+            "delete".into(),
+            "preset".into(),
+        ))?;
+        Ok(())
+    }
+
+    pub async fn for_model(model: &str) -> Result<Option<Self>> {
+        let selection: Option<ModelPreset> = db!().select(("model_preset", model)).await?;
+        match selection {
+            Some(selection) => Ok(db!().select(selection.preset).await?),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn set_for_model(model: &str, preset: Option<String>) -> Result<()> {
+        match preset {
+            Some(preset) => {
+                let selection: Option<ModelPreset> = db!()
+                    .upsert(("model_preset", model))
+                    .content(ModelPreset {
+                        preset: RecordId::from_table_key("preset", preset),
+                    })
+                    .await?;
+                selection.ok_or(AliceError::DatabaseOperation(
+                    "upsert".into(),
+                    "model_preset".into(),
+                ))?;
+            }
+            None => {
+                let _: Option<ModelPreset> = db!().delete(("model_preset", model)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // The conversation's preset wins over the model's preset, which wins over the defaults.
+    pub async fn resolve(
+        conversation: &Conversation,
+        model: Option<&Model>,
+    ) -> Result<EngineParameters> {
+        if let Some(id) = &conversation.preset {
+            let preset: Option<Self> = db!().select(id.clone()).await?;
+            if let Some(preset) = preset {
+                return Ok(preset.parameters);
+            }
+        }
+        if let Some(model) = model {
+            if let Some(preset) = Self::for_model(&model.name).await? {
+                return Ok(preset.parameters);
+            }
+        }
+        Ok(EngineParameters::default())
+    }
+}
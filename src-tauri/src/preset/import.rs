@@ -0,0 +1,367 @@
+use crate::prelude::*;
+
+use serde_json::{Map, Value};
+
+use crate::models::parameters::EngineParameters;
+
+// Imports a SillyTavern or text-generation-webui sampler preset. Both formats are flat JSON
+// objects, they only disagree on a few key names, so each field lists every known spelling.
+// Keys we don't know about are ignored and missing keys keep their default value.
+pub fn from_json(json: &str) -> Result<EngineParameters> {
+    let value: Value = serde_json::from_str(json)?;
+    let Value::Object(preset) = value else {
+        return Err(AliceError::InvalidPreset("expected a JSON object".into()));
+    };
+    Ok(from_map(&preset))
+}
+
+pub fn from_map(preset: &Map<String, Value>) -> EngineParameters {
+    let mut params = EngineParameters::default();
+
+    set_i64(
+        preset,
+        &["genamt", "max_new_tokens", "max_tokens"],
+        &mut params.max_tokens,
+    );
+    set_i64(
+        preset,
+        &["max_length", "truncation_length", "max_context_length"],
+        &mut params.context_window,
+    );
+
+    set_f64(preset, &["temp", "temperature"], &mut params.temperature);
+    set_i64(preset, &["top_k"], &mut params.top_k);
+    set_f64(preset, &["top_p"], &mut params.top_p);
+
+    set_f64(preset, &["typical_p", "typical"], &mut params.typical_p);
+    set_f64(preset, &["min_p"], &mut params.min_p);
+    set_f64(preset, &["top_a"], &mut params.top_a);
+
+    set_f64(preset, &["tfs"], &mut params.tfs);
+    set_f64(preset, &["epsilon_cutoff"], &mut params.epsilon_cutoff);
+    set_f64(preset, &["eta_cutoff"], &mut params.eta_cutoff);
+
+    set_f64(
+        preset,
+        &["rep_pen", "repetition_penalty"],
+        &mut params.repetition_penalty,
+    );
+    set_i64(
+        preset,
+        &["rep_pen_range", "repetition_penalty_range"],
+        &mut params.repetition_penalty_range,
+    );
+    set_f64(
+        preset,
+        &["encoder_rep_pen", "encoder_repetition_penalty"],
+        &mut params.encoder_penalty,
+    );
+
+    set_f64(
+        preset,
+        &["freq_pen", "frequency_penalty"],
+        &mut params.frequency_penalty,
+    );
+    set_f64(
+        preset,
+        &["presence_pen", "presence_penalty"],
+        &mut params.presence_penalty,
+    );
+    set_f64(
+        preset,
+        &["no_repeat_ngram_size"],
+        &mut params.no_repeat_ngram_size,
+    );
+
+    set_f64(preset, &["smoothing_factor"], &mut params.smoothing_factor);
+    set_f64(preset, &["smoothing_curve"], &mut params.smoothing_curve);
+
+    set_f64(preset, &["dry_multiplier"], &mut params.dry_multiplier);
+    set_f64(preset, &["dry_base"], &mut params.dry_base);
+    set_i64(
+        preset,
+        &["dry_allowed_length"],
+        &mut params.dry_allowed_length,
+    );
+    if let Some(breakers) = get(preset, &["dry_sequence_breakers"]).and_then(string_list) {
+        params.dry_sequence_breakers = breakers;
+    }
+
+    set_bool(preset, &["dynatemp", "dynamic_temperature"], &mut params.dt);
+    set_f64(
+        preset,
+        &["min_temp", "dynatemp_low"],
+        &mut params.dt_min_temperature,
+    );
+    set_f64(
+        preset,
+        &["max_temp", "dynatemp_high"],
+        &mut params.dt_max_temperature,
+    );
+
+    set_i64(
+        preset,
+        &["mirostat_mode", "mirostat"],
+        &mut params.mirostat_mode,
+    );
+    params.mirostat = params.mirostat_mode > 0;
+    set_f64(preset, &["mirostat_tau"], &mut params.mirostat_tau);
+    set_f64(preset, &["mirostat_eta"], &mut params.mirostat_eta);
+
+    set_i64(preset, &["num_beams"], &mut params.bs_n);
+    params.bs = params.bs_n > 1;
+    set_f64(preset, &["length_penalty"], &mut params.bs_length_penalty);
+    set_bool(preset, &["early_stopping"], &mut params.bs_early_stopping);
+
+    set_f64(preset, &["penalty_alpha"], &mut params.cs_penalty_alpha);
+    params.cs = params.cs_penalty_alpha > 0.0;
+
+    set_bool(preset, &["do_sample"], &mut params.do_sample);
+    set_bool(preset, &["add_bos_token"], &mut params.add_beos_token);
+    set_bool(preset, &["ban_eos_token"], &mut params.ban_beos_token);
+    set_bool(
+        preset,
+        &["skip_special_tokens"],
+        &mut params.skip_special_tokens,
+    );
+    set_bool(preset, &["temperature_last"], &mut params.temperature_last);
+
+    if let Some(bans) = get(preset, &["banned_tokens", "custom_token_bans"]) {
+        let (tokens, strings) = token_bans(bans);
+        params.banned_tokens = tokens;
+        params.banned_strings = strings;
+    }
+
+    if let Some(stops) =
+        get(preset, &["stopping_strings", "custom_stopping_strings"]).and_then(string_list)
+    {
+        params.stop_sequences = stops;
+    }
+
+    // A Mirostat or beam search preset rarely spells out `top_k` or `do_sample`, and their
+    // defaults conflict with it, so missing ones get the value the mode needs. Values the preset
+    // does give are kept, conflicts between them are left for validation to report.
+    if params.mirostat && get(preset, &["top_k"]).is_none() {
+        params.top_k = 0;
+    }
+    if (params.bs || params.cs) && get(preset, &["do_sample"]).is_none() {
+        params.do_sample = false;
+    }
+    params
+}
+
+fn get<'a>(preset: &'a Map<String, Value>, keys: &[&str]) -> Option<&'a Value> {
+    keys.iter().find_map(|key| preset.get(*key))
+}
+
+fn set_f64(preset: &Map<String, Value>, keys: &[&str], field: &mut f64) {
+    if let Some(value) = get(preset, keys).and_then(as_f64) {
+        *field = value;
+    }
+}
+
+fn set_i64(preset: &Map<String, Value>, keys: &[&str], field: &mut i64) {
+    if let Some(value) = get(preset, keys).and_then(as_f64) {
+        *field = value.round() as i64;
+    }
+}
+
+fn set_bool(preset: &Map<String, Value>, keys: &[&str], field: &mut bool) {
+    if let Some(value) = get(preset, keys).and_then(as_bool) {
+        *field = value;
+    }
+}
+
+// Presets are hand edited often enough that numbers and booleans show up as strings.
+fn as_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(string) => string.trim().parse().ok(),
+        Value::Bool(bool) => Some(if *bool { 1.0 } else { 0.0 }),
+        _ => None,
+    }
+}
+
+fn as_bool(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(bool) => Some(*bool),
+        Value::Number(number) => number.as_f64().map(|number| number != 0.0),
+        Value::String(string) => string.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+// SillyTavern stores string lists as a JSON encoded array (`"[\"\\n\", \":\"]"`), while
+// text-generation-webui uses a bare comma separated list of quoted strings (`"\n", ":"`).
+fn string_list(value: &Value) -> Option<Vec<String>> {
+    match value {
+        Value::Array(values) => Some(
+            values
+                .iter()
+                .filter_map(|value| value.as_str().map(str::to_string))
+                .collect(),
+        ),
+        Value::String(string) => {
+            let string = string.trim();
+            if string.is_empty() {
+                return Some(vec![]);
+            }
+            if let Ok(list) = serde_json::from_str::<Vec<String>>(string) {
+                return Some(list);
+            }
+            if let Ok(list) = serde_json::from_str::<Vec<String>>(&format!("[{}]", string)) {
+                return Some(list);
+            }
+            Some(
+                string
+                    .split(',')
+                    .map(|item| item.trim().to_string())
+                    .filter(|item| !item.is_empty())
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+// SillyTavern bans one entry per line, either token ids (`[1, 2]`) or text. text-generation-webui
+// bans a comma separated list of token ids.
+fn token_bans(value: &Value) -> (Vec<i64>, Vec<String>) {
+    let mut tokens = Vec::new();
+    let mut strings = Vec::new();
+    let entries: Vec<String> = match value {
+        Value::Array(values) => values
+            .iter()
+            .map(|value| match value {
+                Value::String(string) => string.clone(),
+                value => value.to_string(),
+            })
+            .collect(),
+        Value::String(string) => string.lines().map(str::to_string).collect(),
+        _ => vec![],
+    };
+    for entry in entries {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
+        }
+        if let Ok(ids) = serde_json::from_str::<Vec<i64>>(entry) {
+            tokens.extend(ids);
+        } else if let Ok(ids) = serde_json::from_str::<Vec<i64>>(&format!("[{}]", entry)) {
+            tokens.extend(ids);
+        } else {
+            strings.push(entry.trim_matches('"').to_string());
+        }
+    }
+    (tokens, strings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::models::parameters::ParameterError;
+
+    static SILLYTAVERN_PRESET: &str = r#"{
+        "temp": 1.25,
+        "temperature_last": true,
+        "top_p": 1,
+        "top_k": 0,
+        "min_p": 0.05,
+        "rep_pen": 1.1,
+        "rep_pen_range": 2048,
+        "freq_pen": 0,
+        "presence_pen": 0,
+        "smoothing_factor": 0.25,
+        "smoothing_curve": 1,
+        "dry_multiplier": 0.8,
+        "dry_base": 1.75,
+        "dry_allowed_length": 2,
+        "dry_sequence_breakers": "[\"\\n\", \":\", \"*\"]",
+        "dynatemp": true,
+        "min_temp": 0.5,
+        "max_temp": 1.5,
+        "mirostat_mode": 2,
+        "mirostat_tau": 4,
+        "mirostat_eta": 0.2,
+        "num_beams": 1,
+        "penalty_alpha": 0,
+        "ban_eos_token": false,
+        "banned_tokens": "[420, 69]\n\"As an AI\"",
+        "genamt": 300,
+        "max_length": 8192,
+        "unknown_key": "ignored"
+    }"#;
+
+    static TEXTGEN_WEBUI_PRESET: &str = r#"{
+        "temperature": 0.7,
+        "dynamic_temperature": false,
+        "repetition_penalty": 1.15,
+        "encoder_repetition_penalty": 1.0,
+        "typical_p": 0.95,
+        "dry_sequence_breakers": "\"\\n\", \":\", \"\\\"\"",
+        "num_beams": 4,
+        "length_penalty": 1.2,
+        "early_stopping": true,
+        "custom_token_bans": "13, 29871",
+        "max_new_tokens": "400"
+    }"#;
+
+    #[test]
+    fn test_sillytavern_preset() {
+        let params = from_json(SILLYTAVERN_PRESET).unwrap();
+        assert_eq!(params.temperature, 1.25);
+        assert_eq!(params.top_k, 0);
+        assert_eq!(params.repetition_penalty, 1.1);
+        assert_eq!(params.repetition_penalty_range, 2048);
+        assert_eq!(params.smoothing_factor, 0.25);
+        assert_eq!(params.dry_sequence_breakers, vec!["\n", ":", "*"]);
+        assert!(params.dt);
+        assert_eq!(params.dt_min_temperature, 0.5);
+        assert!(params.mirostat);
+        assert_eq!(params.mirostat_mode, 2);
+        assert_eq!(params.validate(), Ok(()));
+        assert!(!params.bs);
+        assert!(!params.cs);
+        assert_eq!(params.banned_tokens, vec![420, 69]);
+        assert_eq!(params.banned_strings, vec!["As an AI"]);
+        assert_eq!(params.max_tokens, 300);
+        assert_eq!(params.context_window, 8192);
+    }
+
+    #[test]
+    fn test_textgen_webui_preset() {
+        let params = from_json(TEXTGEN_WEBUI_PRESET).unwrap();
+        assert_eq!(params.temperature, 0.7);
+        assert!(!params.dt);
+        assert_eq!(params.repetition_penalty, 1.15);
+        assert_eq!(params.typical_p, 0.95);
+        assert_eq!(params.dry_sequence_breakers, vec!["\n", ":", "\""]);
+        assert!(params.bs);
+        assert_eq!(params.bs_n, 4);
+        assert!(params.bs_early_stopping);
+        assert!(!params.do_sample);
+        assert_eq!(params.banned_tokens, vec![13, 29871]);
+        assert_eq!(params.max_tokens, 400);
+        // Untouched fields keep their defaults.
+        assert_eq!(params.min_p, EngineParameters::default().min_p);
+    }
+
+    #[test]
+    fn test_mode_defaults() {
+        let params = from_json(r#"{"mirostat_mode": 2, "num_beams": 4}"#).unwrap();
+        assert_eq!(params.top_k, 0);
+        assert!(!params.do_sample);
+        let params = from_json(r#"{"mirostat_mode": 2, "top_k": 40}"#).unwrap();
+        assert_eq!(params.top_k, 40);
+        assert_eq!(
+            params.validate(),
+            Err(vec![ParameterError::Conflict("mirostat", "top_k")])
+        );
+    }
+
+    #[test]
+    fn test_not_an_object() {
+        assert!(from_json("[1, 2, 3]").is_err());
+    }
+}
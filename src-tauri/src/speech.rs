@@ -0,0 +1,3 @@
+pub mod sentences;
+pub mod synthesis;
+pub mod transcription;
@@ -0,0 +1,163 @@
+// Characters that can close a sentence after its final punctuation, like a quote.
+static CLOSERS: &[char] = &['"', '\'', '”', '’', ')', '*', '_'];
+
+// Abbreviations whose period doesn't end a sentence.
+static ABBREVIATIONS: &[&str] = &["mr", "mrs", "ms", "dr", "st", "vs", "etc", "e.g", "i.e"];
+
+// Splits streamed text into sentences as soon as each one is complete, so speech can start before
+// the reply is done. Sentences shorter than `min_chars` are merged into the next one, since every
+// clip costs a request.
+#[derive(Debug, Clone)]
+pub struct Sentences {
+    buffer: String,
+    min_chars: usize,
+}
+
+impl Sentences {
+    pub fn new(min_chars: usize) -> Self {
+        Self {
+            buffer: String::new(),
+            min_chars,
+        }
+    }
+
+    // Adds streamed tokens, returning the sentences they completed.
+    pub fn push(&mut self, tokens: &str) -> Vec<String> {
+        self.buffer.push_str(tokens);
+        let mut sentences = Vec::new();
+        let mut start = 0;
+        for end in boundaries(&self.buffer) {
+            let sentence = &self.buffer[start..end];
+            if sentence.trim().chars().count() < self.min_chars {
+                continue;
+            }
+            sentences.extend(speakable(sentence));
+            start = end;
+        }
+        self.buffer.drain(..start);
+        sentences
+    }
+
+    // Whatever is left once the stream ends.
+    pub fn finish(&mut self) -> Option<String> {
+        speakable(&std::mem::take(&mut self.buffer))
+    }
+}
+
+// Every sentence of a finished text.
+pub fn split(text: &str, min_chars: usize) -> Vec<String> {
+    let mut sentences = Sentences::new(min_chars);
+    let mut split = sentences.push(text);
+    split.extend(sentences.finish());
+    split
+}
+
+// Where sentences end in `text`, as byte offsets past their last character. A period only counts
+// once what follows it is known, so the end of the text is never a boundary.
+fn boundaries(text: &str) -> Vec<usize> {
+    let chars = text.char_indices().collect::<Vec<_>>();
+    let mut boundaries = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let (at, char) = chars[index];
+        if char == '\n' {
+            boundaries.push(at + 1);
+        } else if matches!(char, '.' | '!' | '?' | '…') && !is_abbreviation(&text[..at]) {
+            let mut next = index + 1;
+            while next < chars.len()
+                && (CLOSERS.contains(&chars[next].1) || ".!?".contains(chars[next].1))
+            {
+                next += 1;
+            }
+            match chars.get(next) {
+                Some((at, char)) if char.is_whitespace() => {
+                    boundaries.push(*at);
+                    index = next;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        index += 1;
+    }
+    boundaries
+}
+
+fn is_abbreviation(before: &str) -> bool {
+    let word = before
+        .rsplit(|char: char| char.is_whitespace())
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    ABBREVIATIONS.contains(&word.as_str())
+}
+
+// The sentence without the markdown around it, or nothing when there's nothing to say.
+pub fn speakable(text: &str) -> Option<String> {
+    let text = text
+        .chars()
+        .filter(|char| !matches!(char, '*' | '_' | '`' | '#'))
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    text.chars()
+        .any(|char| char.is_alphanumeric())
+        .then_some(text)
+}
+
+// Where speech starts in a reply, past the `Name:` prefix models echo from earlier replies.
+// Nothing while the reply could still turn out to be the prefix.
+pub fn speech_start(text: &str, speaker: Option<&str>) -> Option<usize> {
+    let Some(speaker) = speaker else {
+        return Some(0);
+    };
+    let prefix = format!("{}:", speaker);
+    let trimmed = text.trim_start();
+    if trimmed.starts_with(&prefix) {
+        Some(text.len() - trimmed.len() + prefix.len())
+    } else if prefix.starts_with(trimmed) {
+        None
+    } else {
+        Some(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push() {
+        let mut sentences = Sentences::new(0);
+        assert!(sentences.push("Hello there").is_empty());
+        assert_eq!(sentences.push(". How are"), vec!["Hello there."]);
+        assert_eq!(
+            sentences.push(" you? *She waves.* Fine"),
+            vec!["How are you?", "She waves."]
+        );
+        assert_eq!(sentences.finish(), Some("Fine".into()));
+        assert_eq!(sentences.finish(), None);
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(
+            split("Mr. Smith paid 3.50 dollars. \"Thanks!\" he said.\n...", 0),
+            vec!["Mr. Smith paid 3.50 dollars.", "\"Thanks!\"", "he said."]
+        );
+        // Short sentences are merged with the next one.
+        assert_eq!(
+            split("Oh. I see. That makes sense now.", 8),
+            vec!["Oh. I see.", "That makes sense now."]
+        );
+    }
+
+    #[test]
+    fn test_speech_start() {
+        assert_eq!(speech_start(" Nika: Hello.", Some("Nika")), Some(6));
+        assert_eq!(speech_start("Nik", Some("Nika")), None);
+        assert_eq!(speech_start("Nice day.", Some("Nika")), Some(0));
+        assert_eq!(speech_start("Nika: Hello.", None), Some(0));
+    }
+}
@@ -0,0 +1,635 @@
+use crate::prelude::*;
+
+use crate::DB;
+
+use std::path::Path;
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+use crate::{
+    character::Character, conversation::Conversation, events, models::message::Message,
+    tools::TOOL_CALL_START,
+};
+
+use super::sentences::{self, Sentences};
+
+// Sentences shorter than this are spoken together with the next one.
+static MIN_SENTENCE_CHARS: usize = 24;
+
+static LANGUAGE: &str = "en";
+
+// How a character sounds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Voice {
+    // A voice the backend knows by name, e.g. `alloy` on OpenAI compatible servers.
+    #[serde(default)]
+    pub name: Option<String>,
+    // A reference recording to clone the voice from, for XTTS and AllTalk.
+    #[serde(default)]
+    pub sample: Option<String>,
+    // Only backends that take it per request honor it.
+    #[serde(default = "default_speed")]
+    pub speed: f64,
+}
+
+fn default_speed() -> f64 {
+    1.0
+}
+
+impl Default for Voice {
+    fn default() -> Self {
+        Self {
+            name: None,
+            sample: None,
+            speed: default_speed(),
+        }
+    }
+}
+
+#[async_trait]
+pub trait Synthesizer: Send + Sync {
+    // The encoded audio, in whatever format the backend returns.
+    async fn synthesize(&mut self, text: &str, voice: &Voice, language: &str) -> Result<Vec<u8>>;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAiSynthesizerConfig {
+    pub url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+}
+
+impl Default for OpenAiSynthesizerConfig {
+    fn default() -> Self {
+        Self {
+            url: "http://localhost:8080/v1".into(),
+            api_key: None,
+            model: "tts-1".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalSynthesizerConfig {
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SynthesizerBackend {
+    OpenAi(OpenAiSynthesizerConfig),
+    Xtts(LocalSynthesizerConfig),
+    AllTalk(LocalSynthesizerConfig),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SynthesizerConfig {
+    pub backend: SynthesizerBackend,
+    // For the assistant, the user and characters without a voice of their own.
+    #[serde(default)]
+    pub voice: Voice,
+    #[serde(default)]
+    pub language: Option<String>,
+    // Replies are read out while they're being generated.
+    #[serde(default)]
+    pub speak_replies: bool,
+    // The oldest clips are deleted once the cache grows past this.
+    #[serde(default = "default_cache_bytes")]
+    pub cache_bytes: u64,
+}
+
+fn default_cache_bytes() -> u64 {
+    256 * 1024 * 1024
+}
+
+impl Default for SynthesizerConfig {
+    fn default() -> Self {
+        Self {
+            backend: SynthesizerBackend::OpenAi(OpenAiSynthesizerConfig::default()),
+            voice: Voice::default(),
+            language: None,
+            speak_replies: false,
+            cache_bytes: default_cache_bytes(),
+        }
+    }
+}
+
+impl SynthesizerConfig {
+    pub async fn get() -> Result<Self> {
+        let config: Option<Self> = db!().select(("synthesizer", "default")).await?;
+        Ok(config.unwrap_or_default())
+    }
+
+    pub async fn set(self) -> Result<Self> {
+        db!()
+            .upsert(("synthesizer", "default"))
+            .content(self)
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "upsert".into(),
+                "synthesizer".into(),
+            ))
+    }
+
+    pub fn into_synthesizer(self) -> Box<dyn Synthesizer> {
+        match self.backend {
+            SynthesizerBackend::OpenAi(config) => Box::new(OpenAiSynthesizer::new(
+                config.url,
+                config.api_key,
+                config.model,
+            )),
+            SynthesizerBackend::Xtts(config) => Box::new(XttsSynthesizer::new(config.url)),
+            SynthesizerBackend::AllTalk(config) => Box::new(AllTalkSynthesizer::new(config.url)),
+        }
+    }
+}
+
+// A clip spoken while a reply is generated, `part` counting the message's sentences.
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeechClip {
+    pub id: String,
+    pub index: usize,
+    pub part: usize,
+    pub path: String,
+}
+
+// Speaks with one voice, caching every clip in the data dir under the hash of everything that
+// went into it. Speaking a message twice, or after it was read out while generated, costs nothing.
+pub struct Narrator {
+    key: String,
+    language: String,
+    voice: Voice,
+    cache_bytes: u64,
+    synthesizer: Box<dyn Synthesizer>,
+}
+
+impl Narrator {
+    // Without a `voice` the configured one is used.
+    pub fn new(config: SynthesizerConfig, voice: Option<Voice>) -> Result<Self> {
+        let voice = voice.unwrap_or_else(|| config.voice.clone());
+        let language = config.language.clone().unwrap_or(LANGUAGE.to_string());
+        Ok(Self {
+            key: serde_json::to_string(&(&config.backend, &voice, &language))?,
+            language,
+            voice,
+            cache_bytes: config.cache_bytes,
+            synthesizer: config.into_synthesizer(),
+        })
+    }
+
+    fn dir() -> String {
+        format!("{}/speech", crate::data_dir())
+    }
+
+    fn path(&self, text: &str) -> String {
+        let hash = format!("{:x}", Sha256::digest(format!("{}\n{}", self.key, text)));
+        format!("{}/{}", Self::dir(), hash)
+    }
+
+    // The path of the clip saying `text`.
+    pub async fn clip(&mut self, text: &str) -> Result<String> {
+        let path = self.path(text);
+        if !tokio::fs::try_exists(&path).await? {
+            let speech = self
+                .synthesizer
+                .synthesize(text, &self.voice, &self.language)
+                .await?;
+            tokio::fs::create_dir_all(Self::dir()).await?;
+            tokio::fs::write(&path, speech).await?;
+            prune(&Self::dir(), self.cache_bytes).await?;
+        }
+        Ok(path)
+    }
+
+    // Deletes the clip saying `text`, if it was ever made.
+    pub async fn forget(&self, text: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.path(text)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Deletes the oldest clips until the ones left fit in `max_bytes`. The newest is always kept, it's
+// the one just made. Another narration may be pruning at the same time, so clips that are already
+// gone are fine.
+async fn prune(dir: &str, max_bytes: u64) -> Result<()> {
+    let mut clips = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        if metadata.is_file() {
+            clips.push((metadata.modified()?, metadata.len(), entry.path()));
+        }
+    }
+    clips.sort_by_key(|(modified, _, _)| std::cmp::Reverse(*modified));
+    let mut kept = clips.first().map_or(0, |(_, len, _)| *len);
+    for (_, len, path) in clips.into_iter().skip(1) {
+        kept += len;
+        if kept > max_bytes {
+            let _ = tokio::fs::remove_file(path).await;
+        }
+    }
+    Ok(())
+}
+
+// Reads a reply out a sentence at a time while it's being generated. Dropping it flushes the
+// last sentence.
+pub struct Narration {
+    sender: UnboundedSender<String>,
+}
+
+impl Narration {
+    // Nothing when replies aren't read out. `index` is the message the reply goes into, `speaker`
+    // the name of the character writing it.
+    pub async fn start(
+        id: String,
+        index: usize,
+        speaker: Option<String>,
+        voice: Option<Voice>,
+    ) -> Result<Option<Self>> {
+        let config = SynthesizerConfig::get().await?;
+        if !config.speak_replies {
+            return Ok(None);
+        }
+        let mut narrator = Narrator::new(config, voice)?;
+        let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+        tokio::spawn(async move {
+            let mut sentences = Sentences::new(MIN_SENTENCE_CHARS);
+            let mut full = String::new();
+            let mut pushed = 0;
+            let mut part = 0;
+            let mut calling = false;
+            let mut started = false;
+            while let Some(tokens) = receiver.recv().await {
+                full.push_str(&tokens);
+                if !started {
+                    match sentences::speech_start(&full, speaker.as_deref()) {
+                        Some(start) => {
+                            pushed = start;
+                            started = true;
+                        }
+                        None => continue,
+                    }
+                }
+                // Tool calls aren't part of what the character says, and anything from a `<` on
+                // could be the start of one.
+                let end = match full.find(TOOL_CALL_START) {
+                    Some(at) => {
+                        calling = true;
+                        at
+                    }
+                    None => full[pushed..]
+                        .rfind('<')
+                        .map_or(full.len(), |at| pushed + at),
+                };
+                let completed = sentences.push(&full[pushed..end.max(pushed)]);
+                pushed = end.max(pushed);
+                if !narrate(&mut narrator, &id, index, &mut part, completed).await {
+                    return;
+                }
+                if calling {
+                    break;
+                }
+            }
+            let mut rest = match calling {
+                true => vec![],
+                false => sentences.push(&full[pushed..]),
+            };
+            rest.extend(sentences.finish());
+            narrate(&mut narrator, &id, index, &mut part, rest).await;
+        });
+        Ok(Some(Self { sender }))
+    }
+
+    pub fn push(&self, tokens: &str) {
+        let _ = self.sender.send(tokens.to_string());
+    }
+}
+
+// Speaks the sentences as the next parts of the message. Returns false once a clip fails, after
+// which the rest of the reply stays silent.
+async fn narrate(
+    narrator: &mut Narrator,
+    id: &str,
+    index: usize,
+    part: &mut usize,
+    sentences: Vec<String>,
+) -> bool {
+    for sentence in sentences {
+        let path = match narrator.clip(&sentence).await {
+            Ok(path) => path,
+            Err(e) => {
+                let _ = events::emit_speech_error(&e.to_string()).await;
+                return false;
+            }
+        };
+        let clip = SpeechClip {
+            id: id.to_string(),
+            index,
+            part: *part,
+            path,
+        };
+        *part += 1;
+        if events::emit_speech_clip(&clip).await.is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+// The character who wrote the message: its author, or the only character of a one on one chat.
+async fn speaker(conversation: &Conversation, message: &Message) -> Result<Option<Character>> {
+    let characters = conversation.characters().await?;
+    let speaker = match &message.author {
+        Some(author) => characters
+            .into_iter()
+            .find(|(_, character)| character.id.to_string() == *author),
+        None if message.role == "assistant" && characters.len() == 1 => {
+            characters.into_iter().next()
+        }
+        None => None,
+    };
+    Ok(speaker.map(|(_, character)| character))
+}
+
+// The narrator in the message's author's voice and the sentences it says.
+async fn narration(conversation: &Conversation, index: usize) -> Result<(Narrator, Vec<String>)> {
+    let message = conversation
+        .messages
+        .get(index)
+        .ok_or(AliceError::IndexOutOfBounds(index))?;
+    let speaker = speaker(conversation, message).await?;
+    let name = speaker.as_ref().map(|speaker| speaker.name.as_str());
+    let start = sentences::speech_start(&message.content, name).unwrap_or(0);
+    let voice = speaker.and_then(|speaker| speaker.voice);
+    let narrator = Narrator::new(SynthesizerConfig::get().await?, voice)?;
+    Ok((
+        narrator,
+        sentences::split(&message.content[start..], MIN_SENTENCE_CHARS),
+    ))
+}
+
+// The clips reading out a message, in order, in its author's voice.
+pub async fn speak_message(id: String, index: usize) -> Result<Vec<String>> {
+    let conversation = Conversation::find(id).await?;
+    let (mut narrator, sentences) = narration(&conversation, index).await?;
+    let mut clips = Vec::new();
+    for sentence in sentences {
+        clips.push(narrator.clip(&sentence).await?);
+    }
+    Ok(clips)
+}
+
+// Deletes the cached clips reading out a message, before the message itself is deleted.
+pub async fn forget_message(conversation: &Conversation, index: usize) -> Result<()> {
+    let (narrator, sentences) = narration(conversation, index).await?;
+    for sentence in sentences {
+        narrator.forget(&sentence).await?;
+    }
+    Ok(())
+}
+
+// Any server exposing the OpenAI `/v1/audio/speech` endpoint, e.g. Kokoro-FastAPI or
+// openedai-speech.
+pub struct OpenAiSynthesizer {
+    client: Client,
+    url: String,
+    api_key: Option<String>,
+    model: String,
+}
+
+impl OpenAiSynthesizer {
+    pub fn new(url: String, api_key: Option<String>, model: String) -> Self {
+        Self {
+            client: Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            api_key,
+            model,
+        }
+    }
+}
+
+#[async_trait]
+impl Synthesizer for OpenAiSynthesizer {
+    async fn synthesize(&mut self, text: &str, voice: &Voice, _language: &str) -> Result<Vec<u8>> {
+        let request = self
+            .client
+            .post(format!("{}/audio/speech", self.url))
+            .json(&json!({
+                "model": self.model,
+                "input": text,
+                "voice": voice.name.as_deref().unwrap_or("alloy"),
+                "speed": voice.speed,
+                "response_format": "mp3",
+            }));
+        let request = match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        };
+        let data = request.send().await?.error_for_status()?.bytes().await?;
+        Ok(data.to_vec())
+    }
+}
+
+// xtts-api-server's `/tts_to_audio/`, which clones the voice from a sample on the server.
+pub struct XttsSynthesizer {
+    client: Client,
+    url: String,
+}
+
+impl XttsSynthesizer {
+    pub fn new(url: String) -> Self {
+        Self {
+            client: Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Synthesizer for XttsSynthesizer {
+    async fn synthesize(&mut self, text: &str, voice: &Voice, language: &str) -> Result<Vec<u8>> {
+        let sample = voice
+            .sample
+            .as_deref()
+            .ok_or(AliceError::MissingVoiceSample("XTTS".into()))?;
+        let data = self
+            .client
+            .post(format!("{}/tts_to_audio/", self.url))
+            .json(&json!({
+                "text": text,
+                "speaker_wav": sample,
+                "language": language,
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        Ok(data.to_vec())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AllTalkResult {
+    status: String,
+    output_file_url: Option<String>,
+}
+
+// AllTalk's `/api/tts-generate`, which writes the clip on the server and returns where to fetch
+// it from. Voices are picked by the sample's file name in AllTalk's voices folder.
+pub struct AllTalkSynthesizer {
+    client: Client,
+    url: String,
+}
+
+impl AllTalkSynthesizer {
+    pub fn new(url: String) -> Self {
+        Self {
+            client: Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Synthesizer for AllTalkSynthesizer {
+    async fn synthesize(&mut self, text: &str, voice: &Voice, language: &str) -> Result<Vec<u8>> {
+        let sample = voice
+            .sample
+            .as_deref()
+            .map(|sample| {
+                Path::new(sample)
+                    .file_name()
+                    .map_or(sample.to_string(), |name| {
+                        name.to_string_lossy().into_owned()
+                    })
+            })
+            .ok_or(AliceError::MissingVoiceSample("AllTalk".into()))?;
+        let result = self
+            .client
+            .post(format!("{}/api/tts-generate", self.url))
+            .form(&[
+                ("text_input", text),
+                ("text_filtering", "standard"),
+                ("character_voice_gen", &sample),
+                ("narrator_enabled", "false"),
+                ("language", language),
+                ("output_file_name", "alice"),
+                ("output_file_timestamp", "true"),
+                ("autoplay", "false"),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<AllTalkResult>()
+            .await?;
+        let url = match result.output_file_url {
+            Some(url) if result.status == "generate-success" => url,
+            _ => return Err(AliceError::Other(format!("AllTalk: {}", result.status))),
+        };
+        let url = match url.starts_with("http") {
+            true => url,
+            false => format!("{}{}", self.url, url),
+        };
+        let data = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        Ok(data.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, StubResponse};
+
+    #[tokio::test]
+    async fn test_openai_synthesizer() {
+        let (url, mut requests) =
+            testing::serve(|_| StubResponse::new(200, "audio/mpeg", b"ID3".to_vec())).await;
+        let voice = Voice {
+            name: Some("nova".into()),
+            sample: None,
+            speed: 1.25,
+        };
+        let speech = OpenAiSynthesizer::new(format!("{}/v1", url), None, "tts-1".into())
+            .synthesize("Hello.", &voice, "en")
+            .await
+            .unwrap();
+        assert_eq!(speech, b"ID3");
+
+        let request = requests.recv().await.unwrap();
+        assert_eq!(request.path, "/v1/audio/speech");
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["voice"], "nova");
+        assert_eq!(body["speed"], 1.25);
+    }
+
+    #[tokio::test]
+    async fn test_prune() {
+        let dir = std::env::temp_dir().join(format!("alice-speech-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let now = std::time::SystemTime::now();
+        for (name, age) in [("oldest", 3), ("older", 2), ("newest", 1)] {
+            let path = dir.join(name);
+            std::fs::write(&path, [0; 10]).unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(now - std::time::Duration::from_secs(age))
+                .unwrap();
+        }
+        let dir_path = dir.to_string_lossy();
+        prune(&dir_path, 25).await.unwrap();
+        assert!(!dir.join("oldest").exists());
+        assert!(dir.join("older").exists());
+        prune(&dir_path, 0).await.unwrap();
+        assert!(!dir.join("older").exists());
+        assert!(dir.join("newest").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_alltalk_synthesizer() {
+        let (url, mut requests) = testing::serve(|request| match request.path.as_str() {
+            "/api/tts-generate" => StubResponse::json(json!({
+                "status": "generate-success",
+                "output_file_path": "/alltalk/outputs/alice_1.wav",
+                "output_file_url": "/audio/alice_1.wav",
+            })),
+            _ => StubResponse::new(200, "audio/wav", b"RIFF".to_vec()),
+        })
+        .await;
+        let voice = Voice {
+            sample: Some("/samples/female_01.wav".into()),
+            ..Voice::default()
+        };
+        let speech = AllTalkSynthesizer::new(url)
+            .synthesize("Hello.", &voice, "en")
+            .await
+            .unwrap();
+        assert_eq!(speech, b"RIFF");
+
+        let generate = requests.recv().await.unwrap();
+        assert!(generate
+            .text()
+            .contains("character_voice_gen=female_01.wav"));
+        assert_eq!(requests.recv().await.unwrap().path, "/audio/alice_1.wav");
+        assert!(AllTalkSynthesizer::new("http://localhost".into())
+            .synthesize("Hello.", &Voice::default(), "en")
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,329 @@
+use crate::prelude::*;
+
+use crate::DB;
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use reqwest::{
+    multipart::{Form, Part},
+    Client,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    api::abstractions::{sockets::ClientSocket, MethodCall},
+    config::UllmConfig,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptSegment {
+    // Seconds from the start of the recording.
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Transcription {
+    pub text: String,
+    // What the backend detected, when it says.
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub segments: Vec<TranscriptSegment>,
+}
+
+#[async_trait]
+pub trait Transcriber: Send + Sync {
+    // `mime` is the recording's format, e.g. `audio/webm` from the browser's `MediaRecorder`.
+    // Without a `language` the backend detects it.
+    async fn transcribe(
+        &mut self,
+        audio: Vec<u8>,
+        mime: &str,
+        language: Option<String>,
+    ) -> Result<Transcription>;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAiTranscriberConfig {
+    pub url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+}
+
+impl Default for OpenAiTranscriberConfig {
+    fn default() -> Self {
+        Self {
+            url: "http://localhost:8080/v1".into(),
+            api_key: None,
+            model: "whisper-1".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TranscriberBackend {
+    Ullm(UllmConfig),
+    OpenAi(OpenAiTranscriberConfig),
+}
+
+// Which backend recordings are transcribed with, kept apart from the completion API since speech
+// models are usually served separately.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriberConfig {
+    pub backend: TranscriberBackend,
+    // Used when a recording doesn't come with one.
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+impl Default for TranscriberConfig {
+    fn default() -> Self {
+        Self {
+            backend: TranscriberBackend::OpenAi(OpenAiTranscriberConfig::default()),
+            language: None,
+        }
+    }
+}
+
+impl TranscriberConfig {
+    pub async fn get() -> Result<Self> {
+        let config: Option<Self> = db!().select(("transcriber", "default")).await?;
+        Ok(config.unwrap_or_default())
+    }
+
+    pub async fn set(self) -> Result<Self> {
+        db!()
+            .upsert(("transcriber", "default"))
+            .content(self)
+            .await?
+            .ok_or(AliceError::DatabaseOperation(
+                "upsert".into(),
+                "transcriber".into(),
+            ))
+    }
+
+    pub fn into_transcriber(self) -> Result<Box<dyn Transcriber>> {
+        match self.backend {
+            TranscriberBackend::Ullm(config) => Ok(Box::new(UllmTranscriber::new(config.url)?)),
+            TranscriberBackend::OpenAi(config) => Ok(Box::new(OpenAiTranscriber::new(
+                config.url,
+                config.api_key,
+                config.model,
+            ))),
+        }
+    }
+}
+
+// Transcribes with the configured backend.
+pub async fn transcribe(
+    audio: Vec<u8>,
+    mime: &str,
+    language: Option<String>,
+) -> Result<Transcription> {
+    if audio.is_empty() {
+        return Err(AliceError::EmptyRecording);
+    }
+    let config = TranscriberConfig::get().await?;
+    let language = language.or(config.language.clone());
+    config
+        .into_transcriber()?
+        .transcribe(audio, mime, language)
+        .await
+}
+
+// Any server exposing the OpenAI `/v1/audio/transcriptions` endpoint, e.g. whisper.cpp's server
+// or faster-whisper-server.
+pub struct OpenAiTranscriber {
+    client: Client,
+    url: String,
+    api_key: Option<String>,
+    model: String,
+}
+
+impl OpenAiTranscriber {
+    pub fn new(url: String, api_key: Option<String>, model: String) -> Self {
+        Self {
+            client: Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            api_key,
+            model,
+        }
+    }
+}
+
+#[async_trait]
+impl Transcriber for OpenAiTranscriber {
+    async fn transcribe(
+        &mut self,
+        audio: Vec<u8>,
+        mime: &str,
+        language: Option<String>,
+    ) -> Result<Transcription> {
+        // Servers tell the format by the file name's extension as often as by the content type.
+        let file = Part::bytes(audio)
+            .file_name(format!("recording.{}", extension(mime)))
+            .mime_str(mime)?;
+        let mut form = Form::new()
+            .part("file", file)
+            .text("model", self.model.clone())
+            .text("response_format", "verbose_json")
+            .text("timestamp_granularities[]", "segment");
+        if let Some(language) = language {
+            form = form.text("language", language);
+        }
+        let request = self
+            .client
+            .post(format!("{}/audio/transcriptions", self.url))
+            .multipart(form);
+        let request = match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        };
+        let mut transcription = request
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Transcription>()
+            .await?;
+        transcription.text = transcription.text.trim().to_string();
+        for segment in &mut transcription.segments {
+            segment.text = segment.text.trim().to_string();
+        }
+        Ok(transcription)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct TranscribeParams {
+    // Base64, the socket only carries JSON.
+    audio: String,
+    mime: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct TranscribeResponse {
+    result: Transcription,
+}
+
+// µLLM's `transcribe` method, on a connection of its own so a long completion doesn't hold up
+// the recording.
+pub struct UllmTranscriber {
+    client: ClientSocket,
+}
+
+impl UllmTranscriber {
+    pub fn new(addr: String) -> Result<Self> {
+        Ok(Self {
+            client: ClientSocket::new(addr)?,
+        })
+    }
+}
+
+#[async_trait]
+impl Transcriber for UllmTranscriber {
+    async fn transcribe(
+        &mut self,
+        audio: Vec<u8>,
+        mime: &str,
+        language: Option<String>,
+    ) -> Result<Transcription> {
+        if !self.client.is_connected().await {
+            self.client.connect().await?;
+        }
+        let transcribe = MethodCall {
+            id: Uuid::new_v4(),
+            method: "transcribe".to_string(),
+            params: Some(TranscribeParams {
+                audio: STANDARD.encode(audio),
+                mime: mime.to_string(),
+                language,
+            }),
+        };
+        self.client
+            .send_str(serde_json::to_string(&transcribe)?)
+            .await?;
+        let transcription = self
+            .client
+            .return_single::<TranscribeResponse>()
+            .await?
+            .result;
+        let _ = self.client.disconnect().await;
+        Ok(transcription)
+    }
+}
+
+// The file extension for a recording's MIME type, ignoring parameters like the codec.
+fn extension(mime: &str) -> &'static str {
+    let essence = mime.split(';').next().unwrap_or_default().trim();
+    match essence.to_lowercase().as_str() {
+        "audio/wav" | "audio/wave" | "audio/x-wav" => "wav",
+        "audio/mpeg" | "audio/mp3" => "mp3",
+        "audio/mp4" | "audio/m4a" | "audio/x-m4a" => "m4a",
+        "audio/ogg" => "ogg",
+        "audio/flac" | "audio/x-flac" => "flac",
+        _ => "webm",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::{self, StubResponse};
+
+    #[test]
+    fn test_extension() {
+        assert_eq!(extension("audio/webm;codecs=opus"), "webm");
+        assert_eq!(extension("audio/x-wav"), "wav");
+        assert_eq!(extension("audio/MPEG"), "mp3");
+    }
+
+    #[tokio::test]
+    async fn test_openai_transcriber() {
+        let (url, mut requests) = testing::serve(|_| {
+            StubResponse::json(json!({
+                "text": " Hello there. General Kenobi.",
+                "language": "english",
+                "duration": 2.5,
+                "segments": [
+                    {"id": 0, "start": 0.0, "end": 1.2, "text": " Hello there."},
+                    {"id": 1, "start": 1.2, "end": 2.5, "text": " General Kenobi."},
+                ],
+            }))
+        })
+        .await;
+        let mut transcriber =
+            OpenAiTranscriber::new(format!("{}/v1/", url), None, "whisper-1".into());
+        let transcription = transcriber
+            .transcribe(b"RIFF".to_vec(), "audio/wav", Some("en".into()))
+            .await
+            .unwrap();
+        assert_eq!(transcription.text, "Hello there. General Kenobi.");
+        assert_eq!(transcription.language.as_deref(), Some("english"));
+        assert_eq!(
+            transcription.segments[1],
+            TranscriptSegment {
+                start: 1.2,
+                end: 2.5,
+                text: "General Kenobi.".into(),
+            }
+        );
+
+        let request = requests.recv().await.unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/v1/audio/transcriptions");
+        assert!(request
+            .header("content-type")
+            .is_some_and(|content_type| content_type.starts_with("multipart/form-data")));
+        let body = request.text();
+        assert!(body.contains("filename=\"recording.wav\""));
+        assert!(body.contains("verbose_json"));
+        assert!(body.contains("name=\"language\"\r\n\r\nen"));
+    }
+}
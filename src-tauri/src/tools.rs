@@ -0,0 +1,183 @@
+use std::sync::Arc;
+
+use crate::prelude::*;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub mod calculator;
+pub mod search;
+pub mod time;
+pub mod web;
+
+pub static TOOL_CALL_START: &str = "<tool_call>";
+pub static TOOL_CALL_END: &str = "</tool_call>";
+
+#[async_trait]
+pub trait Tool: Send + Sync {
+    fn name(&self) -> &str;
+    fn description(&self) -> &str;
+    // JSON schema of the arguments object.
+    fn parameters(&self) -> Value;
+
+    async fn call(&self, arguments: Value) -> Result<String>;
+}
+
+// What the prompt template gets to see of a tool.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolDescription {
+    pub name: String,
+    pub description: String,
+    pub parameters: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub name: String,
+    #[serde(default, alias = "parameters")]
+    pub arguments: Value,
+}
+
+pub struct ToolRegistry {
+    tools: Vec<Arc<dyn Tool>>,
+}
+
+impl Default for ToolRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register(Arc::new(calculator::Calculator));
+        registry.register(Arc::new(time::CurrentTime));
+        registry.register(Arc::new(search::ConversationSearch::default()));
+        registry
+    }
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self { tools: Vec::new() }
+    }
+
+    // The default tools, plus whichever optional ones are turned on.
+    pub async fn enabled() -> Result<Self> {
+        let mut registry = Self::default();
+        web::register(&mut registry).await?;
+        Ok(registry)
+    }
+
+    // The enabled tools as called from the conversation `id`.
+    pub async fn for_conversation(id: &str) -> Result<Self> {
+        let mut registry = Self::enabled().await?;
+        registry.register(Arc::new(search::ConversationSearch::excluding(
+            id.to_string(),
+        )));
+        Ok(registry)
+    }
+
+    // Registering a tool with an existing name replaces the old one.
+    pub fn register(&mut self, tool: Arc<dyn Tool>) {
+        self.tools.retain(|existing| existing.name() != tool.name());
+        self.tools.push(tool);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Tool>> {
+        self.tools.iter().find(|tool| tool.name() == name).cloned()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    pub fn describe(&self) -> Vec<ToolDescription> {
+        self.tools
+            .iter()
+            .map(|tool| ToolDescription {
+                name: tool.name().to_string(),
+                description: tool.description().to_string(),
+                parameters: tool.parameters().to_string(),
+            })
+            .collect()
+    }
+
+    // Failures are reported back to the model instead of aborting the generation, so it gets a
+    // chance to correct itself.
+    pub async fn call(&self, call: &ToolCall) -> String {
+        let result = match self.get(&call.name) {
+            Some(tool) => tool.call(call.arguments.clone()).await,
+            None => Err(AliceError::UnknownTool(call.name.clone())),
+        };
+        match result {
+            Ok(output) => output,
+            Err(e) => format!("Error: {}", e),
+        }
+    }
+}
+
+// Finds every `<tool_call>{"name": ..., "arguments": {...}}</tool_call>` block in a completion.
+// The closing tag is used as a stop sequence, so the last block is allowed to be unterminated.
+pub fn parse_tool_calls(completion: &str) -> Vec<ToolCall> {
+    let mut calls = Vec::new();
+    let mut rest = completion;
+    while let Some(start) = rest.find(TOOL_CALL_START) {
+        rest = &rest[start + TOOL_CALL_START.len()..];
+        let (block, remainder) = match rest.find(TOOL_CALL_END) {
+            Some(end) => (&rest[..end], &rest[end + TOOL_CALL_END.len()..]),
+            None => (rest, ""),
+        };
+        if let Ok(call) = serde_json::from_str::<ToolCall>(block.trim()) {
+            calls.push(call);
+        }
+        rest = remainder;
+    }
+    calls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_tool_calls() {
+        let completion = r#"Let me check.
+<tool_call>{"name": "calculator", "arguments": {"expression": "2 + 2"}}</tool_call>
+<tool_call>
+{"name": "current_time", "parameters": {}}
+"#;
+        assert_eq!(
+            parse_tool_calls(completion),
+            vec![
+                ToolCall {
+                    name: "calculator".into(),
+                    arguments: json!({"expression": "2 + 2"}),
+                },
+                ToolCall {
+                    name: "current_time".into(),
+                    arguments: json!({}),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_tool_call() {
+        assert!(parse_tool_calls("<tool_call>not json</tool_call>").is_empty());
+        assert!(parse_tool_calls("no tools here").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_registry_call() {
+        let registry = ToolRegistry::default();
+        let call = ToolCall {
+            name: "calculator".into(),
+            arguments: json!({"expression": "(1 + 2) * 3"}),
+        };
+        assert_eq!(registry.call(&call).await, "9");
+        let call = ToolCall {
+            name: "missing".into(),
+            arguments: json!({}),
+        };
+        assert_eq!(registry.call(&call).await, "Error: Unknown tool: missing");
+    }
+}
@@ -0,0 +1,199 @@
+use std::{iter::Peekable, str::Chars};
+
+use crate::prelude::*;
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use super::Tool;
+
+// How deep parentheses and signs may nest, so a long run of them can't overflow the stack.
+const MAX_DEPTH: usize = 64;
+
+pub struct Calculator;
+
+#[async_trait]
+impl Tool for Calculator {
+    fn name(&self) -> &str {
+        "calculator"
+    }
+
+    fn description(&self) -> &str {
+        "Evaluates an arithmetic expression with + - * / % ^ and parentheses."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "expression": { "type": "string" }
+            },
+            "required": ["expression"]
+        })
+    }
+
+    async fn call(&self, arguments: Value) -> Result<String> {
+        let expression = arguments.get("expression").and_then(Value::as_str).ok_or(
+            AliceError::InvalidToolArguments("`expression` must be a string".into()),
+        )?;
+        let result = evaluate(expression)?;
+        Ok(if result.fract() == 0.0 && result.abs() < 1e15 {
+            format!("{}", result as i64)
+        } else {
+            format!("{}", result)
+        })
+    }
+}
+
+pub fn evaluate(expression: &str) -> Result<f64> {
+    let mut chars = expression.chars().peekable();
+    let result = expr(&mut chars, 0)?;
+    skip_whitespace(&mut chars);
+    match chars.next() {
+        None if result.is_finite() => Ok(result),
+        None => Err(AliceError::InvalidToolArguments(
+            "result is not a finite number".into(),
+        )),
+        Some(c) => Err(AliceError::InvalidToolArguments(format!(
+            "unexpected `{}`",
+            c
+        ))),
+    }
+}
+
+// expr := term (('+' | '-') term)*
+fn expr(chars: &mut Peekable<Chars>, depth: usize) -> Result<f64> {
+    let mut value = term(chars, depth)?;
+    loop {
+        skip_whitespace(chars);
+        match chars.peek() {
+            Some('+') => {
+                chars.next();
+                value += term(chars, depth)?;
+            }
+            Some('-') => {
+                chars.next();
+                value -= term(chars, depth)?;
+            }
+            _ => return Ok(value),
+        }
+    }
+}
+
+// term := unary (('*' | '/' | '%') unary)*
+fn term(chars: &mut Peekable<Chars>, depth: usize) -> Result<f64> {
+    let mut value = unary(chars, depth)?;
+    loop {
+        skip_whitespace(chars);
+        match chars.peek() {
+            Some('*') => {
+                chars.next();
+                value *= unary(chars, depth)?;
+            }
+            Some('/') => {
+                chars.next();
+                value /= unary(chars, depth)?;
+            }
+            Some('%') => {
+                chars.next();
+                value %= unary(chars, depth)?;
+            }
+            _ => return Ok(value),
+        }
+    }
+}
+
+// unary := '-' unary | power
+fn unary(chars: &mut Peekable<Chars>, depth: usize) -> Result<f64> {
+    // Every way back into the grammar goes through here.
+    if depth > MAX_DEPTH {
+        return Err(AliceError::InvalidToolArguments(
+            "expression is nested too deeply".into(),
+        ));
+    }
+    skip_whitespace(chars);
+    if let Some('-') = chars.peek() {
+        chars.next();
+        return Ok(-unary(chars, depth + 1)?);
+    }
+    power(chars, depth)
+}
+
+// power := atom ('^' unary)?
+fn power(chars: &mut Peekable<Chars>, depth: usize) -> Result<f64> {
+    let base = atom(chars, depth)?;
+    skip_whitespace(chars);
+    if let Some('^') = chars.peek() {
+        chars.next();
+        return Ok(base.powf(unary(chars, depth + 1)?));
+    }
+    Ok(base)
+}
+
+// atom := number | '(' expr ')'
+fn atom(chars: &mut Peekable<Chars>, depth: usize) -> Result<f64> {
+    skip_whitespace(chars);
+    match chars.peek() {
+        Some('(') => {
+            chars.next();
+            let value = expr(chars, depth + 1)?;
+            skip_whitespace(chars);
+            match chars.next() {
+                Some(')') => Ok(value),
+                _ => Err(AliceError::InvalidToolArguments("expected `)`".into())),
+            }
+        }
+        Some(c) if c.is_ascii_digit() || *c == '.' => {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_digit() || c == '.') {
+                    break;
+                }
+                number.push(c);
+                chars.next();
+            }
+            number.parse().map_err(|_| {
+                AliceError::InvalidToolArguments(format!("invalid number `{}`", number))
+            })
+        }
+        Some(c) => Err(AliceError::InvalidToolArguments(format!(
+            "unexpected `{}`",
+            c
+        ))),
+        None => Err(AliceError::InvalidToolArguments(
+            "unexpected end of expression".into(),
+        )),
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(evaluate("1 + 2 * 3").unwrap(), 7.0);
+        assert_eq!(evaluate("(1 + 2) * 3").unwrap(), 9.0);
+        assert_eq!(evaluate("2 ^ 3 ^ 2").unwrap(), 512.0);
+        assert_eq!(evaluate("-2 ^ 2").unwrap(), -4.0);
+        assert_eq!(evaluate("2 ^ -1").unwrap(), 0.5);
+        assert_eq!(evaluate("10 % 4 - .5").unwrap(), 1.5);
+    }
+
+    #[test]
+    fn test_evaluate_errors() {
+        assert!(evaluate("1 +").is_err());
+        assert!(evaluate("(1 + 2").is_err());
+        assert!(evaluate("1 / 0").is_err());
+        assert!(evaluate("2 x 3").is_err());
+        assert!(evaluate(&format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000))).is_err());
+        assert!(evaluate(&format!("{}1", "-".repeat(100_000))).is_err());
+        assert!(evaluate(&format!("{}1{}", "(".repeat(32), ")".repeat(32))).is_ok());
+    }
+}
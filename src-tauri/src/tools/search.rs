@@ -0,0 +1,70 @@
+use crate::prelude::*;
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::conversation::Conversation;
+
+use super::Tool;
+
+static DEFAULT_LIMIT: usize = 5;
+// The model picks the limit, this keeps a careless one from flooding the context.
+static MAX_LIMIT: usize = 20;
+
+// Searches every conversation but the one it's called from, which would only find the call.
+#[derive(Default)]
+pub struct ConversationSearch {
+    exclude: Option<String>,
+}
+
+impl ConversationSearch {
+    pub fn excluding(id: String) -> Self {
+        Self { exclude: Some(id) }
+    }
+}
+
+#[async_trait]
+impl Tool for ConversationSearch {
+    fn name(&self) -> &str {
+        "conversation_search"
+    }
+
+    fn description(&self) -> &str {
+        "Searches the messages of all past conversations for a piece of text."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string" },
+                "limit": { "type": "integer", "minimum": 1, "maximum": MAX_LIMIT }
+            },
+            "required": ["query"]
+        })
+    }
+
+    async fn call(&self, arguments: Value) -> Result<String> {
+        let query = arguments.get("query").and_then(Value::as_str).ok_or(
+            AliceError::InvalidToolArguments("`query` must be a string".into()),
+        )?;
+        let limit = arguments
+            .get("limit")
+            .and_then(Value::as_u64)
+            .map(|limit| (limit as usize).clamp(1, MAX_LIMIT))
+            .unwrap_or(DEFAULT_LIMIT);
+        let matches =
+            Conversation::search_messages(query.to_string(), limit, self.exclude.clone()).await?;
+        if matches.is_empty() {
+            return Ok("No matching messages found.".into());
+        }
+        let mut result = String::new();
+        for conversation in matches {
+            result.push_str(&format!("In \"{}\":\n", conversation.name));
+            for message in conversation.messages {
+                result.push_str(&format!("- {}: {}\n", message.role, message.content));
+            }
+        }
+        Ok(result.trim_end().to_string())
+    }
+}
@@ -0,0 +1,31 @@
+use crate::prelude::*;
+
+use async_trait::async_trait;
+use chrono::Local;
+use serde_json::{json, Value};
+
+use super::Tool;
+
+pub struct CurrentTime;
+
+#[async_trait]
+impl Tool for CurrentTime {
+    fn name(&self) -> &str {
+        "current_time"
+    }
+
+    fn description(&self) -> &str {
+        "Returns the current local date, time and weekday."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {}
+        })
+    }
+
+    async fn call(&self, _arguments: Value) -> Result<String> {
+        Ok(Local::now().format("%A %Y-%m-%d %H:%M:%S %:z").to_string())
+    }
+}
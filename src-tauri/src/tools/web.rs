@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use crate::prelude::*;
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::browsing::{Browser, BrowsingConfig};
+
+use super::{Tool, ToolRegistry};
+
+static DEFAULT_LIMIT: usize = 5;
+
+// Pages are cut down to this many characters so one fetch can't fill the context window.
+static MAX_PAGE_CHARS: usize = 8000;
+
+// Adds the web tools when browsing is enabled.
+pub async fn register(registry: &mut ToolRegistry) -> Result<()> {
+    if BrowsingConfig::get().await?.enabled {
+        registry.register(Arc::new(WebSearch));
+        registry.register(Arc::new(FetchPage));
+    }
+    Ok(())
+}
+
+pub struct WebSearch;
+
+#[async_trait]
+impl Tool for WebSearch {
+    fn name(&self) -> &str {
+        "web_search"
+    }
+
+    fn description(&self) -> &str {
+        "Searches the web, returning the title, URL and a snippet of each result."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string" },
+                "limit": { "type": "integer" }
+            },
+            "required": ["query"]
+        })
+    }
+
+    async fn call(&self, arguments: Value) -> Result<String> {
+        let query = arguments.get("query").and_then(Value::as_str).ok_or(
+            AliceError::InvalidToolArguments("`query` must be a string".into()),
+        )?;
+        let limit = arguments
+            .get("limit")
+            .and_then(Value::as_u64)
+            .map(|limit| limit as usize)
+            .unwrap_or(DEFAULT_LIMIT);
+        let results = Browser::with_config().await?.search(query, limit).await?;
+        if results.is_empty() {
+            return Ok("No results found.".into());
+        }
+        let mut output = String::new();
+        for result in results {
+            output.push_str(&format!("- {} ({})\n", result.title, result.url));
+            if !result.content.is_empty() {
+                output.push_str(&format!("  {}\n", result.content));
+            }
+        }
+        Ok(output.trim_end().to_string())
+    }
+}
+
+pub struct FetchPage;
+
+#[async_trait]
+impl Tool for FetchPage {
+    fn name(&self) -> &str {
+        "fetch_page"
+    }
+
+    fn description(&self) -> &str {
+        "Fetches a web page and returns its main text."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "url": { "type": "string" }
+            },
+            "required": ["url"]
+        })
+    }
+
+    async fn call(&self, arguments: Value) -> Result<String> {
+        let url = arguments.get("url").and_then(Value::as_str).ok_or(
+            AliceError::InvalidToolArguments("`url` must be a string".into()),
+        )?;
+        let page = Browser::with_config().await?.fetch(url).await?;
+        let mut output = match page.title {
+            Some(title) => format!("{} ({})\n\n", title, page.url),
+            None => format!("{}\n\n", page.url),
+        };
+        let mut content = page.content.chars();
+        output.extend(content.by_ref().take(MAX_PAGE_CHARS));
+        if page.truncated || content.next().is_some() {
+            output.push_str("\n\n[The page was cut off here.]");
+        }
+        Ok(output)
+    }
+}
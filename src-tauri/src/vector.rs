@@ -0,0 +1,75 @@
+use crate::prelude::*;
+
+use crate::DB;
+
+use serde::{Deserialize, Serialize};
+
+// Nearest-neighbour search over the embeddings of memories and document chunks. Each table keeps
+// its embeddings in an `embedding` field with an HNSW index named after the table.
+
+// How many of the latest messages memories and passages are looked up with.
+pub static QUERY_MESSAGES: usize = 2;
+
+// Candidates the HNSW search keeps while walking the graph, higher is slower but more accurate.
+pub static SEARCH_EF: usize = 40;
+
+// Candidates fetched per result wanted, for filters that can only be applied after the search.
+pub static OVERFETCH: usize = 4;
+
+// The size of the embeddings a table is indexed for, stored at ("vector_index", table).
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexedDimension {
+    dimension: usize,
+}
+
+// Selects `fields` and the cosine similarity to `$embedding` as `score` of the `neighbours` rows
+// nearest to it, best first. `condition` is applied during the search, so rows it leaves out don't
+// take the neighbours' places. The number of neighbours has to be a literal in the KNN operator.
+pub fn knn(table: &str, fields: &str, neighbours: usize, condition: Option<&str>) -> String {
+    let condition = condition.map_or(String::new(), |condition| format!(" AND ({})", condition));
+    format!(
+        "SELECT {}, vector::similarity::cosine(embedding, $embedding) AS score FROM {} WHERE embedding <|{},{}|> $embedding{} ORDER BY score DESC",
+        fields, table, neighbours, SEARCH_EF, condition
+    )
+}
+
+// The index is defined once the embedding size is known, which depends on the model. Embeddings of
+// another size were made by another model and can't be compared with new ones, so when the size
+// changes they're deleted and the index is defined again. Returns whether that happened.
+pub async fn define_index(table: &str, dimension: usize) -> Result<bool> {
+    let indexed: Option<IndexedDimension> = db!().select(("vector_index", table)).await?;
+    if indexed
+        .as_ref()
+        .is_some_and(|indexed| indexed.dimension == dimension)
+    {
+        return Ok(false);
+    }
+    db!()
+        .query(format!(
+            "REMOVE INDEX IF EXISTS {table}_embedding ON {table}; DELETE {table} WHERE array::len(embedding) != {dimension}; DEFINE INDEX {table}_embedding ON {table} FIELDS embedding HNSW DIMENSION {dimension} DIST COSINE",
+        ))
+        .await?
+        .check()?;
+    let _: Option<IndexedDimension> = db!()
+        .upsert(("vector_index", table))
+        .content(IndexedDimension { dimension })
+        .await?;
+    Ok(indexed.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_knn() {
+        assert_eq!(
+            knn("memory", "content", 8, None),
+            "SELECT content, vector::similarity::cosine(embedding, $embedding) AS score FROM memory WHERE embedding <|8,40|> $embedding ORDER BY score DESC"
+        );
+        assert!(
+            knn("document_chunk", "content", 4, Some("conversation = NONE"))
+                .contains("$embedding AND (conversation = NONE) ORDER BY")
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use super::{
+    format::TRAITS,
+    item::{Attribute, WppItem},
+    split,
+};
+
+// Boostyle, e.g. `Nika Orchid ( Shy + Quiet + Nickname: Nika + Age: 19 )`. Terms are joined by
+// `+`, a `Key:` prefix files the term under that attribute and the rest are free traits. Like
+// PList it has no item types, and terms with `+` or `)` in them are quoted.
+
+pub fn parse(input: &str) -> Result<Vec<WppItem>, String> {
+    let mut items = Vec::new();
+    let mut rest = input.trim();
+    while !rest.is_empty() {
+        let Some((name, body)) = rest.split_once('(') else {
+            return Err(format!("expected `(` after `{}`", rest.trim()));
+        };
+        let Some(end) = split::find(body, ')') else {
+            return Err("unterminated `(`".into());
+        };
+        let (terms, tail) = (&body[..end], &body[end + 1..]);
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("missing name".into());
+        }
+        items.push(parse_terms(name, terms));
+        rest = tail.trim_start();
+    }
+    Ok(items)
+}
+
+fn parse_terms(name: &str, terms: &str) -> WppItem {
+    // Attributes in order of first appearance, so repeated keys collect their values.
+    let mut attributes: Vec<Attribute> = Vec::new();
+    for term in split::split(terms, '+') {
+        let (key, value) = split::key_value(term)
+            .filter(|(key, _)| !key.is_empty())
+            .unwrap_or((TRAITS, term));
+        let value = split::unquote(value);
+        match attributes
+            .iter_mut()
+            .find(|attribute| attribute.name() == key)
+        {
+            Some(attribute) => attribute.add_value(value),
+            None => {
+                let mut attribute = Attribute::new(key);
+                attribute.add_value(value);
+                attributes.push(attribute);
+            }
+        }
+    }
+    let mut item = WppItem::new("Character", name);
+    for attribute in attributes {
+        item.add_attribute(attribute);
+    }
+    item
+}
+
+pub fn format(items: &[WppItem]) -> String {
+    items.iter().map(format_item).collect::<Vec<_>>().join("\n")
+}
+
+fn format_item(item: &WppItem) -> String {
+    let terms = item
+        .attributes()
+        .iter()
+        .flat_map(|attribute| {
+            attribute.values().iter().map(move |value| {
+                if attribute.name() == TRAITS {
+                    // A trait with a colon would read back as a key.
+                    split::quote(value, &['+', ')', ':'])
+                } else {
+                    format!("{}: {}", attribute.name(), split::quote(value, &['+', ')']))
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    format!("{} ( {} )", item.name(), terms.join(" + "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let items =
+            parse("Nika Orchid ( Shy + Age: 19 + Quiet + Age: 19 years old )\nMira ()").unwrap();
+        assert_eq!(items.len(), 2);
+        let nika = &items[0];
+        assert_eq!(nika.name(), "Nika Orchid");
+        assert_eq!(nika.attributes()[0].name(), TRAITS);
+        assert_eq!(nika.attributes()[0].values(), &vec!["Shy", "Quiet"]);
+        assert_eq!(nika.attributes()[1].values(), &vec!["19", "19 years old"]);
+        assert!(items[1].attributes().is_empty());
+
+        assert!(parse("Nika Orchid").is_err());
+        assert!(parse("Nika ( Shy").is_err());
+        assert!(parse("( Shy )").is_err());
+    }
+
+    #[test]
+    fn test_parse_compound_values() {
+        let items =
+            parse(r#"Nika ( Wakes at 5:30 + Likes: tea (green) + "Salt + pepper" )"#).unwrap();
+        let nika = &items[0];
+        assert_eq!(
+            nika.attribute(TRAITS).unwrap().values(),
+            &vec!["Wakes at 5:30", "Salt + pepper"]
+        );
+        assert_eq!(
+            nika.attribute("Likes").unwrap().values(),
+            &vec!["tea (green)"]
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let input = "Nika Orchid ( Shy + Quiet + Nickname: Nika + Age: 19 + Age: 19 years old )";
+        assert_eq!(format(&parse(input).unwrap()), input);
+        let quoted = r#"Nika ( "Salt + pepper" + "Note: shy" + Likes: tea )"#;
+        assert_eq!(format(&parse(quoted).unwrap()), quoted);
+    }
+}
@@ -1,75 +1,319 @@
 use anyhow::Result;
-use std::rc::Rc;
 
-use super::item::WppItem;
+use crate::models::message::Message as ConversationMessage;
+
+use super::header::{Header, HeaderItem};
+
+// Marks the start of every example dialogue, the same marker character cards use.
+pub static EXAMPLE_SEPARATOR: &str = "<START>";
 
 pub struct Message {
-    author: Rc<WppItem>,
+    author: String,
     message: String,
 }
 
+impl Message {
+    pub fn new(author: &str, message: &str) -> Self {
+        Self {
+            author: author.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
 pub struct Example {
     dialogue: Vec<Message>,
 }
 
+impl Example {
+    pub fn new(dialogue: Vec<Message>) -> Self {
+        Self { dialogue }
+    }
+
+    // A card's example dialogue, a `Name: message` line per message. Lines without a name carry on
+    // the message before them.
+    pub fn parse(text: &str) -> Self {
+        let mut dialogue: Vec<Message> = Vec::new();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let named = line
+                .split_once(':')
+                .filter(|(author, _)| !author.trim().is_empty() && author.len() <= 64);
+            match (named, dialogue.last_mut()) {
+                (Some((author, message)), _) => {
+                    dialogue.push(Message::new(author.trim(), message.trim()))
+                }
+                (None, Some(last)) => {
+                    last.message.push('\n');
+                    last.message.push_str(line.trim());
+                }
+                (None, None) => dialogue.push(Message::new("", line.trim())),
+            }
+        }
+        Self { dialogue }
+    }
+}
+
+// A roleplay chat: the header describing everyone taking part, example dialogues showing how the
+// characters talk, then the live history. The header and examples become the instruct template's
+// system prompt, the history its turns.
 pub struct ChatPrompt {
-    characters: Vec<Rc<WppItem>>,
-    user: Rc<WppItem>,
-    first_message: Option<Message>,
+    characters: Vec<HeaderItem>,
+    user: String,
+    persona: Option<HeaderItem>,
+    system: Option<String>,
     scenario: String,
     examples: Vec<Example>,
+    history: Vec<ConversationMessage>,
+    lore_before: Vec<String>,
+    lore_after: Vec<String>,
+    memories: Vec<String>,
+    documents: Vec<String>,
+    summary: Option<String>,
 }
 
+// The names macros resolve to: `{{char}}` is the first character, `{{char[1].name}}` the second,
+// `{{user}}` the user's persona. Unknown macros are left as they are.
 pub struct ChatPromptContext {
+    characters: Vec<String>,
+    user: String,
+}
+
+impl ChatPromptContext {
+    pub fn new(characters: Vec<String>, user: String) -> Self {
+        Self { characters, user }
+    }
+
+    pub fn substitute(&self, text: &str) -> String {
+        let mut substituted = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("{{") {
+            substituted.push_str(&rest[..start]);
+            let Some(end) = rest[start..].find("}}") else {
+                rest = &rest[start..];
+                break;
+            };
+            let expression = &rest[start + 2..start + end];
+            match self.resolve(expression.trim()) {
+                Some(value) => substituted.push_str(value),
+                None => substituted.push_str(&rest[start..start + end + 2]),
+            }
+            rest = &rest[start + end + 2..];
+        }
+        substituted.push_str(rest);
+        substituted
+    }
 
+    fn resolve(&self, expression: &str) -> Option<&str> {
+        let expression = expression.strip_suffix(".name").unwrap_or(expression);
+        if expression == "user" {
+            return Some(&self.user);
+        }
+        let index = match expression.strip_prefix("char")? {
+            "" => 0,
+            index => index
+                .strip_prefix('[')?
+                .strip_suffix(']')?
+                .trim()
+                .parse()
+                .ok()?,
+        };
+        self.characters.get(index).map(String::as_str)
+    }
+}
+
+impl ChatPrompt {
+    // `user` is the name `{{user}}` resolves to, with or without a persona describing them.
+    pub fn new(characters: Vec<HeaderItem>, user: &str) -> Self {
+        Self {
+            characters,
+            user: user.to_string(),
+            persona: None,
+            system: None,
+            scenario: String::new(),
+            examples: Vec::new(),
+            history: Vec::new(),
+            lore_before: Vec::new(),
+            lore_after: Vec::new(),
+            memories: Vec::new(),
+            documents: Vec::new(),
+            summary: None,
+        }
+    }
+
+    pub fn with_persona(mut self, persona: Option<HeaderItem>) -> Self {
+        self.persona = persona;
+        self
+    }
+
+    pub fn with_system(mut self, system: &str) -> Self {
+        self.system = Some(system.to_string());
+        self
+    }
+
+    pub fn with_scenario(mut self, scenario: &str) -> Self {
+        self.scenario = scenario.to_string();
+        self
+    }
+
+    pub fn with_examples(mut self, examples: Vec<Example>) -> Self {
+        self.examples = examples;
+        self
+    }
+
+    pub fn with_history(mut self, history: Vec<ConversationMessage>) -> Self {
+        self.history = history;
+        self
+    }
+
+    // Triggered lorebook entries, placed before and after the character definitions.
+    pub fn with_lore(mut self, before: Vec<String>, after: Vec<String>) -> Self {
+        self.lore_before = before;
+        self.lore_after = after;
+        self
+    }
+
+    pub fn with_memories(mut self, memories: Vec<String>) -> Self {
+        self.memories = memories;
+        self
+    }
+
+    pub fn with_documents(mut self, documents: Vec<String>) -> Self {
+        self.documents = documents;
+        self
+    }
+
+    pub fn with_summary(mut self, summary: Option<String>) -> Self {
+        self.summary = summary;
+        self
+    }
+
+    pub fn context(&self) -> ChatPromptContext {
+        ChatPromptContext {
+            characters: self
+                .characters
+                .iter()
+                .map(|character| character.name().to_string())
+                .collect(),
+            user: self.user.clone(),
+        }
+    }
+
+    // The header followed by the example dialogues, with every macro substituted.
+    pub fn prompt(&self) -> Result<String> {
+        let context = self.context();
+        let item =
+            |item: &HeaderItem| HeaderItem::new(item.name(), &context.substitute(item.full()));
+        let substitute = |texts: &[String]| {
+            texts
+                .iter()
+                .map(|text| context.substitute(text))
+                .collect::<Vec<_>>()
+        };
+        let header = Header::new(
+            self.system
+                .as_deref()
+                .map(|system| context.substitute(system)),
+            self.characters.iter().map(item).collect(),
+            self.persona.as_ref().map(item),
+            Some(context.substitute(&self.scenario)).filter(|scenario| !scenario.trim().is_empty()),
+        )
+        .with_lore(substitute(&self.lore_before), substitute(&self.lore_after))
+        .with_memories(self.memories.clone())
+        .with_documents(self.documents.clone())
+        .with_summary(self.summary.clone());
+        let mut prompt = header.evaluate()?;
+        for example in &self.examples {
+            prompt.push_str("\n\n");
+            prompt.push_str(EXAMPLE_SEPARATOR);
+            for message in &example.dialogue {
+                prompt.push('\n');
+                prompt.push_str(&self.format_message(&context, message));
+            }
+        }
+        Ok(prompt)
+    }
+
+    // The live history, as the template's turns.
+    pub fn messages(&self) -> Vec<ConversationMessage> {
+        self.history.clone()
+    }
+
+    fn format_message(&self, context: &ChatPromptContext, message: &Message) -> String {
+        let content = context.substitute(&message.message);
+        match message.author.is_empty() {
+            true => content,
+            false => format!("{}: {}", context.substitute(&message.author), content),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::wpp::item::WppItem;
+
     #[test]
     fn test_chat_prompt() {
-        let example_char = Rc::new(WppItem::try_from(super::super::CHARACTER_FULL_VALID).unwrap());
-        let char = Rc::clone(&example_char);
-        let user = Rc::clone(&example_char);
-        let characters = vec![char.clone()];
+        let example_char = WppItem::try_from(super::super::CHARACTER_FULL_VALID).unwrap();
+        let char = HeaderItem::new(example_char.name(), &example_char.prompt());
         // Some example messages
-        let chat = ChatPrompt {
-            characters,
-            user: user.clone(),
-            first_message: Some(Message {
-                author: char.clone(),
-                message: "Hello!".to_string(),
-            }),
-            scenario: "{{char[0].name}} meets {{user.name}}".to_string(),
-            examples: vec![
-                Example {
-                    dialogue: vec![
-                        Message {
-                            author: char.clone(),
-                            message: "Hello!".to_string(),
-                        },
-                        Message {
-                            author: user.clone(),
-                            message: "Hi!".to_string(),
-                        },
-                    ],
-                },
-                Example {
-                    dialogue: vec![
-                        Message {
-                            author: char.clone(),
-                            message: "How are you?".to_string(),
-                        },
-                        Message {
-                            author: user.clone(),
-                            message: "Good, you?".to_string(),
-                        },
-                    ],
-                },
-            ],
-        };
+        let chat = ChatPrompt::new(vec![char], example_char.name())
+            .with_scenario("{{char[0].name}} meets {{user.name}}")
+            .with_examples(vec![
+                Example::new(vec![
+                    Message::new(example_char.name(), "Hello!"),
+                    Message::new(example_char.name(), "Hi!"),
+                ]),
+                Example::new(vec![
+                    Message::new(example_char.name(), "How are you?"),
+                    Message::new(example_char.name(), "Good, you?"),
+                ]),
+            ]);
         println!("{}", chat.prompt().unwrap());
     }
+
+    #[test]
+    fn test_chat_prompt_macros() {
+        let char = HeaderItem::new("Nika Orchid", "[Character(\"{{char}}\")]");
+        let persona = HeaderItem::new("Alex", "{{user}} is 20.");
+        let history = vec![ConversationMessage {
+            timestamp: chrono::Utc::now(),
+            role: "assistant".into(),
+            content: "Welcome home, Alex.".into(),
+            author: None,
+            citations: Vec::new(),
+            attachments: Vec::new(),
+        }];
+        let chat = ChatPrompt::new(vec![char], "Alex")
+            .with_persona(Some(persona))
+            .with_scenario("{{char}} meets {{user}} in {{place}}.")
+            .with_examples(vec![Example::parse(
+                "{{user}}: Hi {{char[0].name}}.\n{{char}}: Hello, {{user}}.\nHow was your day?",
+            )])
+            .with_summary(Some("Alex came home.".into()))
+            .with_history(history);
+
+        let prompt = chat.prompt().unwrap();
+        assert!(prompt.contains("[Character(\"Nika Orchid\")]"));
+        assert!(prompt.contains("Alex is 20."));
+        assert!(prompt.contains("Nika Orchid meets Alex in {{place}}."));
+        assert!(prompt.contains("Alex came home."));
+        assert!(prompt.ends_with(
+            "<START>\nAlex: Hi Nika Orchid.\nNika Orchid: Hello, Alex.\nHow was your day?"
+        ));
+        assert_eq!(chat.messages().len(), 1);
+    }
+
+    #[test]
+    fn test_context_substitute() {
+        let context = ChatPromptContext {
+            characters: vec!["Nika".into(), "Mira".into()],
+            user: "Alex".into(),
+        };
+        assert_eq!(
+            context.substitute("{{char}}, {{ char[1].name }} and {{user}} {{char[2]}} {{"),
+            "Nika, Mira and Alex {{char[2]}} {{"
+        );
+    }
 }
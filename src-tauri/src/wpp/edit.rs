@@ -0,0 +1,213 @@
+use serde::{Deserialize, Serialize};
+
+use super::{item::WppItem, parser};
+
+// A single change the character editor makes to a definition, so it can edit stored characters
+// without sending the whole W++ text back. Keys are matched ignoring case.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum WppEdit {
+    SetName {
+        name: String,
+    },
+    AddAttribute {
+        name: String,
+        values: Vec<String>,
+    },
+    RenameAttribute {
+        key: String,
+        name: String,
+    },
+    RemoveAttribute {
+        key: String,
+    },
+    MoveAttribute {
+        key: String,
+        index: usize,
+    },
+    SetValues {
+        key: String,
+        values: Vec<String>,
+    },
+    ReplaceValue {
+        key: String,
+        old: String,
+        new: String,
+    },
+    RemoveValue {
+        key: String,
+        value: String,
+    },
+    // Merges another definition, given as W++, into this one.
+    Merge {
+        definition: String,
+    },
+}
+
+impl WppEdit {
+    pub fn apply(&self, item: &mut WppItem) -> Result<(), String> {
+        let missing = |key: &str| format!("no attribute `{}`", key);
+        match self {
+            WppEdit::SetName { name } => item.set_name(name),
+            WppEdit::AddAttribute { name, values } => {
+                item.insert_attribute(name, values.clone())?
+            }
+            WppEdit::RenameAttribute { key, name } => item.rename_attribute(key, name)?,
+            WppEdit::RemoveAttribute { key } => {
+                item.remove_attribute(key).ok_or_else(|| missing(key))?;
+            }
+            WppEdit::MoveAttribute { key, index } => {
+                if !item.move_attribute(key, *index) {
+                    return Err(missing(key));
+                }
+            }
+            WppEdit::SetValues { key, values } => item
+                .attribute_mut(key)
+                .ok_or_else(|| missing(key))?
+                .set_values(values.clone()),
+            WppEdit::ReplaceValue { key, old, new } => {
+                let attribute = item.attribute_mut(key).ok_or_else(|| missing(key))?;
+                if !attribute.replace_value(old, new) {
+                    return Err(format!("`{}` has no value \"{}\"", key, old));
+                }
+            }
+            WppEdit::RemoveValue { key, value } => {
+                let attribute = item.attribute_mut(key).ok_or_else(|| missing(key))?;
+                if !attribute.remove_value(value) {
+                    return Err(format!("`{}` has no value \"{}\"", key, value));
+                }
+            }
+            WppEdit::Merge { definition } => {
+                let other = parser::parse(definition).map_err(|e| e.to_string())?;
+                item.merge(&other);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Applies every edit in order, the item is left untouched if any of them fails.
+pub fn apply_all(item: &WppItem, edits: &[WppEdit]) -> Result<WppItem, String> {
+    let mut edited = item.clone();
+    for edit in edits {
+        edit.apply(&mut edited)?;
+    }
+    Ok(edited)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wpp::item::{Attribute, AttributeChange};
+
+    fn nika() -> WppItem {
+        parser::parse(super::super::CHARACTER_FULL_VALID).unwrap()
+    }
+
+    #[test]
+    fn test_apply_all() {
+        let edits = vec![
+            WppEdit::RenameAttribute {
+                key: "nickname".into(),
+                name: "Alias".into(),
+            },
+            WppEdit::MoveAttribute {
+                key: "ALIAS".into(),
+                index: 99,
+            },
+            WppEdit::RemoveAttribute {
+                key: "personality".into(),
+            },
+            WppEdit::ReplaceValue {
+                key: "age".into(),
+                old: "19".into(),
+                new: "20".into(),
+            },
+            WppEdit::RemoveValue {
+                key: "Body".into(),
+                value: "158cm tall".into(),
+            },
+            WppEdit::AddAttribute {
+                name: "Favorite food".into(),
+                values: vec!["Fish".into()],
+            },
+        ];
+        let item = apply_all(&nika(), &edits).unwrap();
+        let names = item
+            .attributes()
+            .iter()
+            .map(Attribute::name)
+            .collect::<Vec<_>>();
+        assert_eq!(names.last(), Some(&"Favorite food"));
+        assert_eq!(names[names.len() - 2], "Alias");
+        assert!(item.attribute("Personality").is_none());
+        assert_eq!(item.attribute("Age").unwrap().values()[0], "20");
+        assert_eq!(item.attribute("body").unwrap().values().len(), 2);
+    }
+
+    #[test]
+    fn test_apply_all_errors() {
+        let item = nika();
+        let missing = WppEdit::SetValues {
+            key: "Wings".into(),
+            values: vec![],
+        };
+        assert!(apply_all(&item, &[missing]).is_err());
+        let duplicate = WppEdit::RenameAttribute {
+            key: "Mind".into(),
+            name: "personality".into(),
+        };
+        assert!(apply_all(&item, &[duplicate]).is_err());
+        let case_only = WppEdit::RenameAttribute {
+            key: "Mind".into(),
+            name: "MIND".into(),
+        };
+        assert!(apply_all(&item, &[case_only]).is_ok());
+        for name in ["", "Wake up: 5", "Age(19)", "\"Age\"", "Loves + Hates"] {
+            let add = WppEdit::AddAttribute {
+                name: name.into(),
+                values: vec![],
+            };
+            let rename = WppEdit::RenameAttribute {
+                key: "Age".into(),
+                name: name.into(),
+            };
+            assert!(apply_all(&item, &[add]).is_err(), "{}", name);
+            assert!(apply_all(&item, &[rename]).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_merge_and_diff() {
+        let item = nika();
+        let merge = WppEdit::Merge {
+            definition: r#"[Character("Other") { age("20" + "19") Wings("Small") }]"#.into(),
+        };
+        let merged = apply_all(&item, &[merge]).unwrap();
+        assert_eq!(merged.name(), "Nika Orchid");
+        assert_eq!(
+            merged.attribute("Age").unwrap().values(),
+            &vec!["19", "19 years old", "20"]
+        );
+
+        let changes = item.diff(&merged);
+        assert_eq!(
+            changes,
+            vec![
+                AttributeChange::Changed {
+                    name: "Age".into(),
+                    added: vec!["20".into()],
+                    removed: vec![],
+                },
+                AttributeChange::Added {
+                    name: "Wings".into(),
+                    values: vec!["Small".into()],
+                },
+            ]
+        );
+        assert!(merged.diff(&item).iter().any(|change| matches!(
+            change,
+            AttributeChange::Removed { name, .. } if name == "Wings"
+        )));
+    }
+}
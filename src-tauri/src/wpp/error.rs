@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{format::Format, tokenizer::Span};
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum WppParseError {
+    #[error("unterminated string literal at {}:{}", .span.line, .span.column)]
+    UnterminatedString { span: Span },
+    #[error("unterminated block comment at {}:{}", .span.line, .span.column)]
+    UnterminatedComment { span: Span },
+    #[error("unexpected character `{character}` at {}:{}", .span.line, .span.column)]
+    UnexpectedCharacter { character: char, span: Span },
+    #[error("expected {expected}, found {found} at {}:{}", .span.line, .span.column)]
+    UnexpectedToken {
+        expected: &'static str,
+        found: String,
+        span: Span,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum FormatError {
+    #[error("invalid W++: {0}")]
+    Wpp(#[from] WppParseError),
+    #[error("invalid {format:?} definition: {message}")]
+    Invalid { format: Format, message: String },
+}
+
+// What the character editor needs to underline an error: the message, where it is, and the
+// rendered source excerpt with a caret line under the offending span.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WppDiagnostic {
+    pub message: String,
+    pub span: Span,
+    pub annotated: String,
+}
+
+impl WppParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            WppParseError::UnterminatedString { span }
+            | WppParseError::UnterminatedComment { span }
+            | WppParseError::UnexpectedCharacter { span, .. }
+            | WppParseError::UnexpectedToken { span, .. } => *span,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            WppParseError::UnterminatedString { .. } => "unterminated string literal".into(),
+            WppParseError::UnterminatedComment { .. } => "unterminated block comment".into(),
+            WppParseError::UnexpectedCharacter { character, .. } => {
+                format!("unexpected character `{}`", character)
+            }
+            WppParseError::UnexpectedToken {
+                expected, found, ..
+            } => format!("expected {}, found {}", expected, found),
+        }
+    }
+
+    // Renders the error against the source it came from, e.g.
+    //
+    // error: expected `(`, found `{`
+    //  --> 3:13
+    //   |
+    // 3 |     Nickname{"Nika")
+    //   |             ^
+    pub fn diagnostic(&self, input: &str) -> WppDiagnostic {
+        let span = self.span();
+        let message = self.message();
+        let line = input.lines().nth(span.line - 1).unwrap_or_default();
+        // Spans over several lines are only underlined up to the end of the first one.
+        let width = input
+            .get(span.start..span.end)
+            .map_or(0, |text| {
+                text.lines().next().unwrap_or_default().chars().count()
+            })
+            .max(1);
+        let gutter = " ".repeat(span.line.to_string().len());
+        let annotated = format!(
+            "error: {message}\n{gutter}--> {}:{}\n{gutter} |\n{} | {line}\n{gutter} | {}{}",
+            span.line,
+            span.column,
+            span.line,
+            " ".repeat(span.column - 1),
+            "^".repeat(width),
+        );
+        WppDiagnostic {
+            message,
+            span,
+            annotated,
+        }
+    }
+}
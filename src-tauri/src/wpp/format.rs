@@ -1,20 +1,284 @@
-use super::item::WppItem;
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashSet;
+
+use super::{
+    boostyle,
+    error::FormatError,
+    item::{Attribute, WppItem},
+    parser, plist,
+};
+
+// The attribute PList and Boostyle file their unkeyed traits under.
+pub static TRAITS: &str = "Traits";
+
+// The ways a definition can be written. Characters store one parsed definition and get rendered
+// in whichever of these works best for the model at hand.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    Wpp,
+    #[serde(rename = "plist")]
+    PList,
+    Boostyle,
+    Json,
+    Yaml,
+}
+
+impl Format {
+    pub fn parse(&self, input: &str) -> Result<Vec<WppItem>, FormatError> {
+        let invalid = |message: String| FormatError::Invalid {
+            format: *self,
+            message,
+        };
+        match self {
+            Format::Wpp => Ok(parser::parse_document(input)?),
+            Format::PList => plist::parse(input).map_err(invalid),
+            Format::Boostyle => boostyle::parse(input).map_err(invalid),
+            // A lone item is accepted as well as a list of them.
+            Format::Json => serde_json::from_str::<Vec<WppItem>>(input)
+                .or_else(|_| serde_json::from_str::<WppItem>(input).map(|item| vec![item]))
+                .map_err(|e| invalid(e.to_string())),
+            Format::Yaml => serde_yaml::from_str::<Vec<WppItem>>(input)
+                .or_else(|_| serde_yaml::from_str::<WppItem>(input).map(|item| vec![item]))
+                .map_err(|e| invalid(e.to_string())),
+        }
+    }
+
+    pub fn format(&self, items: &[WppItem]) -> Result<String, FormatError> {
+        let invalid = |message: String| FormatError::Invalid {
+            format: *self,
+            message,
+        };
+        match self {
+            Format::Wpp => Ok(items.iter().map(format).collect::<Vec<_>>().join("\n\n")),
+            Format::PList => Ok(plist::format(items)),
+            Format::Boostyle => Ok(boostyle::format(items)),
+            Format::Json => serde_json::to_string_pretty(items).map_err(|e| invalid(e.to_string())),
+            Format::Yaml => serde_yaml::to_string(items).map_err(|e| invalid(e.to_string())),
+        }
+    }
+}
+
+pub fn convert(input: &str, from: Format, to: Format) -> Result<String, FormatError> {
+    to.format(&from.parse(input)?)
+}
+
+// How a definition gets rendered into a prompt. The default is the canonical multi-line W++,
+// the rest trade readability (and parseability) for context space.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderOptions {
+    // Everything on one line.
+    pub compact: bool,
+    // No quotes around names and values.
+    pub unquoted: bool,
+    // Values already given by an earlier attribute are left out, as are attributes left empty.
+    pub dedupe: bool,
+    // Only these attributes, in this order.
+    pub attributes: Option<Vec<String>>,
+}
+
+// A rendering of a definition with its estimated cost.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderVariant {
+    pub name: String,
+    pub options: RenderOptions,
+    pub text: String,
+    pub tokens: usize,
+}
 
 pub fn format(item: &WppItem) -> String {
-    let mut formatted = String::new();
-    formatted.push_str(&format!("[{}(\"{}\")", item.item_type(), item.name()));
-    formatted.push_str("\n{\n");
-    for attribute in item.attributes() {
-        formatted.push_str(&format!("    {}(", attribute.name()));
-        let value_count = attribute.values().len();
-        for (i, value) in attribute.values().iter().enumerate() {
-            formatted.push_str(&format!("\"{}\"", value));
-            if i < value_count - 1 {
-                formatted.push_str(" + ");
+    format_with(item, &RenderOptions::default())
+}
+
+pub fn format_with(item: &WppItem, options: &RenderOptions) -> String {
+    let quote = |value: &str| {
+        if options.unquoted {
+            value.to_string()
+        } else {
+            format!("\"{}\"", escape(value))
+        }
+    };
+    let selected: Vec<&Attribute> = match &options.attributes {
+        Some(names) => names
+            .iter()
+            .filter_map(|name| item.attribute(name))
+            .collect(),
+        None => item.attributes().iter().collect(),
+    };
+    let mut seen = HashSet::new();
+    let attributes = selected
+        .into_iter()
+        .filter_map(|attribute| {
+            let values = attribute
+                .values()
+                .iter()
+                .filter(|value| !options.dedupe || seen.insert(value.as_str()))
+                .map(|value| quote(value))
+                .collect::<Vec<_>>();
+            if values.is_empty() && !attribute.values().is_empty() {
+                return None;
             }
+            Some(format!("{}({})", attribute.name(), values.join(" + ")))
+        })
+        .collect::<Vec<_>>();
+
+    let header = format!("[{}({})", item.item_type(), quote(item.name()));
+    if options.compact {
+        format!("{} {{ {} }}]", header, attributes.join(" "))
+    } else {
+        let body = attributes
+            .iter()
+            .map(|attribute| format!("    {}\n", attribute))
+            .collect::<String>();
+        format!("{}\n{{\n{}}}]", header, body)
+    }
+}
+
+// The usual trade-offs, from the full block to the smallest rendering, each with an estimate of
+// its token cost.
+pub fn variants(item: &WppItem, attributes: Option<Vec<String>>) -> Vec<RenderVariant> {
+    let variants = [
+        ("verbose", false, false, false),
+        ("compact", true, false, false),
+        ("compact, unquoted", true, true, false),
+        ("compact, unquoted, deduplicated", true, true, true),
+    ];
+    variants
+        .into_iter()
+        .map(|(name, compact, unquoted, dedupe)| {
+            let options = RenderOptions {
+                compact,
+                unquoted,
+                dedupe,
+                attributes: attributes.clone(),
+            };
+            let text = format_with(item, &options);
+            RenderVariant {
+                name: name.to_string(),
+                tokens: estimate_tokens(&text),
+                options,
+                text,
+            }
+        })
+        .collect()
+}
+
+// A rough token count, without access to the model's tokenizer: BPE vocabularies cover about
+// four characters of a word per token, and most punctuation and line breaks take one each.
+pub fn estimate_tokens(text: &str) -> usize {
+    let mut tokens = 0;
+    let mut word: usize = 0;
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            word += 1;
+            continue;
+        }
+        tokens += word.div_ceil(4);
+        word = 0;
+        if c == '\n' || !c.is_whitespace() {
+            tokens += 1;
+        }
+    }
+    tokens + word.div_ceil(4)
+}
+
+// Quotes and backslashes inside values have to be escaped to parse back.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static FORMATS: [Format; 5] = [
+        Format::Wpp,
+        Format::PList,
+        Format::Boostyle,
+        Format::Json,
+        Format::Yaml,
+    ];
+
+    #[test]
+    fn test_convert_round_trip() {
+        let input = r#"[Character("Nika Orchid") { Traits("Shy" + "Quiet") Nickname("Nika") Age("19" + "19 years old") }]"#;
+        let items = Format::Wpp.parse(input).unwrap();
+        for format in FORMATS {
+            let converted = convert(input, Format::Wpp, format).unwrap();
+            let back = format.parse(&converted).unwrap();
+            assert_eq!(
+                Format::Wpp.format(&back).unwrap(),
+                Format::Wpp.format(&items).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_convert_plist() {
+        let converted = convert(
+            super::super::CHARACTER_FULL_VALID,
+            Format::Wpp,
+            Format::PList,
+        )
+        .unwrap();
+        assert!(converted.starts_with(
+            "[Nika Orchid; Nickname: Nika; Species: Human Cat; Age: 19, 19 years old;"
+        ));
+        assert!(matches!(
+            convert("Nika", Format::Boostyle, Format::Wpp),
+            Err(FormatError::Invalid {
+                format: Format::Boostyle,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_verbose_default() {
+        let item = parser::parse(r#"[Character("Nika") { Age("19" + "19 years old") }]"#).unwrap();
+        assert_eq!(
+            item.prompt(),
+            "[Character(\"Nika\")\n{\n    Age(\"19\" + \"19 years old\")\n}]"
+        );
+    }
+
+    #[test]
+    fn test_render_options() {
+        let item = parser::parse(super::super::CHARACTER_FULL_VALID).unwrap();
+        let compact = item.prompt_with(&RenderOptions {
+            compact: true,
+            unquoted: true,
+            dedupe: true,
+            attributes: None,
+        });
+        assert!(!compact.contains('\n'));
+        assert!(!compact.contains('"'));
+        assert!(compact.starts_with("[Character(Nika Orchid) { Nickname(Nika) Species(Human Cat)"));
+        // Personality repeats Mind word for word.
+        assert!(compact.contains("Mind(Shy + Reserved"));
+        assert!(!compact.contains("Personality("));
+
+        let selected = item.prompt_with(&RenderOptions {
+            compact: true,
+            attributes: Some(vec!["age".into(), "NICKNAME".into(), "Unknown".into()]),
+            ..Default::default()
+        });
+        assert_eq!(
+            selected,
+            "[Character(\"Nika Orchid\") { Age(\"19\" + \"19 years old\") Nickname(\"Nika\") }]"
+        );
+    }
+
+    #[test]
+    fn test_variants() {
+        let item = parser::parse(super::super::CHARACTER_FULL_VALID).unwrap();
+        let variants = variants(&item, None);
+        assert_eq!(variants[0].text, item.prompt());
+        for pair in variants.windows(2) {
+            assert!(pair[1].tokens < pair[0].tokens);
         }
-        formatted.push_str(")\n");
+        assert_eq!(estimate_tokens("Age(\"19 years old\")"), 9);
     }
-    formatted.push_str("}]");
-    formatted
 }
@@ -15,9 +15,76 @@ pub struct Header {
     characters: Vec<HeaderItem>,
     user: Option<HeaderItem>,
     scenario: Option<String>,
+    // Triggered lorebook entries, on either side of the character definitions.
+    lore_before: Vec<String>,
+    lore_after: Vec<String>,
+    // Relevant excerpts of past conversations.
+    memories: Vec<String>,
+    // Numbered document passages the reply can cite.
+    documents: Vec<String>,
+    // What happened in the messages that no longer fit.
+    summary: Option<String>,
+}
+
+impl HeaderItem {
+    pub fn new(name: &str, full: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            full: full.to_string(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn full(&self) -> &str {
+        &self.full
+    }
 }
 
 impl Header {
+    pub fn new(
+        system: Option<String>,
+        characters: Vec<HeaderItem>,
+        user: Option<HeaderItem>,
+        scenario: Option<String>,
+    ) -> Self {
+        Self {
+            template: TEMPLATE.to_string(),
+            system,
+            characters,
+            user,
+            scenario,
+            lore_before: Vec::new(),
+            lore_after: Vec::new(),
+            memories: Vec::new(),
+            documents: Vec::new(),
+            summary: None,
+        }
+    }
+
+    pub fn with_lore(mut self, before: Vec<String>, after: Vec<String>) -> Self {
+        self.lore_before = before;
+        self.lore_after = after;
+        self
+    }
+
+    pub fn with_memories(mut self, memories: Vec<String>) -> Self {
+        self.memories = memories;
+        self
+    }
+
+    pub fn with_documents(mut self, documents: Vec<String>) -> Self {
+        self.documents = documents;
+        self
+    }
+
+    pub fn with_summary(mut self, summary: Option<String>) -> Self {
+        self.summary = summary;
+        self
+    }
+
     pub fn evaluate(&self) -> Result<String> {
         let mut handlebars = Handlebars::new();
         handlebars.register_escape_fn(handlebars::no_escape);
@@ -34,6 +101,10 @@ static TEMPLATE: &str = r#"
 {{system}}
 {{/if}}
 
+{{#each lore_before as |lore|}}
+{{lore}}
+{{/each}}
+
 {{#each characters as |char|}}
 {{char.name}}:
 {{char.full}}
@@ -48,6 +119,29 @@ static TEMPLATE: &str = r#"
 Scenario:
 {{scenario}}
 {{/if}}
+
+{{#each lore_after as |lore|}}
+{{lore}}
+{{/each}}
+
+{{#if memories}}
+Things remembered from earlier:
+{{#each memories as |memory|}}
+{{memory}}
+{{/each}}
+{{/if}}
+
+{{#if documents}}
+Passages from documents. When using one, cite it by its number, like [1]:
+{{#each documents as |document|}}
+{{document}}
+{{/each}}
+{{/if}}
+
+{{#if summary}}
+Summary of the conversation so far:
+{{summary}}
+{{/if}}
 "#;
 
 #[cfg(test)]
@@ -74,6 +168,11 @@ mod tests {
                     full: "[THIS IS CHARACTER 2 WPP]".to_string(),
                 },
             ],
+            lore_before: vec!["[THIS IS A LOREBOOK ENTRY]".to_string()],
+            lore_after: vec![],
+            memories: vec!["user: Where is the Madou tower?".to_string()],
+            documents: vec!["[1] madou.md › Location\nNorth of the lake.".to_string()],
+            summary: Some("User asked where the Madou tower is.".to_string()),
         };
         println!("{}", header.evaluate().unwrap());
     }
@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
 
-use super::{parser, format};
+use super::{
+    format::{self, RenderOptions},
+    parser, tokenizer,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Attribute {
@@ -27,6 +30,55 @@ impl Attribute {
     pub fn values(&self) -> &Vec<String> {
         &self.values
     }
+
+    pub fn rename(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    pub fn set_values(&mut self, values: Vec<String>) {
+        self.values = values;
+    }
+
+    // Replaces the first occurrence of `old`, returns whether there was one.
+    pub fn replace_value(&mut self, old: &str, new: &str) -> bool {
+        match self.values.iter_mut().find(|value| *value == old) {
+            Some(value) => {
+                *value = new.to_string();
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Removes every occurrence of `value`, returns whether there was one.
+    pub fn remove_value(&mut self, value: &str) -> bool {
+        let len = self.values.len();
+        self.values.retain(|existing| existing != value);
+        self.values.len() != len
+    }
+
+    fn has_name(&self, key: &str) -> bool {
+        self.name.eq_ignore_ascii_case(key.trim())
+    }
+}
+
+// A difference between two items' attributes, attributes are matched by case-insensitive key.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum AttributeChange {
+    Added {
+        name: String,
+        values: Vec<String>,
+    },
+    Removed {
+        name: String,
+        values: Vec<String>,
+    },
+    Changed {
+        name: String,
+        added: Vec<String>,
+        removed: Vec<String>,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -61,9 +113,144 @@ impl WppItem {
         self.attributes.push(attribute);
     }
 
+    pub fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    // Looks an attribute up by key, ignoring case, e.g. `likes` finds `Likes`.
+    pub fn attribute(&self, key: &str) -> Option<&Attribute> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.has_name(key))
+    }
+
+    pub fn attribute_mut(&mut self, key: &str) -> Option<&mut Attribute> {
+        self.attributes
+            .iter_mut()
+            .find(|attribute| attribute.has_name(key))
+    }
+
+    fn position(&self, key: &str) -> Option<usize> {
+        self.attributes
+            .iter()
+            .position(|attribute| attribute.has_name(key))
+    }
+
+    // Adds an attribute the user named, which has to be a valid key no other attribute has.
+    pub fn insert_attribute(&mut self, name: &str, values: Vec<String>) -> Result<(), String> {
+        check_name(name)?;
+        if self.attribute(name).is_some() {
+            return Err(format!("attribute `{}` already exists", name));
+        }
+        let mut attribute = Attribute::new(name);
+        attribute.set_values(values);
+        self.attributes.push(attribute);
+        Ok(())
+    }
+
+    // Fails when there's no such attribute, or `name` is invalid or taken by another one.
+    pub fn rename_attribute(&mut self, key: &str, name: &str) -> Result<(), String> {
+        check_name(name)?;
+        if !key.eq_ignore_ascii_case(name) && self.attribute(name).is_some() {
+            return Err(format!("attribute `{}` already exists", name));
+        }
+        self.attribute_mut(key)
+            .ok_or_else(|| format!("no attribute `{}`", key))?
+            .rename(name);
+        Ok(())
+    }
+
+    pub fn remove_attribute(&mut self, key: &str) -> Option<Attribute> {
+        let index = self.position(key)?;
+        Some(self.attributes.remove(index))
+    }
+
+    // Moves the attribute to `index`, clamped to the last position. Returns whether it existed.
+    pub fn move_attribute(&mut self, key: &str, index: usize) -> bool {
+        let Some(from) = self.position(key) else {
+            return false;
+        };
+        let attribute = self.attributes.remove(from);
+        let index = index.min(self.attributes.len());
+        self.attributes.insert(index, attribute);
+        true
+    }
+
+    // Adds the other item's attributes, values of attributes both have are combined without
+    // repeating any. The name and type stay ours.
+    pub fn merge(&mut self, other: &WppItem) {
+        for theirs in &other.attributes {
+            match self.attribute_mut(&theirs.name) {
+                Some(ours) => {
+                    for value in &theirs.values {
+                        if !ours.values.contains(value) {
+                            ours.add_value(value);
+                        }
+                    }
+                }
+                None => self.attributes.push(theirs.clone()),
+            }
+        }
+    }
+
+    // What changed from this item to `other`, in the order of our attributes then theirs.
+    pub fn diff(&self, other: &WppItem) -> Vec<AttributeChange> {
+        let mut changes = Vec::new();
+        for ours in &self.attributes {
+            match other.attribute(&ours.name) {
+                Some(theirs) => {
+                    let added = theirs
+                        .values
+                        .iter()
+                        .filter(|value| !ours.values.contains(value))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    let removed = ours
+                        .values
+                        .iter()
+                        .filter(|value| !theirs.values.contains(value))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    if !added.is_empty() || !removed.is_empty() {
+                        changes.push(AttributeChange::Changed {
+                            name: ours.name.clone(),
+                            added,
+                            removed,
+                        });
+                    }
+                }
+                None => changes.push(AttributeChange::Removed {
+                    name: ours.name.clone(),
+                    values: ours.values.clone(),
+                }),
+            }
+        }
+        for theirs in &other.attributes {
+            if self.attribute(&theirs.name).is_none() {
+                changes.push(AttributeChange::Added {
+                    name: theirs.name.clone(),
+                    values: theirs.values.clone(),
+                });
+            }
+        }
+        changes
+    }
+
     pub fn prompt(&self) -> String {
         format::format(self)
     }
+
+    pub fn prompt_with(&self, options: &RenderOptions) -> String {
+        format::format_with(self, options)
+    }
+}
+
+// Keys are written without quotes, so they can't contain what ends one, like `(` or `:`.
+fn check_name(name: &str) -> Result<(), String> {
+    match tokenizer::is_identifier(name) {
+        true => Ok(()),
+        false => Err(format!("`{}` isn't a valid attribute name", name)),
+    }
 }
 
 impl TryFrom<&str> for WppItem {
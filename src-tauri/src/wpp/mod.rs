@@ -1,16 +1,21 @@
-// pub mod chat;
+pub mod boostyle;
+pub mod chat;
+pub mod edit;
+pub mod error;
 pub mod format;
 pub mod header;
 pub mod item;
 pub mod parser;
+pub mod plist;
 pub mod prompting;
+pub mod split;
 pub mod tokenizer;
 
 // https://rentry.co/WPP_For_Dummies#description
 // the identifier `character` is technically a variable key that is used to identify the
 // character, but we only care about charactes for now.
 // Any attribute e.g. `Nickname` is a key, they are not hardcoded and can be anything.
-static CHARACTER_FULL_VALID: &str = r#"
+pub(crate) static CHARACTER_FULL_VALID: &str = r#"
 [Character("Nika Orchid")
 {
     Nickname("Nika")
@@ -1,68 +1,110 @@
 use std::iter::Peekable;
 
 use super::{
+    error::WppParseError,
     item::{Attribute, WppItem},
-    tokenizer::{self, Token},
+    tokenizer::{self, Token, TokenKind},
 };
 
-// Responsible for parsing the entire w++ entry
-pub fn parse(input: &str) -> Result<WppItem, String> {
-    let mut tokens = tokenizer::tokenize(input).into_iter().peekable();
-    let (iden, name) = parse_header(&mut tokens)?;
+// Responsible for parsing an input holding exactly one w++ entry
+pub fn parse(input: &str) -> Result<WppItem, WppParseError> {
+    let mut tokens = tokenizer::tokenize(input)?.into_iter().peekable();
+    let item = parse_item(&mut tokens)?;
+    expect(&mut tokens, TokenKind::End, "end of input")?;
+    Ok(item)
+}
+
+// Responsible for parsing a document of any number of w++ entries, e.g. a character followed by
+// the places and factions they belong to
+pub fn parse_document(input: &str) -> Result<Vec<WppItem>, WppParseError> {
+    let mut tokens = tokenizer::tokenize(input)?.into_iter().peekable();
+    let mut items = Vec::new();
+    while tokens
+        .peek()
+        .is_some_and(|token| token.kind != TokenKind::End)
+    {
+        items.push(parse_item(&mut tokens)?);
+    }
+    Ok(items)
+}
+
+// Responsible for parsing a single `[Item("name"){ ... }]` block
+fn parse_item(
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+) -> Result<WppItem, WppParseError> {
+    let (iden, name) = parse_header(tokens)?;
     let mut item = WppItem::new(&iden, &name);
-    let attributes = parse_attributes(&mut tokens)?;
+    let attributes = parse_attributes(tokens)?;
     for attribute in attributes {
         item.add_attribute(attribute);
     }
-    if let Some(Token::RightBracket) = tokens.next() {
+    expect(tokens, TokenKind::RightBracket, "`]`")?;
+    Ok(item)
+}
+
+// Takes the next token, which has to be `kind`.
+fn expect(
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+    kind: TokenKind,
+    expected: &'static str,
+) -> Result<Token, WppParseError> {
+    let token = next(tokens);
+    if token.kind == kind {
+        Ok(token)
     } else {
-        return Err(format!(
-            "Expected `]` got {:?}, while parsing character",
-            tokens.next()
-        ));
+        Err(unexpected(token, expected))
+    }
+}
+
+// The tokenizer always ends with `TokenKind::End`, and nothing reads past it.
+fn next(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Token {
+    tokens.next().expect("tokens end with `TokenKind::End`")
+}
+
+fn unexpected(token: Token, expected: &'static str) -> WppParseError {
+    WppParseError::UnexpectedToken {
+        expected,
+        found: token.kind.to_string(),
+        span: token.span,
     }
-    Ok(item)
 }
 
 // Responsible for parsing the header of the w++ entry e.g. `[Character("Nika Orchid")\n{`
 fn parse_header(
     tokens: &mut Peekable<impl Iterator<Item = Token>>,
-) -> Result<(String, String), String> {
-    if let Some(Token::LeftBracket) = tokens.next() {
-    } else {
-        return Err("Expected `[`".to_string());
-    }
-    let iden = match tokens.next() {
-        Some(Token::Identifier(iden)) => iden,
-        _ => return Err("Expected w++ entry name".to_string()),
+) -> Result<(String, String), WppParseError> {
+    expect(tokens, TokenKind::LeftBracket, "`[`")?;
+    let iden = match next(tokens) {
+        Token {
+            kind: TokenKind::Identifier(iden),
+            ..
+        } => iden,
+        token => return Err(unexpected(token, "w++ entry name")),
     };
-    if let Some(Token::LeftParen) = tokens.next() {
-    } else {
-        return Err("Expected `(`".to_string());
-    }
-    let name = match tokens.next() {
-        Some(Token::StringLiteral(name)) => name,
-        _ => return Err("Expected string literal".to_string()),
+    expect(tokens, TokenKind::LeftParen, "`(`")?;
+    let name = match next(tokens) {
+        Token {
+            kind: TokenKind::StringLiteral(name),
+            ..
+        } => name,
+        token => return Err(unexpected(token, "string literal")),
     };
-    if let Some(Token::RightParen) = tokens.next() {
-    } else {
-        return Err("Expected `)`, while parsing character".to_string());
-    }
-    if let Some(Token::LeftBrace) = tokens.next() {
-    } else {
-        return Err("Expected `{`".to_string());
-    }
+    expect(tokens, TokenKind::RightParen, "`)`")?;
+    expect(tokens, TokenKind::LeftBrace, "`{`")?;
     Ok((iden, name))
 }
 
 // Responsible for parsing the values of an attribute e.g. `ThisPartHere("value1" + "value2")`
 fn parse_attributes(
     tokens: &mut Peekable<impl Iterator<Item = Token>>,
-) -> Result<Vec<Attribute>, String> {
+) -> Result<Vec<Attribute>, WppParseError> {
     let mut attributes = Vec::new();
-    while let Some(token) = tokens.next() {
-        match token {
-            Token::Identifier(key) => {
+    loop {
+        match next(tokens) {
+            Token {
+                kind: TokenKind::Identifier(key),
+                ..
+            } => {
                 let values = parse_attribute_values(tokens)?;
                 let mut attribute = Attribute::new(&key);
                 for value in values {
@@ -70,13 +112,11 @@ fn parse_attributes(
                 }
                 attributes.push(attribute);
             }
-            Token::RightBrace => break,
-            _ => {
-                return Err(format!(
-                    "Expected `}}` got {:?}, while parsing attribute",
-                    token
-                ))
-            }
+            Token {
+                kind: TokenKind::RightBrace,
+                ..
+            } => break,
+            token => return Err(unexpected(token, "attribute name or `}`")),
         }
     }
     Ok(attributes)
@@ -85,31 +125,132 @@ fn parse_attributes(
 // Responsible for parsing the values of an attribute e.g. `Nickname(THIS PART HERE)`
 fn parse_attribute_values(
     tokens: &mut Peekable<impl Iterator<Item = Token>>,
-) -> Result<Vec<String>, String> {
+) -> Result<Vec<String>, WppParseError> {
     let mut values = Vec::new();
-    if let Some(Token::LeftParen) = tokens.peek() {
-        tokens.next();
-    } else {
-        return Err("Expected `(`".to_string());
-    }
-    for token in tokens.by_ref() {
-        match token {
-            Token::StringLiteral(value) => {
-                values.push(value.clone());
+    expect(tokens, TokenKind::LeftParen, "`(`")?;
+    loop {
+        match next(tokens) {
+            Token {
+                kind: TokenKind::StringLiteral(value),
+                ..
+            } => {
+                values.push(value);
             }
-            Token::Plus => {
+            Token {
+                kind: TokenKind::Plus,
+                ..
+            } => {
                 continue;
             }
-            Token::RightParen => {
+            Token {
+                kind: TokenKind::RightParen,
+                ..
+            } => {
                 break;
             }
-            _ => {
-                return Err(format!(
-                    "Expected `)` got {:?}, while parsing attribute values",
-                    token
-                ))
-            }
+            token => return Err(unexpected(token, "string literal, `+` or `)`")),
         }
     }
     Ok(values)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_valid() {
+        let item = parse(super::super::CHARACTER_FULL_VALID).unwrap();
+        assert_eq!(item.item_type(), "Character");
+        assert_eq!(item.name(), "Nika Orchid");
+        assert_eq!(item.attributes().len(), 10);
+    }
+
+    #[test]
+    fn test_diagnostic() {
+        let input = "[Character(\"Nika Orchid\")\n{\n    Nickname{\"Nika\")\n}]";
+        let error = parse(input).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expected `(`, found `{` at 3:13".to_string()
+        );
+        let diagnostic = error.diagnostic(input);
+        assert_eq!(diagnostic.span.start, input.find("{\"Nika").unwrap());
+        assert_eq!(
+            diagnostic.annotated,
+            "error: expected `(`, found `{`\n --> 3:13\n  |\n3 |     Nickname{\"Nika\")\n  |             ^"
+        );
+    }
+
+    #[test]
+    fn test_unexpected_end() {
+        let input = "[Character(\"Nika\")\n{\n    Age(\"19\")";
+        let error = parse(input).unwrap_err();
+        assert!(matches!(
+            error,
+            WppParseError::UnexpectedToken {
+                expected: "attribute name or `}`",
+                ..
+            }
+        ));
+        assert_eq!(error.span().start, input.len());
+    }
+
+    #[test]
+    fn test_unterminated_diagnostic() {
+        let input = "[Character(\"Nika\")\n{\n    Age(\"19)\n}]";
+        let diagnostic = parse(input).unwrap_err().diagnostic(input);
+        assert_eq!(diagnostic.message, "unterminated string literal");
+        assert!(diagnostic.annotated.ends_with("  |         ^^^^"));
+    }
+
+    #[test]
+    fn test_parse_document() {
+        let input = r#"
+// Characters and the places they live in.
+[Character("Nika Orchid")
+{
+    Favorite_food("Fish")
+    Likes-Food("Tuna" + "Salmon")
+    Catch phrase("\"Yes, master.\"")
+}]
+
+/* The tower Nika serves in. */
+[Location("Madou Tower")
+{
+    Floors("100")
+}]
+"#;
+        let items = parse_document(input).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].attributes()[0].name(), "Favorite_food");
+        assert_eq!(items[0].attributes()[1].name(), "Likes-Food");
+        assert_eq!(items[0].attributes()[2].name(), "Catch phrase");
+        assert_eq!(
+            items[0].attributes()[2].values(),
+            &vec!["\"Yes, master.\"".to_string()]
+        );
+        assert_eq!(items[1].item_type(), "Location");
+
+        assert!(parse_document("// nothing here").unwrap().is_empty());
+        let error = parse(input).unwrap_err();
+        assert!(matches!(
+            error,
+            WppParseError::UnexpectedToken {
+                expected: "end of input",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_format_round_trip() {
+        let input = r#"[Character("Nika") { Quote("She said \"hi\" \\ waved") }]"#;
+        let item = parse(input).unwrap();
+        let reparsed = parse(&item.prompt()).unwrap();
+        assert_eq!(
+            reparsed.attributes()[0].values(),
+            item.attributes()[0].values()
+        );
+    }
+}
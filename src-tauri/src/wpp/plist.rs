@@ -0,0 +1,149 @@
+use super::{
+    format::TRAITS,
+    item::{Attribute, WppItem},
+    split,
+};
+
+// PList, e.g. `[Nika Orchid: shy, quiet; Nickname: Nika; Age: 19, 19 years old]`. The first
+// segment is the name followed by free traits, every other one a key and its values. PList has
+// no item types, so everything reads back as a `Character`, and values with `,` or `;` in them
+// are quoted.
+
+pub fn parse(input: &str) -> Result<Vec<WppItem>, String> {
+    let mut items = Vec::new();
+    let mut rest = input.trim();
+    while !rest.is_empty() {
+        let Some(body) = rest.strip_prefix('[') else {
+            return Err(format!("expected `[`, found `{}`", preview(rest)));
+        };
+        let Some(end) = split::find(body, ']') else {
+            return Err("unterminated `[`".into());
+        };
+        items.push(parse_item(&body[..end])?);
+        rest = body[end + 1..].trim_start();
+    }
+    Ok(items)
+}
+
+fn parse_item(body: &str) -> Result<WppItem, String> {
+    let mut segments = split::split(body, ';').into_iter();
+    let (name, traits) = split_segment(segments.next().unwrap_or_default());
+    if name.is_empty() {
+        return Err("missing name".into());
+    }
+    let mut item = WppItem::new("Character", name);
+    if !traits.is_empty() {
+        item.add_attribute(attribute(TRAITS, &traits));
+    }
+    for segment in segments {
+        let (key, values) = split_segment(segment);
+        if key.is_empty() {
+            return Err(format!("missing key in `{}`", segment));
+        }
+        item.add_attribute(attribute(key, &values));
+    }
+    Ok(item)
+}
+
+// `Key: a, b` into the key and its values.
+fn split_segment(segment: &str) -> (&str, Vec<&str>) {
+    match split::key_value(segment) {
+        Some((key, values)) => (
+            key,
+            split::split(values, ',')
+                .into_iter()
+                .map(split::unquote)
+                .collect(),
+        ),
+        None => (segment.trim(), vec![]),
+    }
+}
+
+fn attribute(name: &str, values: &[&str]) -> Attribute {
+    let mut attribute = Attribute::new(name);
+    for value in values {
+        attribute.add_value(value);
+    }
+    attribute
+}
+
+fn preview(text: &str) -> String {
+    text.chars().take(16).collect()
+}
+
+pub fn format(items: &[WppItem]) -> String {
+    items.iter().map(format_item).collect::<Vec<_>>().join("\n")
+}
+
+fn format_item(item: &WppItem) -> String {
+    let mut segments = vec![];
+    let traits = item
+        .attributes()
+        .iter()
+        .find(|attribute| attribute.name() == TRAITS);
+    match traits {
+        Some(traits) => segments.push(format!("{}: {}", item.name(), values(traits))),
+        None => segments.push(item.name().to_string()),
+    }
+    for attribute in item.attributes() {
+        if attribute.name() != TRAITS {
+            segments.push(format!("{}: {}", attribute.name(), values(attribute)));
+        }
+    }
+    format!("[{}]", segments.join("; "))
+}
+
+fn values(attribute: &Attribute) -> String {
+    attribute
+        .values()
+        .iter()
+        .map(|value| split::quote(value, &[',', ';', ']']))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let items =
+            parse("[Nika Orchid: shy, quiet; Nickname: Nika; Age: 19, 19 years old]\n[Mira]")
+                .unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].name(), "Nika Orchid");
+        assert_eq!(items[0].attributes()[0].name(), TRAITS);
+        assert_eq!(items[0].attributes()[0].values(), &vec!["shy", "quiet"]);
+        assert_eq!(
+            items[0].attributes()[2].values(),
+            &vec!["19", "19 years old"]
+        );
+        assert!(items[1].attributes().is_empty());
+
+        assert!(parse("Nika: shy").is_err());
+        assert!(parse("[Nika: shy").is_err());
+        assert!(parse("[: shy]").is_err());
+    }
+
+    #[test]
+    fn test_parse_compound_values() {
+        let items =
+            parse(r#"[Nika: shy; Likes: tea, but only green, "salt, pepper"; Wakes: 5:30]"#)
+                .unwrap();
+        let nika = &items[0];
+        assert_eq!(
+            nika.attribute("Likes").unwrap().values(),
+            &vec!["tea, but only green", "salt, pepper"]
+        );
+        assert_eq!(nika.attribute("Wakes").unwrap().values(), &vec!["5:30"]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let input = "[Nika Orchid: shy, quiet; Nickname: Nika; Age: 19, 19 years old]";
+        assert_eq!(format(&parse(input).unwrap()), input);
+        let quoted = r#"[Nika: shy; Likes: "salt, pepper", tea]"#;
+        assert_eq!(format(&parse(quoted).unwrap()), quoted);
+    }
+}
@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 
-use crate::{models::message::Message, prelude::*};
+use crate::{models::message::Message, prelude::*, tools::ToolDescription};
+
+use super::chat::ChatPrompt;
 
 use serde_json::Value;
 
@@ -34,4 +36,27 @@ impl Prompt {
             .insert("messages".to_string(), serde_json::to_value(messages)?);
         Ok(self)
     }
+
+    // Leaves the last message unterminated and skips the next turn's header, so the completion
+    // continues the last message instead of starting a new one.
+    pub fn with_open_last_message(mut self) -> Self {
+        self.vars
+            .insert("open_last_message".to_string(), Value::Bool(true));
+        self
+    }
+
+    pub fn with_tools(mut self, tools: Vec<ToolDescription>) -> Result<Self> {
+        self.vars
+            .insert("tools".to_string(), serde_json::to_value(tools)?);
+        Ok(self)
+    }
+
+    // Uses a roleplay chat's header and examples as the system prompt and its messages as turns.
+    pub fn with_chat(self, chat: &ChatPrompt) -> Result<Self> {
+        let system_prompt = chat
+            .prompt()
+            .map_err(|e| AliceError::Other(e.to_string()))?;
+        self.with_str_var("system_prompt", &system_prompt)
+            .with_messages(chat.messages())
+    }
 }
@@ -0,0 +1,117 @@
+// Splitting the loosely written formats, PList and Boostyle, into their parts. Separators only
+// count at the top level: not inside quotes, where values with separators in them go, and not
+// inside brackets, like `Likes: tea (green, iced)`.
+
+// Words that carry a value on past a comma, e.g. `tea, but only green`.
+static CONTINUATIONS: [&str; 7] = ["but", "and", "or", "though", "although", "yet", "except"];
+
+// Where the first top-level `target` is in `text`.
+pub fn find(text: &str, target: char) -> Option<usize> {
+    scan(text, target).next()
+}
+
+// The trimmed non-empty parts between top-level `separator`s. A part after a comma that starts
+// with a continuation word stays with the one before it.
+pub fn split(text: &str, separator: char) -> Vec<&str> {
+    let mut parts: Vec<(usize, usize)> = Vec::new();
+    let mut start = 0;
+    let ends = scan(text, separator).chain([text.len()]);
+    for end in ends {
+        let continues = separator == ',' && is_continuation(&text[start..end]);
+        match parts.last_mut() {
+            Some(last) if continues => last.1 = end,
+            _ => parts.push((start, end)),
+        }
+        start = end + separator.len_utf8();
+    }
+    parts
+        .into_iter()
+        .map(|(start, end)| text[start..end].trim())
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+// Splits `Key: value` at its first top-level colon. A colon between digits is part of the value,
+// like in `5:30`. The key is left empty when there's nothing before the colon.
+pub fn key_value(term: &str) -> Option<(&str, &str)> {
+    let colon = scan(term, ':').find(|&at| {
+        let before = term[..at].chars().next_back();
+        let after = term[at + 1..].chars().next();
+        !(before.is_some_and(|c| c.is_ascii_digit()) && after.is_some_and(|c| c.is_ascii_digit()))
+    })?;
+    Some((term[..colon].trim(), term[colon + 1..].trim()))
+}
+
+// A value without the quotes it was protected by.
+pub fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    [('"', '"'), ('“', '”')]
+        .iter()
+        .find_map(|(open, close)| value.strip_prefix(*open)?.strip_suffix(*close))
+        .unwrap_or(value)
+}
+
+// A value quoted when it contains any of `separators`, so it reads back whole.
+pub fn quote(value: &str, separators: &[char]) -> String {
+    match value.contains(separators) {
+        true => format!("\"{}\"", value),
+        false => value.to_string(),
+    }
+}
+
+fn is_continuation(part: &str) -> bool {
+    let word = part
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    CONTINUATIONS.contains(&word.as_str())
+}
+
+// The positions of the top-level `target`s.
+fn scan(text: &str, target: char) -> impl Iterator<Item = usize> + '_ {
+    let mut depth = 0usize;
+    let mut quote: Option<char> = None;
+    text.char_indices().filter_map(move |(at, c)| {
+        match (quote, c) {
+            (Some('"'), '"') | (Some('“'), '”') => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '“') => quote = Some(c),
+            (None, c) if c == target && depth == 0 => return Some(at),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        assert_eq!(
+            split("tea, but only green, cake , ", ','),
+            vec!["tea, but only green", "cake"]
+        );
+        assert_eq!(
+            split(r#""salt, pepper", tea (green, iced)"#, ','),
+            vec![r#""salt, pepper""#, "tea (green, iced)"]
+        );
+        assert_eq!(split("Shy + \"A + B\"", '+'), vec!["Shy", "\"A + B\""]);
+        assert_eq!(find("Likes: tea (green) ) rest", ')'), Some(19));
+    }
+
+    #[test]
+    fn test_key_value() {
+        assert_eq!(key_value("Wakes: 5:30"), Some(("Wakes", "5:30")));
+        assert_eq!(key_value("Wakes at 5:30"), None);
+        assert_eq!(key_value("\"Note: quoted\""), None);
+        assert_eq!(key_value(": nothing"), Some(("", "nothing")));
+        assert_eq!(unquote(" \"tea, green\" "), "tea, green");
+        assert_eq!(quote("tea, green", &[',']), "\"tea, green\"");
+        assert_eq!(quote("tea", &[',']), "tea");
+    }
+}
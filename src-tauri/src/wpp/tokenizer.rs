@@ -1,5 +1,21 @@
-#[derive(Debug, Clone)]
-pub enum Token {
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::error::WppParseError;
+
+// Where a token sits in the source: byte offsets for slicing, 1-based line and column (counted in
+// characters) for people.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
     LeftBracket,
     RightBracket,
     LeftParen,
@@ -9,46 +25,343 @@ pub enum Token {
     Plus,
     Identifier(String),
     StringLiteral(String),
+    // Always the last token, so running out of input is reported like any unexpected token.
+    End,
 }
 
-pub fn tokenize(input: &str) -> Vec<Token> {
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::LeftBracket => write!(f, "`[`"),
+            TokenKind::RightBracket => write!(f, "`]`"),
+            TokenKind::LeftParen => write!(f, "`(`"),
+            TokenKind::RightParen => write!(f, "`)`"),
+            TokenKind::LeftBrace => write!(f, "`{{`"),
+            TokenKind::RightBrace => write!(f, "`}}`"),
+            TokenKind::Plus => write!(f, "`+`"),
+            TokenKind::Identifier(identifier) => write!(f, "`{}`", identifier),
+            TokenKind::StringLiteral(literal) => write!(f, "\"{}\"", literal),
+            TokenKind::End => write!(f, "end of input"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+// Tracks the position of the next character while walking the input.
+struct Cursor<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    len: usize,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            chars: input.char_indices().peekable(),
+            len: input.len(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn offset(&mut self) -> usize {
+        self.chars.peek().map_or(self.len, |(offset, _)| *offset)
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|(_, c)| *c)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let (_, c) = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    // A span from `start` (with its line and column) to the current position.
+    fn span(&mut self, start: usize, line: usize, column: usize) -> Span {
+        Span {
+            start,
+            end: self.offset(),
+            line,
+            column,
+        }
+    }
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>, WppParseError> {
     let mut tokens = Vec::new();
-    let mut chars = input.trim().chars().peekable();
+    let mut cursor = Cursor::new(input);
 
-    while let Some(c) = chars.next() {
+    loop {
+        let (start, line, column) = (cursor.offset(), cursor.line, cursor.column);
+        let Some(c) = cursor.next() else {
+            tokens.push(Token {
+                kind: TokenKind::End,
+                span: cursor.span(start, line, column),
+            });
+            break;
+        };
         if c.is_whitespace() {
             continue;
         }
-        match c {
-            '[' => tokens.push(Token::LeftBracket),
-            ']' => tokens.push(Token::RightBracket),
-            '(' => tokens.push(Token::LeftParen),
-            ')' => tokens.push(Token::RightParen),
-            '{' => tokens.push(Token::LeftBrace),
-            '}' => tokens.push(Token::RightBrace),
-            '+' => tokens.push(Token::Plus),
+        let kind = match c {
+            '[' => TokenKind::LeftBracket,
+            ']' => TokenKind::RightBracket,
+            '(' => TokenKind::LeftParen,
+            ')' => TokenKind::RightParen,
+            '{' => TokenKind::LeftBrace,
+            '}' => TokenKind::RightBrace,
+            '+' => TokenKind::Plus,
+            '/' if cursor.peek() == Some('/') => {
+                while cursor.peek().is_some_and(|c| c != '\n') {
+                    cursor.next();
+                }
+                continue;
+            }
+            '/' if cursor.peek() == Some('*') => {
+                cursor.next();
+                loop {
+                    match cursor.next() {
+                        Some('*') if cursor.peek() == Some('/') => {
+                            cursor.next();
+                            break;
+                        }
+                        Some(_) => {}
+                        None => {
+                            return Err(WppParseError::UnterminatedComment {
+                                span: cursor.span(start, line, column),
+                            })
+                        }
+                    }
+                }
+                continue;
+            }
             '"' => {
                 let mut literal = String::new();
-                for c in chars.by_ref() {
-                    if c == '"' {
-                        break;
+                loop {
+                    match cursor.next() {
+                        Some('"') => break,
+                        Some('\\') => match cursor.next() {
+                            Some('n') => literal.push('\n'),
+                            Some('t') => literal.push('\t'),
+                            Some(c @ ('"' | '\\')) => literal.push(c),
+                            // Unknown escapes are kept as written.
+                            Some(c) => {
+                                literal.push('\\');
+                                literal.push(c);
+                            }
+                            None => {
+                                return Err(WppParseError::UnterminatedString {
+                                    span: cursor.span(start, line, column),
+                                })
+                            }
+                        },
+                        Some(c) => literal.push(c),
+                        None => {
+                            return Err(WppParseError::UnterminatedString {
+                                span: cursor.span(start, line, column),
+                            })
+                        }
                     }
-                    literal.push(c);
                 }
-                tokens.push(Token::StringLiteral(literal));
+                TokenKind::StringLiteral(literal)
             }
-            _ => {
-                let mut identifier = String::new();
-                identifier.push(c);
-                while let Some(&c) = chars.peek() {
-                    if !c.is_alphanumeric() {
+            c if is_identifier_start(c) => {
+                // Keys may contain spaces, e.g. `Favorite food("Cake")`, so the span ends after
+                // the last character that isn't one.
+                let mut identifier = String::from(c);
+                let mut end = cursor.offset();
+                while let Some(c) = cursor.peek() {
+                    if !is_identifier_start(c) && c != '-' && c != ' ' && c != '\t' {
                         break;
                     }
-                    identifier.push(chars.next().unwrap());
+                    identifier.push(c);
+                    cursor.next();
+                    if !c.is_whitespace() {
+                        end = cursor.offset();
+                    }
                 }
-                tokens.push(Token::Identifier(identifier));
+                tokens.push(Token {
+                    kind: TokenKind::Identifier(identifier.trim_end().to_string()),
+                    span: Span {
+                        start,
+                        end,
+                        line,
+                        column,
+                    },
+                });
+                continue;
+            }
+            c => {
+                return Err(WppParseError::UnexpectedCharacter {
+                    character: c,
+                    span: cursor.span(start, line, column),
+                })
+            }
+        };
+        tokens.push(Token {
+            kind,
+            span: cursor.span(start, line, column),
+        });
+    }
+    Ok(tokens)
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// Whether `name` reads back as a single key, so it can be written without quotes. Surrounding
+// whitespace wouldn't survive the round trip either.
+pub fn is_identifier(name: &str) -> bool {
+    match tokenize(name).as_deref() {
+        Ok([token, _]) => token.kind == TokenKind::Identifier(name.to_string()),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spans() {
+        let tokens = tokenize("[Character(\"Nïka\")\n{\n  Age(\"19\")").unwrap();
+        let spans = tokens
+            .iter()
+            .map(|token| (token.span.line, token.span.column))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            vec![
+                (1, 1),
+                (1, 2),
+                (1, 11),
+                (1, 12),
+                (1, 18),
+                (2, 1),
+                (3, 3),
+                (3, 6),
+                (3, 7),
+                (3, 11),
+                (3, 12)
+            ]
+        );
+        assert_eq!(tokens[3].span.start, 11);
+        assert_eq!(tokens[3].span.end, 18);
+        assert_eq!(tokens.last().unwrap().kind, TokenKind::End);
+    }
+
+    #[test]
+    fn test_unterminated_string() {
+        let error = tokenize("[Character(\"Nika)]").unwrap_err();
+        assert_eq!(
+            error,
+            WppParseError::UnterminatedString {
+                span: Span {
+                    start: 11,
+                    end: 18,
+                    line: 1,
+                    column: 12,
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_stray_character() {
+        let error = tokenize("[Character(\"Nika\")\n{\n  Age: (\"19\")").unwrap_err();
+        assert!(matches!(
+            error,
+            WppParseError::UnexpectedCharacter {
+                character: ':',
+                span: Span {
+                    line: 3,
+                    column: 6,
+                    ..
+                },
             }
+        ));
+    }
+
+    #[test]
+    fn test_escapes() {
+        let tokens = tokenize(r#""She said \"hi\"" "C:\\" "a\nb" "\d""#).unwrap();
+        let literals = tokens
+            .into_iter()
+            .filter_map(|token| match token.kind {
+                TokenKind::StringLiteral(literal) => Some(literal),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(literals, vec!["She said \"hi\"", "C:\\", "a\nb", "\\d"]);
+    }
+
+    #[test]
+    fn test_identifiers() {
+        let tokens = tokenize("Favorite_food Likes-Food\nFavorite food  (").unwrap();
+        let kinds = tokens.iter().map(|token| &token.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                &TokenKind::Identifier("Favorite_food Likes-Food".into()),
+                &TokenKind::Identifier("Favorite food".into()),
+                &TokenKind::LeftParen,
+                &TokenKind::End,
+            ]
+        );
+        assert_eq!(
+            tokens[1].span.end,
+            tokens[1].span.start + "Favorite food".len()
+        );
+
+        assert!(is_identifier("Favorite food"));
+        assert!(is_identifier("Likes-Food"));
+        for name in [
+            "",
+            " Age",
+            "Age(19)",
+            "Time: 5",
+            "Say \"hi\"",
+            "A + B",
+            "A // B",
+        ] {
+            assert!(!is_identifier(name), "{}", name);
         }
     }
-    tokens
+
+    #[test]
+    fn test_comments() {
+        let tokens = tokenize("// header\n[ /* inline\n comment */ ] // trailing").unwrap();
+        let kinds = tokens.iter().map(|token| &token.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                &TokenKind::LeftBracket,
+                &TokenKind::RightBracket,
+                &TokenKind::End
+            ]
+        );
+        assert_eq!(tokens[1].span.line, 3);
+        assert!(matches!(
+            tokenize("[ /* never closed"),
+            Err(WppParseError::UnterminatedComment { .. })
+        ));
+        assert!(matches!(
+            tokenize("[ / ]"),
+            Err(WppParseError::UnexpectedCharacter { character: '/', .. })
+        ));
+    }
 }